@@ -8,20 +8,22 @@ use aruna_rust_api::api::{
         internal_authorize_service_server::InternalAuthorizeServiceServer,
         internal_event_emitter_service_server::InternalEventEmitterService,
         internal_event_service_client::InternalEventServiceClient,
-        internal_event_service_server::InternalEventServiceServer, EmitEventRequest, Relation,
+        internal_event_service_server::InternalEventServiceServer, EmitEventRequest,
+        ObjectGroupRelation, Relation,
     },
     notification::services::v1::{
         read_stream_group_messages_request::StreamAction,
         update_notification_service_client::UpdateNotificationServiceClient,
         update_notification_service_server::UpdateNotificationServiceServer,
-        CreateEventStreamingGroupRequest, EventType, NotficationStreamAck, NotificationStreamInit,
-        ReadStreamGroupMessagesRequest,
+        CreateEventStreamingGroupRequest, DeleteEventStreamingGroupRequest, EventType,
+        NotficationStreamAck, NotificationStreamInit, NotificationStreamResponse,
+        ReadStreamGroupMessagesRequest, ReadStreamGroupMessagesResponse,
     },
     storage::{
         models::v1::{Project, ResourceType},
         services::v1::{
             resource_info_service_client::ResourceInfoServiceClient,
-            resource_info_service_server::ResourceInfoServiceServer,
+            resource_info_service_server::ResourceInfoServiceServer, Hierarchy,
         },
     },
 };
@@ -32,154 +34,2125 @@ use std::io::Write;
 use tokio_stream::StreamExt;
 
 use core::time;
-use crossbeam_utils::sync::WaitGroup;
 use std::{
     collections::HashMap,
     net::SocketAddr,
-    sync::{Mutex, Once, RwLock},
+    sync::{Arc, Mutex, Once},
     thread,
 };
 use tokio::net::TcpListener;
 use tokio_stream::wrappers::TcpListenerStream;
-use tonic::{metadata::MetadataMap, transport::Server, Request};
+use tonic::{
+    metadata::MetadataMap,
+    transport::{Channel, Server},
+    Request,
+};
+
+use crate::{
+    server::{
+        http_gateway::HttpGateway,
+        internal_event_server::InternalServer,
+        public_event_server::{PublicServer, MAX_UNACKED_CHUNKS},
+        server::{
+            ACK_MODE_METADATA_NAME, DUPLICATE_EVENT_METADATA_NAME, EVENT_TYPES_METADATA_NAME,
+            FLOW_CONTROL_METADATA_NAME, HEARTBEAT_INTERVAL_METADATA_NAME,
+            IDEMPOTENCY_KEY_METADATA_NAME, INACTIVITY_TTL_METADATA_NAME, INTERNAL_AUTHZ_TOKEN,
+            MAX_BATCH_SIZE_METADATA_NAME, MAX_DELIVER_METADATA_NAME,
+            MAX_IN_FLIGHT_CHUNKS_METADATA_NAME, MAX_WAIT_METADATA_NAME, NACK_CHUNK_PREFIX,
+            STREAM_START_METADATA_NAME, TERMINATE_CHUNK_PREFIX, TOKEN_METADATA_NAME,
+            UNKNOWN_CHUNK_PREFIX, WEBHOOK_SECRET_METADATA_NAME, WEBHOOK_URL_METADATA_NAME,
+        },
+        webhook::{
+            sign_payload, WebhookOptions, WEBHOOK_SIGNATURE_HEADER, WEBHOOK_TIMESTAMP_HEADER,
+        },
+    },
+    storage_test_server::storage_endpoint_mock::{
+        AuthzEndpointMock, ResourceInfoMock, StorageEndpointMock,
+    },
+    stream_handler::{
+        handler::{EventHandler, FetchOptions, StoredWebhook, StreamStartPosition},
+        memory::MemoryEventHandler,
+        natsio::{NatsIOEventHandler, NatsIOStreamConfig},
+    },
+};
+
+static INIT: Once = Once::new();
+
+fn initialize_logging() {
+    INIT.call_once(|| {
+        env_logger::Builder::new()
+            .format(|buf, record| {
+                writeln!(
+                    buf,
+                    "{}:{} {} [{}] - {}",
+                    record.file().unwrap_or("unknown"),
+                    record.line().unwrap_or(0),
+                    chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"),
+                    record.level(),
+                    record.args()
+                )
+            })
+            .filter_level(log::LevelFilter::Error)
+            .init();
+    });
+}
+
+// Starts the mocked storage endpoints in the background and returns their port
+// Each tokio test has its own runtime, so the endpoints have to be started per test
+async fn start_mock_server() -> u16 {
+    let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+    let listener = TcpListener::bind(addr).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    tokio::spawn(async move {
+        let stream_group_store = Mutex::new(HashMap::new());
+        let authz_service = AuthzEndpointMock {};
+        let event_service = StorageEndpointMock {
+            stream_groups: stream_group_store,
+        };
+        let resource_service = ResourceInfoMock {};
+
+        Server::builder()
+            .add_service(InternalAuthorizeServiceServer::new(authz_service))
+            .add_service(InternalEventServiceServer::new(event_service))
+            .add_service(ResourceInfoServiceServer::new(resource_service))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    port
+}
+
+// Starts the public notification server with the given event handler against the mocked storage endpoints
+// Returns the internal server to emit events and a client for the public server
+async fn start_event_servers<T: EventHandler + Clone + Send + Sync + 'static>(
+    event_handler: T,
+) -> (InternalServer, UpdateNotificationServiceClient<Channel>) {
+    let (internal_events_handler, _, public_event_client) = start_servers(event_handler).await;
+    (internal_events_handler, public_event_client)
+}
+
+// Like start_event_servers, additionally returns the public server itself for the http gateway
+async fn start_servers<T: EventHandler + Clone + Send + Sync + 'static>(
+    event_handler: T,
+) -> (
+    InternalServer,
+    Arc<PublicServer>,
+    UpdateNotificationServiceClient<Channel>,
+) {
+    initialize_logging();
+    let server_addr_port = start_mock_server().await;
+
+    let internal_events_handler = InternalServer {
+        event_handler: Box::new(event_handler.clone()),
+        internal_token: "test".to_string(),
+    };
+
+    let authz_client =
+        InternalAuthorizeServiceClient::connect(format!("http://127.0.0.1:{}", server_addr_port))
+            .await
+            .unwrap();
+
+    let resource_client =
+        ResourceInfoServiceClient::connect(format!("http://127.0.0.1:{}", server_addr_port))
+            .await
+            .unwrap();
+
+    let internal_event_client =
+        InternalEventServiceClient::connect(format!("http://127.0.0.1:{}", server_addr_port))
+            .await
+            .unwrap();
+
+    let public_events_handler = Arc::new(PublicServer {
+        event_handler: Arc::new(event_handler),
+        internal_authz_client: authz_client,
+        resource_client,
+        internal_events_client: internal_event_client,
+        active_streams: Default::default(),
+        webhook_options: WebhookOptions {
+            max_attempts: 3,
+            initial_backoff: time::Duration::from_millis(50),
+            max_backoff: time::Duration::from_millis(200),
+            request_timeout: time::Duration::from_secs(2),
+            max_failed_batches: 2,
+            allow_insecure: true,
+        },
+        webhooks: Default::default(),
+        internal_token: "test".to_string(),
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let notification_server_port = listener.local_addr().unwrap().port();
+
+    let public_server = public_events_handler.clone();
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(UpdateNotificationServiceServer::from_arc(public_server))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+
+    let public_event_client = UpdateNotificationServiceClient::connect(format!(
+        "http://127.0.0.1:{}",
+        notification_server_port
+    ))
+    .await
+    .unwrap();
+
+    (
+        internal_events_handler,
+        public_events_handler,
+        public_event_client,
+    )
+}
+
+// Serves the http gateway in front of the given public server and returns its port
+async fn start_http_gateway(
+    public_event_client: UpdateNotificationServiceClient<Channel>,
+    public_server: Arc<PublicServer>,
+) -> u16 {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(
+                HttpGateway::new(public_event_client, public_server)
+                    .router()
+                    .into_make_service(),
+            )
+            .await
+            .unwrap();
+    });
+
+    port
+}
+
+// Local stand-in for a webhook endpoint, answers with the given status codes and with 200 once they are used up
+// Returns its port and the received requests
+async fn start_webhook_stand_in(
+    responses: Vec<u16>,
+) -> (u16, Receiver<(axum::http::HeaderMap, axum::body::Bytes)>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let (request_sender, request_recv) = async_channel::unbounded();
+    let responses = std::sync::Arc::new(Mutex::new(std::collections::VecDeque::from(responses)));
+    let router = axum::Router::new().route(
+        "/hook",
+        axum::routing::post(
+            move |headers: axum::http::HeaderMap, body: axum::body::Bytes| {
+                let request_sender = request_sender.clone();
+                let responses = responses.clone();
+                async move {
+                    request_sender.send((headers, body)).await.unwrap();
+                    let status = responses.lock().unwrap().pop_front().unwrap_or(200);
+                    axum::http::StatusCode::from_u16(status).unwrap()
+                }
+            },
+        ),
+    );
+
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(router.into_make_service())
+            .await
+            .unwrap();
+    });
+
+    (port, request_recv)
+}
+
+// The nats tests share the event stream of one server and run one after another
+static NATS_TEST_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+// Connects to the nats server given in NATS_TEST_SERVER, e.g. localhost:4222
+// Returns None if it is not set, the calling test is skipped then
+async fn nats_event_handler(test_name: &str) -> Option<NatsIOEventHandler> {
+    let nats_server = match std::env::var("NATS_TEST_SERVER") {
+        Ok(value) => value,
+        Err(_) => {
+            eprintln!("NATS_TEST_SERVER is not set, skipping {}", test_name);
+            return None;
+        }
+    };
+    let nats_client = async_nats::connect(nats_server).await.unwrap();
+    let event_handler = NatsIOEventHandler::new(nats_client, NatsIOStreamConfig::default())
+        .await
+        .unwrap();
+
+    return Some(event_handler);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn full_test_nats() {
+    let _lock = NATS_TEST_LOCK.lock().await;
+    if let Some(event_handler) = nats_event_handler("full_test_nats").await {
+        full_test(event_handler).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn full_test_memory() {
+    full_test(MemoryEventHandler::new()).await;
+}
+
+async fn full_test<T: EventHandler + Clone + Send + Sync + 'static>(event_handler: T) {
+    let (internal_events_handler, public_event_client) = start_event_servers(event_handler).await;
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::All,
+        Relation {
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    let notifications = read_stream_group(&public_event_client, stream_group_id, 1).await;
+
+    if notifications.is_empty() {
+        panic!("no messages found")
+    }
+
+    // Sequence and timestamp are taken from the message info of the event system
+    assert!(notifications[0].sequence > 0);
+    assert!(notifications[0].timestamp.is_some());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn object_stream_groups_nats() {
+    let _lock = NATS_TEST_LOCK.lock().await;
+    if let Some(event_handler) = nats_event_handler("object_stream_groups_nats").await {
+        object_stream_groups(event_handler).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn object_stream_groups_memory() {
+    object_stream_groups(MemoryEventHandler::new()).await;
+}
+
+// The stream groups only read new events, events of earlier runs against the same nats server are not delivered
+async fn object_stream_groups<T: EventHandler + Clone + Send + Sync + 'static>(event_handler: T) {
+    let (internal_events_handler, public_event_client) = start_event_servers(event_handler).await;
+
+    let object_stream_group_id = create_new_events_stream_group(
+        &public_event_client,
+        ResourceType::Object,
+        "object_id",
+        false,
+    )
+    .await;
+    let object_group_stream_group_id = create_new_events_stream_group(
+        &public_event_client,
+        ResourceType::ObjectGroup,
+        "object_group_id",
+        true,
+    )
+    .await;
+
+    let relation = Relation {
+        project: "project_id".to_string(),
+        collection: "collection_id".to_string(),
+        shared_object: "shared_object_id".to_string(),
+        object_groups: vec![ObjectGroupRelation {
+            shared_object_group_id: "shared_object_group_id".to_string(),
+            ..Default::default()
+        }],
+    };
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Object,
+        "object_id",
+        EventType::Updated,
+        relation.clone(),
+    )
+    .await;
+    emit_event(
+        &internal_events_handler,
+        ResourceType::ObjectGroup,
+        "object_group_id",
+        EventType::Updated,
+        relation.clone(),
+    )
+    .await;
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Object,
+        "other_object_id",
+        EventType::Updated,
+        Relation {
+            shared_object: "other_shared_object_id".to_string(),
+            ..relation
+        },
+    )
+    .await;
+
+    let object_ids =
+        resource_ids(read_stream_group(&public_event_client, object_stream_group_id, 1).await);
+    let object_group_ids = resource_ids(
+        read_stream_group(&public_event_client, object_group_stream_group_id, 1).await,
+    );
+
+    assert_eq!(object_ids, vec!["object_id"]);
+    assert_eq!(object_group_ids, vec!["object_group_id"]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn all_resources_stream_group_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    for project_id in ["project_id", "other_project_id", "unreadable_project_id"] {
+        emit_event(
+            &internal_events_handler,
+            ResourceType::Project,
+            project_id,
+            EventType::Updated,
+            Relation::default(),
+        )
+        .await;
+    }
+
+    let stream_group_id =
+        create_stream_group(&public_event_client, ResourceType::All, "", false).await;
+
+    let mut project_ids =
+        resource_ids(read_stream_group(&public_event_client, stream_group_id, 2).await);
+    project_ids.sort();
+
+    assert_eq!(project_ids, vec!["other_project_id", "project_id"]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn all_resources_single_collection_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let stream_group_id =
+        create_stream_group(&public_event_client, ResourceType::All, "", false).await;
+
+    // Neither the project nor a sibling of the readable collection are delivered
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "shared_project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+    for collection_id in ["sibling_collection_id", "readable_collection_id"] {
+        emit_event(
+            &internal_events_handler,
+            ResourceType::Collection,
+            collection_id,
+            EventType::Updated,
+            Relation {
+                project: "shared_project_id".to_string(),
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    let collection_ids =
+        resource_ids(read_stream_group(&public_event_client, stream_group_id, 1).await);
+    assert_eq!(collection_ids, vec!["readable_collection_id"]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn unspecified_resource_type_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let mut emit_request = Request::new(EmitEventRequest {
+        event_resource: ResourceType::Unspecified as i32,
+        resource_id: "project_id".to_string(),
+        event_type: EventType::Updated as i32,
+        relations: vec![Relation::default()],
+    });
+    emit_request
+        .metadata_mut()
+        .insert(INTERNAL_AUTHZ_TOKEN, "test".parse().unwrap());
+
+    let emit_err = internal_events_handler
+        .emit_event(emit_request)
+        .await
+        .unwrap_err();
+    assert_eq!(emit_err.code(), tonic::Code::InvalidArgument);
+
+    let mut create_request = Request::new(CreateEventStreamingGroupRequest {
+        resource: ResourceType::Unspecified as i32,
+        resource_id: "project_id".to_string(),
+        ..Default::default()
+    });
+    create_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+
+    let create_err = public_event_client
+        .clone()
+        .create_event_streaming_group(create_request)
+        .await
+        .unwrap_err();
+    assert_eq!(create_err.code(), tonic::Code::InvalidArgument);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn idempotent_emit_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        false,
+    )
+    .await;
+
+    // Identical requests without a key are deduplicated by their derived key,
+    // requests with the same key are deduplicated and requests with different keys are registered as separate events
+    let mut duplicates = Vec::new();
+    for key in [None, None, Some("key"), Some("key"), Some("other_key")] {
+        let mut emit_request = Request::new(EmitEventRequest {
+            event_resource: ResourceType::Project as i32,
+            resource_id: "project_id".to_string(),
+            event_type: EventType::Updated as i32,
+            relations: vec![Relation::default()],
+        });
+        emit_request
+            .metadata_mut()
+            .insert(INTERNAL_AUTHZ_TOKEN, "test".parse().unwrap());
+        if let Some(key) = key {
+            emit_request
+                .metadata_mut()
+                .insert(IDEMPOTENCY_KEY_METADATA_NAME, key.parse().unwrap());
+        }
+
+        let response = internal_events_handler
+            .emit_event(emit_request)
+            .await
+            .unwrap();
+        duplicates.push(
+            response
+                .metadata()
+                .get(DUPLICATE_EVENT_METADATA_NAME)
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string(),
+        );
+    }
+
+    assert_eq!(duplicates, vec!["false", "true", "false", "true", "false"]);
+    assert_eq!(
+        read_stream_group(&public_event_client, stream_group_id, 3)
+            .await
+            .len(),
+        3
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn malformed_resource_ids_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    for resource_id in ["project.id", "project_*", ">", "project id"] {
+        let mut emit_request = Request::new(EmitEventRequest {
+            event_resource: ResourceType::Project as i32,
+            resource_id: resource_id.to_string(),
+            event_type: EventType::Updated as i32,
+            relations: vec![Relation::default()],
+        });
+        emit_request
+            .metadata_mut()
+            .insert(INTERNAL_AUTHZ_TOKEN, "test".parse().unwrap());
+
+        let emit_err = internal_events_handler
+            .emit_event(emit_request)
+            .await
+            .unwrap_err();
+        assert_eq!(emit_err.code(), tonic::Code::InvalidArgument);
+
+        let mut create_request = Request::new(CreateEventStreamingGroupRequest {
+            resource: ResourceType::Project as i32,
+            resource_id: resource_id.to_string(),
+            ..Default::default()
+        });
+        create_request
+            .metadata_mut()
+            .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+
+        let create_err = public_event_client
+            .clone()
+            .create_event_streaming_group(create_request)
+            .await
+            .unwrap_err();
+        assert_eq!(create_err.code(), tonic::Code::InvalidArgument);
+    }
+
+    // A malformed id in any relation rejects the whole event
+    let mut emit_request = Request::new(EmitEventRequest {
+        event_resource: ResourceType::Collection as i32,
+        resource_id: "collection_id".to_string(),
+        event_type: EventType::Created as i32,
+        relations: vec![
+            Relation {
+                project: "project_id".to_string(),
+                ..Default::default()
+            },
+            Relation {
+                project: "project.*".to_string(),
+                ..Default::default()
+            },
+        ],
+    });
+    emit_request
+        .metadata_mut()
+        .insert(INTERNAL_AUTHZ_TOKEN, "test".parse().unwrap());
+
+    let emit_err = internal_events_handler
+        .emit_event(emit_request)
+        .await
+        .unwrap_err();
+    assert_eq!(emit_err.code(), tonic::Code::InvalidArgument);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn delete_stream_group_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    // Keeps the input stream open until the stream is terminated by the server
+    let init_stream_group_id = stream_group_id.clone();
+    let input = async_stream::stream! {
+        yield ReadStreamGroupMessagesRequest {
+            close: false,
+            stream_action: Some(StreamAction::Init(NotificationStreamInit {
+                stream_group_id: init_stream_group_id,
+            })),
+        };
+        futures::future::pending::<()>().await;
+    };
+    let mut stream_request = Request::new(input);
+    stream_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+    stream_request
+        .metadata_mut()
+        .append(HEARTBEAT_INTERVAL_METADATA_NAME, "100".parse().unwrap());
+    let mut output_stream = public_event_client
+        .clone()
+        .read_stream_group_messages(stream_request)
+        .await
+        .unwrap()
+        .into_inner();
+    // Waits for the first heartbeat to make sure the stream is active
+    output_stream.next().await.unwrap().unwrap();
+
+    let delete_stream_group = |stream_group_id: String| {
+        let mut delete_request = Request::new(DeleteEventStreamingGroupRequest { stream_group_id });
+        delete_request
+            .metadata_mut()
+            .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+        let mut client = public_event_client.clone();
+        async move { client.delete_event_streaming_group(delete_request).await }
+    };
+
+    delete_stream_group(stream_group_id.clone()).await.unwrap();
+
+    // The active stream ends with an error after the deletion
+    let terminated = async {
+        while let Some(recv) = output_stream.next().await {
+            if let Err(err) = recv {
+                return err;
+            }
+        }
+        panic!("stream ended without an error");
+    };
+    let err = tokio::time::timeout(time::Duration::from_secs(10), terminated)
+        .await
+        .unwrap();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+
+    let err = delete_stream_group(stream_group_id.clone())
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::NotFound);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn nack_and_terminate_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+
+    let (ack_sender, mut output_stream) =
+        open_read_stream(&public_event_client, stream_group_id, &[]).await;
+
+    // A nack without delay redelivers the chunk immediately
+    let (chunk_id, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+    ack_sender
+        .send(vec![format!("{}{}", NACK_CHUNK_PREFIX, chunk_id)])
+        .await
+        .unwrap();
+
+    // A nack with delay holds the chunk back until the delay expired
+    let (chunk_id, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+    let nacked_at = std::time::Instant::now();
+    ack_sender
+        .send(vec![format!("{}300:{}", NACK_CHUNK_PREFIX, chunk_id)])
+        .await
+        .unwrap();
+
+    // A terminated chunk is never redelivered
+    let (chunk_id, notifications) = next_notifications(&mut output_stream).await;
+    assert!(nacked_at.elapsed() >= time::Duration::from_millis(300));
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+    ack_sender
+        .send(vec![format!("{}{}", TERMINATE_CHUNK_PREFIX, chunk_id)])
+        .await
+        .unwrap();
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Collection,
+        "collection_id",
+        EventType::Created,
+        Relation {
+            project: "project_id".to_string(),
+            ..Default::default()
+        },
+    )
+    .await;
+    let (chunk_id, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(resource_ids(notifications), vec!["collection_id"]);
+    ack_sender.send(vec![chunk_id]).await.unwrap();
+
+    // Malformed nack delays are rejected
+    ack_sender
+        .send(vec![format!("{}soon:{}", NACK_CHUNK_PREFIX, "chunk_id")])
+        .await
+        .unwrap();
+    let rejected = async {
+        while let Some(recv) = output_stream.next().await {
+            if let Err(err) = recv {
+                return err;
+            }
+        }
+        panic!("stream ended without an error");
+    };
+    let err = tokio::time::timeout(time::Duration::from_secs(10), rejected)
+        .await
+        .unwrap();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn sequence_and_timestamp_nats() {
+    let _lock = NATS_TEST_LOCK.lock().await;
+    if let Some(event_handler) = nats_event_handler("sequence_and_timestamp_nats").await {
+        sequence_and_timestamp(event_handler).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn sequence_and_timestamp_memory() {
+    // The memory backend starts its sequences at one
+    assert_eq!(
+        sequence_and_timestamp(MemoryEventHandler::new()).await,
+        vec![1, 2]
+    );
+}
+
+// Returns the sequences of the two read notifications
+async fn sequence_and_timestamp<T: EventHandler + Clone + Send + Sync + 'static>(
+    event_handler: T,
+) -> Vec<u64> {
+    let (internal_events_handler, public_event_client) = start_event_servers(event_handler).await;
+
+    let stream_group_id = create_new_events_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    let started_at = chrono::Utc::now().timestamp();
+    for collection_id in ["collection_id", "other_collection_id"] {
+        emit_event(
+            &internal_events_handler,
+            ResourceType::Collection,
+            collection_id,
+            EventType::Created,
+            Relation {
+                project: "project_id".to_string(),
+                ..Default::default()
+            },
+        )
+        .await;
+    }
+
+    let notifications = read_stream_group(&public_event_client, stream_group_id, 2).await;
+    assert_eq!(
+        resource_ids(notifications.clone()),
+        vec!["collection_id", "other_collection_id"]
+    );
+
+    let sequences: Vec<u64> = notifications.iter().map(|x| x.sequence).collect();
+    assert!(sequences[0] > 0);
+    assert!(sequences[0] < sequences[1]);
+
+    let timestamps: Vec<prost_types::Timestamp> = notifications
+        .into_iter()
+        .map(|x| x.timestamp.unwrap())
+        .collect();
+    assert!(timestamps[0].seconds >= started_at);
+    assert!(
+        (timestamps[0].seconds, timestamps[0].nanos)
+            <= (timestamps[1].seconds, timestamps[1].nanos)
+    );
+
+    sequences
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn stream_new_events_only_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Created,
+        Relation::default(),
+    )
+    .await;
+
+    let mut create_request = Request::new(CreateEventStreamingGroupRequest {
+        resource: ResourceType::Project as i32,
+        resource_id: "project_id".to_string(),
+        include_subresource: true,
+        ..Default::default()
+    });
+    create_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+    create_request
+        .metadata_mut()
+        .append(STREAM_START_METADATA_NAME, "new".parse().unwrap());
+    let stream_group_id = public_event_client
+        .clone()
+        .create_event_streaming_group(create_request)
+        .await
+        .unwrap()
+        .into_inner()
+        .stream_group_id;
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+
+    let notifications = read_stream_group(&public_event_client, stream_group_id, 1).await;
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].sequence, 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn event_type_filter_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let mut create_request = Request::new(CreateEventStreamingGroupRequest {
+        resource: ResourceType::Project as i32,
+        resource_id: "project_id".to_string(),
+        include_subresource: true,
+        ..Default::default()
+    });
+    create_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+    create_request
+        .metadata_mut()
+        .append(EVENT_TYPES_METADATA_NAME, "deleted".parse().unwrap());
+    let stream_group_id = public_event_client
+        .clone()
+        .create_event_streaming_group(create_request)
+        .await
+        .unwrap()
+        .into_inner()
+        .stream_group_id;
+
+    for event_type in [EventType::Updated, EventType::Deleted] {
+        emit_event(
+            &internal_events_handler,
+            ResourceType::Project,
+            "project_id",
+            event_type,
+            Relation::default(),
+        )
+        .await;
+    }
+
+    let notifications = read_stream_group(&public_event_client, stream_group_id, 1).await;
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(
+        notifications[0].message.as_ref().unwrap().updated_type(),
+        EventType::Deleted
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn unacked_chunks_limit_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    for index in 0..MAX_UNACKED_CHUNKS + 1 {
+        let mut emit_request = Request::new(EmitEventRequest {
+            event_resource: ResourceType::Project as i32,
+            resource_id: "project_id".to_string(),
+            event_type: EventType::Updated as i32,
+            relations: vec![Relation::default()],
+        });
+        emit_request
+            .metadata_mut()
+            .insert(INTERNAL_AUTHZ_TOKEN, "test".parse().unwrap());
+        emit_request.metadata_mut().insert(
+            IDEMPOTENCY_KEY_METADATA_NAME,
+            index.to_string().parse().unwrap(),
+        );
+        internal_events_handler
+            .emit_event(emit_request)
+            .await
+            .unwrap();
+    }
+
+    let (ack_sender, mut output_stream) = open_read_stream(
+        &public_event_client,
+        stream_group_id,
+        &[(MAX_BATCH_SIZE_METADATA_NAME, "1")],
+    )
+    .await;
+
+    let mut chunk_ids = Vec::new();
+    for _ in 0..MAX_UNACKED_CHUNKS {
+        let (chunk_id, notifications) = next_notifications(&mut output_stream).await;
+        assert_eq!(notifications.len(), 1);
+        chunk_ids.push(chunk_id);
+    }
+
+    // The stream pauses until a chunk is acknowledged
+    let paused = tokio::time::timeout(
+        time::Duration::from_secs(1),
+        next_notifications(&mut output_stream),
+    )
+    .await;
+    assert!(paused.is_err());
+
+    ack_sender.send(vec![chunk_ids.remove(0)]).await.unwrap();
+    let (_, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(notifications.len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn disconnected_reader_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    // The client goes away without sending a close request
+    let (ack_sender, mut output_stream) =
+        open_read_stream(&public_event_client, stream_group_id.clone(), &[]).await;
+    drop(ack_sender);
+
+    let stopped = async {
+        while let Some(message) = output_stream.next().await {
+            message.unwrap();
+        }
+    };
+    tokio::time::timeout(time::Duration::from_secs(10), stopped)
+        .await
+        .unwrap();
+
+    // The stopped stream does not take events away from other readers
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+
+    let notifications = read_stream_group(&public_event_client, stream_group_id, 1).await;
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn dropped_stream_redelivery_memory() {
+    let (internal_events_handler, public_event_client) = start_event_servers(
+        MemoryEventHandler::with_ack_wait(time::Duration::from_secs(60)),
+    )
+    .await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+
+    let (ack_sender, mut output_stream) =
+        open_read_stream(&public_event_client, stream_group_id.clone(), &[]).await;
+    let (_, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+
+    // The unacknowledged chunk of a dropped stream is redelivered right away instead of after the ack wait
+    drop(ack_sender);
+    drop(output_stream);
+
+    let (_ack_sender, mut output_stream) =
+        open_read_stream(&public_event_client, stream_group_id, &[]).await;
+    let (_, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn unknown_ack_chunk_memory() {
+    let (internal_events_handler, public_event_client) = start_event_servers(
+        MemoryEventHandler::with_ack_wait(time::Duration::from_millis(200)),
+    )
+    .await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+
+    let (ack_sender, mut output_stream) =
+        open_read_stream(&public_event_client, stream_group_id, &[]).await;
+
+    // The first chunk expires without an ack, its message is redelivered in a new chunk
+    let (expired_chunk_id, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+    let (chunk_id, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+
+    // Expired chunk ids are skipped and unknown chunk ids are reported back, the stream keeps delivering
+    ack_sender
+        .send(vec![
+            expired_chunk_id,
+            "unknown_chunk_id".to_string(),
+            chunk_id,
+        ])
+        .await
+        .unwrap();
+    let read_report = async {
+        loop {
+            let message = output_stream.next().await.unwrap().unwrap();
+            if message.notification.is_empty() && !message.ack_chunk_id.is_empty() {
+                return message.ack_chunk_id;
+            }
+        }
+    };
+    let report = tokio::time::timeout(time::Duration::from_secs(10), read_report)
+        .await
+        .unwrap();
+    assert_eq!(report, format!("{}unknown_chunk_id", UNKNOWN_CHUNK_PREFIX));
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Deleted,
+        Relation::default(),
+    )
+    .await;
+    let (_, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(
+        notifications[0].message.as_ref().unwrap().updated_type(),
+        EventType::Deleted
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn adaptive_flow_control_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    for index in 0..4 {
+        let mut emit_request = Request::new(EmitEventRequest {
+            event_resource: ResourceType::Project as i32,
+            resource_id: "project_id".to_string(),
+            event_type: EventType::Updated as i32,
+            relations: vec![Relation::default()],
+        });
+        emit_request
+            .metadata_mut()
+            .insert(INTERNAL_AUTHZ_TOKEN, "test".parse().unwrap());
+        emit_request.metadata_mut().insert(
+            IDEMPOTENCY_KEY_METADATA_NAME,
+            index.to_string().parse().unwrap(),
+        );
+        internal_events_handler
+            .emit_event(emit_request)
+            .await
+            .unwrap();
+    }
+
+    let (ack_sender, mut output_stream) = open_read_stream(
+        &public_event_client,
+        stream_group_id,
+        &[
+            (MAX_BATCH_SIZE_METADATA_NAME, "1"),
+            (FLOW_CONTROL_METADATA_NAME, "adaptive"),
+        ],
+    )
+    .await;
+
+    // The window starts with a single chunk and grows with each acknowledged chunk
+    let (chunk_id, _) = next_notifications(&mut output_stream).await;
+    let paused = tokio::time::timeout(
+        time::Duration::from_millis(500),
+        next_notifications(&mut output_stream),
+    )
+    .await;
+    assert!(paused.is_err());
+
+    ack_sender.send(vec![chunk_id]).await.unwrap();
+    next_notifications(&mut output_stream).await;
+    next_notifications(&mut output_stream).await;
+    let paused = tokio::time::timeout(
+        time::Duration::from_millis(500),
+        next_notifications(&mut output_stream),
+    )
+    .await;
+    assert!(paused.is_err());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn auto_ack_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+
+    // The client only sends the init request
+    let input = tokio_stream::iter(vec![ReadStreamGroupMessagesRequest {
+        close: false,
+        stream_action: Some(StreamAction::Init(NotificationStreamInit {
+            stream_group_id,
+        })),
+    }]);
+    let mut stream_request = Request::new(input);
+    stream_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+    stream_request
+        .metadata_mut()
+        .append(ACK_MODE_METADATA_NAME, "auto".parse().unwrap());
+    let mut output_stream = public_event_client
+        .clone()
+        .read_stream_group_messages(stream_request)
+        .await
+        .unwrap()
+        .into_inner();
+
+    let (chunk_id, notifications) = next_notifications(&mut output_stream).await;
+    assert!(chunk_id.is_empty());
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+
+    // The stream stays open after the input stream ended
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Deleted,
+        Relation::default(),
+    )
+    .await;
+
+    let (_, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(
+        notifications[0].message.as_ref().unwrap().updated_type(),
+        EventType::Deleted
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn heartbeats_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    // The fetch wait is capped at the heartbeat interval, so the long wait does not delay heartbeats
+    let (ack_sender, mut output_stream) = open_read_stream(
+        &public_event_client,
+        stream_group_id,
+        &[
+            (HEARTBEAT_INTERVAL_METADATA_NAME, "200"),
+            (MAX_WAIT_METADATA_NAME, "30000"),
+        ],
+    )
+    .await;
+
+    // An idle stream only sends heartbeats
+    let mut heartbeats = 0;
+    let idle = async {
+        loop {
+            let message = output_stream.next().await.unwrap().unwrap();
+            assert!(message.notification.is_empty());
+            assert!(message.ack_chunk_id.is_empty());
+            heartbeats += 1;
+        }
+    };
+    let _ = tokio::time::timeout(time::Duration::from_secs(1), idle).await;
+    assert!((2..=6).contains(&heartbeats));
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+
+    let (chunk_id, notifications) = next_notifications(&mut output_stream).await;
+    assert!(!chunk_id.is_empty());
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn http_gateway_sse_memory() {
+    let (internal_events_handler, public_server, public_event_client) =
+        start_servers(MemoryEventHandler::new()).await;
+    let gateway_port = start_http_gateway(public_event_client.clone(), public_server).await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+
+    let client = hyper::Client::new();
+    let unauthenticated = client
+        .get(
+            format!(
+                "http://127.0.0.1:{}/v1/stream-groups/{}/sse",
+                gateway_port, stream_group_id
+            )
+            .parse()
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(unauthenticated.status(), 401);
+
+    let response = client
+        .get(
+            format!(
+                "http://127.0.0.1:{}/v1/stream-groups/{}/sse?token=test",
+                gateway_port, stream_group_id
+            )
+            .parse()
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let mut body = response.into_body();
+    let read = async {
+        let mut received = String::new();
+        loop {
+            let data = hyper::body::HttpBody::data(&mut body)
+                .await
+                .unwrap()
+                .unwrap();
+            received.push_str(std::str::from_utf8(&data).unwrap());
+            if let Some(line) = received.lines().find(|x| x.starts_with("data:")) {
+                return serde_json::from_str::<serde_json::Value>(&line[5..]).unwrap();
+            }
+        }
+    };
+    let message = tokio::time::timeout(time::Duration::from_secs(10), read)
+        .await
+        .unwrap();
+
+    assert_eq!(message["type"], "notifications");
+    assert_eq!(message["ack_chunk_id"], "");
+    assert_eq!(message["notifications"][0]["resource_id"], "project_id");
+    assert_eq!(
+        message["notifications"][0]["updated_type"],
+        "EVENT_TYPE_UPDATED"
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn http_gateway_websocket_memory() {
+    use futures::SinkExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let (internal_events_handler, public_server, public_event_client) =
+        start_servers(MemoryEventHandler::new()).await;
+    let gateway_port = start_http_gateway(public_event_client.clone(), public_server).await;
+
+    let stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    for event_type in [EventType::Updated, EventType::Deleted] {
+        emit_event(
+            &internal_events_handler,
+            ResourceType::Project,
+            "project_id",
+            event_type,
+            Relation::default(),
+        )
+        .await;
+    }
+
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!(
+        "ws://127.0.0.1:{}/v1/stream-groups/{}/ws?token=test&max-batch-size=1",
+        gateway_port, stream_group_id
+    ))
+    .await
+    .unwrap();
+
+    let message = next_gateway_message(&mut socket).await;
+    assert_eq!(message["type"], "notifications");
+    assert_eq!(
+        message["notifications"][0]["updated_type"],
+        "EVENT_TYPE_UPDATED"
+    );
+    let ack_request = serde_json::json!({ "ack_chunk_ids": [message["ack_chunk_id"]] });
+    socket
+        .send(Message::Text(ack_request.to_string()))
+        .await
+        .unwrap();
+
+    let message = next_gateway_message(&mut socket).await;
+    assert_eq!(message["type"], "notifications");
+    assert_eq!(
+        message["notifications"][0]["updated_type"],
+        "EVENT_TYPE_DELETED"
+    );
+
+    // Unknown chunk ids are reported back, the stream keeps delivering
+    let ack_request = serde_json::json!({
+        "ack_chunk_ids": ["unknown_chunk_id", message["ack_chunk_id"]]
+    });
+    socket
+        .send(Message::Text(ack_request.to_string()))
+        .await
+        .unwrap();
+    let message = next_gateway_message(&mut socket).await;
+    assert_eq!(message["type"], "unknown_chunk");
+    assert_eq!(message["ack_chunk_id"], "unknown_chunk_id");
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Created,
+        Relation::default(),
+    )
+    .await;
+    let message = next_gateway_message(&mut socket).await;
+    assert_eq!(message["type"], "notifications");
+    assert_eq!(
+        message["notifications"][0]["updated_type"],
+        "EVENT_TYPE_CREATED"
+    );
+}
+
+// Reads the next JSON message of a gateway WebSocket
+async fn next_gateway_message(
+    socket: &mut tokio_tungstenite::WebSocketStream<
+        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+    >,
+) -> serde_json::Value {
+    let read = async {
+        loop {
+            let message = socket.next().await.unwrap().unwrap();
+            if let tokio_tungstenite::tungstenite::Message::Text(text) = message {
+                return serde_json::from_str::<serde_json::Value>(&text).unwrap();
+            }
+        }
+    };
+
+    tokio::time::timeout(time::Duration::from_secs(10), read)
+        .await
+        .unwrap()
+}
+
+const WEBHOOK_SECRET: &str = "0123456789abcdef";
+
+async fn create_webhook_stream_group(
+    public_event_client: &UpdateNotificationServiceClient<Channel>,
+    webhook_port: u16,
+) -> Result<String, tonic::Status> {
+    let mut create_request = Request::new(CreateEventStreamingGroupRequest {
+        resource: ResourceType::Project as i32,
+        resource_id: "project_id".to_string(),
+        include_subresource: true,
+        ..Default::default()
+    });
+    create_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+    create_request.metadata_mut().append(
+        WEBHOOK_URL_METADATA_NAME,
+        format!("http://127.0.0.1:{}/hook", webhook_port)
+            .parse()
+            .unwrap(),
+    );
+    create_request.metadata_mut().append(
+        WEBHOOK_SECRET_METADATA_NAME,
+        WEBHOOK_SECRET.parse().unwrap(),
+    );
+
+    Ok(public_event_client
+        .clone()
+        .create_event_streaming_group(create_request)
+        .await?
+        .into_inner()
+        .stream_group_id)
+}
+
+async fn next_webhook_request(
+    requests: &Receiver<(axum::http::HeaderMap, axum::body::Bytes)>,
+) -> (axum::http::HeaderMap, axum::body::Bytes) {
+    tokio::time::timeout(time::Duration::from_secs(10), requests.recv())
+        .await
+        .unwrap()
+        .unwrap()
+}
 
-use crate::{
-    server::{
-        internal_event_server::InternalServer,
-        public_event_server::PublicServer,
-        server::{INTERNAL_AUTHZ_TOKEN, TOKEN_METADATA_NAME},
-    },
-    storage_test_server::storage_endpoint_mock::{
-        AuthzEndpointMock, ResourceInfoMock, StorageEndpointMock,
-    },
-    stream_handler::{handler::EventHandler, natsio::NatsIOEventHandler},
-};
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn webhook_delivery_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+    let (webhook_port, requests) = start_webhook_stand_in(Vec::new()).await;
 
-static INIT: Once = Once::new();
-static SERVICE_ENDPOINT_PORT: RwLock<u16> = RwLock::new(0);
-
-fn initialize_e2e_server() {
-    env_logger::Builder::new()
-        .format(|buf, record| {
-            writeln!(
-                buf,
-                "{}:{} {} [{}] - {}",
-                record.file().unwrap_or("unknown"),
-                record.line().unwrap_or(0),
-                chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"),
-                record.level(),
-                record.args()
-            )
-        })
-        .filter_level(log::LevelFilter::Error)
-        .init();
+    let stream_group_id = create_webhook_stream_group(&public_event_client, webhook_port)
+        .await
+        .unwrap();
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
 
-    let wg = WaitGroup::new();
-    INIT.call_once(|| start_server_background(wg.clone()));
+    let (headers, body) = next_webhook_request(&requests).await;
+    let timestamp = headers
+        .get(WEBHOOK_TIMESTAMP_HEADER)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse::<i64>()
+        .unwrap();
+    assert_eq!(
+        headers.get(WEBHOOK_SIGNATURE_HEADER).unwrap(),
+        format!("sha256={}", sign_payload(WEBHOOK_SECRET, timestamp, &body)).as_str()
+    );
 
-    wg.wait();
+    let payload = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    assert_eq!(payload["stream_group_id"], stream_group_id.as_str());
+    assert_eq!(payload["notifications"][0]["resource_id"], "project_id");
 }
 
-fn start_server_background(wg: WaitGroup) {
-    tokio::spawn(async move {
-        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
-        let listener = TcpListener::bind(addr).await.unwrap();
-        let port = listener.local_addr().unwrap().port();
-        *SERVICE_ENDPOINT_PORT.write().unwrap() = port;
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn webhook_retry_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+    let (webhook_port, requests) = start_webhook_stand_in(vec![500, 503]).await;
 
-        let stream_group_store = Mutex::new(HashMap::new());
-        let authz_service = AuthzEndpointMock {};
-        let event_service = StorageEndpointMock {
-            stream_groups: stream_group_store,
-        };
-        let resource_service = ResourceInfoMock {};
+    create_webhook_stream_group(&public_event_client, webhook_port)
+        .await
+        .unwrap();
 
-        let builder = Server::builder()
-            .add_service(InternalAuthorizeServiceServer::new(authz_service))
-            .add_service(InternalEventServiceServer::new(event_service))
-            .add_service(ResourceInfoServiceServer::new(resource_service))
-            .serve_with_incoming(TcpListenerStream::new(listener));
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
 
-        drop(wg);
-        builder.await.unwrap();
+    // The same batch is retried until the endpoint accepts it
+    let (_, first_body) = next_webhook_request(&requests).await;
+    for _ in 0..2 {
+        let (_, body) = next_webhook_request(&requests).await;
+        assert_eq!(body, first_body);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn webhook_disabled_memory() {
+    let (internal_events_handler, public_server, public_event_client) =
+        start_servers(MemoryEventHandler::new()).await;
+    let gateway_port = start_http_gateway(public_event_client.clone(), public_server).await;
+    let (webhook_port, requests) = start_webhook_stand_in(vec![500; 6]).await;
+
+    let stream_group_id = create_webhook_stream_group(&public_event_client, webhook_port)
+        .await
+        .unwrap();
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+
+    // Two batches with three attempts each fail before the webhook is disabled
+    for _ in 0..6 {
+        next_webhook_request(&requests).await;
+    }
+    assert!(
+        tokio::time::timeout(time::Duration::from_secs(1), requests.recv())
+            .await
+            .is_err()
+    );
+
+    let webhook_uri = format!(
+        "http://127.0.0.1:{}/v1/stream-groups/{}/webhook",
+        gateway_port, stream_group_id
+    );
+    let (status, body) = gateway_request(hyper::Method::GET, webhook_uri.clone()).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["state"], "disabled");
+    assert_eq!(
+        body["url"],
+        format!("http://127.0.0.1:{}/hook", webhook_port).as_str()
+    );
+
+    // The undelivered messages stay in the stream group and are delivered once the webhook is enabled again
+    let (status, body) =
+        gateway_request(hyper::Method::POST, format!("{}/enable", webhook_uri)).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["state"], "active");
+
+    let (_, body) = next_webhook_request(&requests).await;
+    let payload = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    assert_eq!(payload["notifications"][0]["resource_id"], "project_id");
+    let (_, body) = gateway_request(hyper::Method::GET, webhook_uri).await;
+    assert_eq!(body["state"], "active");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn webhook_registration_memory() {
+    let (internal_events_handler, public_event_client) =
+        start_event_servers(MemoryEventHandler::new()).await;
+
+    let mut create_request = Request::new(CreateEventStreamingGroupRequest {
+        resource: ResourceType::Project as i32,
+        resource_id: "project_id".to_string(),
+        include_subresource: true,
+        ..Default::default()
     });
+    create_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+    create_request.metadata_mut().append(
+        WEBHOOK_URL_METADATA_NAME,
+        "http://127.0.0.1/hook".parse().unwrap(),
+    );
+    let err = public_event_client
+        .clone()
+        .create_event_streaming_group(create_request)
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+}
+
+// Creates a project stream group with a webhook directly in the event handler, like an instance before a restart
+async fn store_webhook_stream_group(
+    event_handler: &MemoryEventHandler,
+    stream_group_id: &str,
+    webhook_port: u16,
+    disabled: bool,
+) {
+    event_handler
+        .create_stream_group(
+            stream_group_id.to_string(),
+            &[Hierarchy {
+                project_id: "project_id".to_string(),
+                ..Default::default()
+            }],
+            ResourceType::Project,
+            "project_id".to_string(),
+            true,
+            &[],
+            StreamStartPosition::All,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    event_handler
+        .store_webhook(
+            stream_group_id.to_string(),
+            &StoredWebhook {
+                url: format!("http://127.0.0.1:{}/hook", webhook_port),
+                secret: WEBHOOK_SECRET.to_string(),
+                fetch_options: FetchOptions::default(),
+                disabled,
+            },
+        )
+        .await
+        .unwrap();
 }
 
 #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
-async fn full_test_nats() {
-    initialize_e2e_server();
-    let nats_client = async_nats::connect("localhost:4222").await.unwrap();
-    let event_handler = Box::new(NatsIOEventHandler::new(nats_client).await.unwrap());
+async fn webhook_resume_memory() {
+    let event_handler = MemoryEventHandler::new();
+    let (active_port, active_requests) = start_webhook_stand_in(Vec::new()).await;
+    let (disabled_port, disabled_requests) = start_webhook_stand_in(Vec::new()).await;
+    store_webhook_stream_group(&event_handler, "active_stream_group", active_port, false).await;
+    store_webhook_stream_group(&event_handler, "disabled_stream_group", disabled_port, true).await;
 
-    let internal_events_handler = InternalServer {
-        event_handler: event_handler.clone(),
-        internal_token: "test".to_string(),
+    let (internal_events_handler, public_server, _) = start_servers(event_handler).await;
+    public_server.resume_webhooks().await.unwrap();
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+
+    // Only the active webhook is resumed, the disabled one waits for being enabled
+    let (_, body) = next_webhook_request(&active_requests).await;
+    let payload = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+    assert_eq!(payload["stream_group_id"], "active_stream_group");
+    assert!(
+        tokio::time::timeout(time::Duration::from_secs(1), disabled_requests.recv())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn webhook_removed_stream_group_memory() {
+    let event_handler = MemoryEventHandler::new();
+    let (internal_events_handler, public_server, public_event_client) =
+        start_servers(event_handler.clone()).await;
+    let (webhook_port, requests) = start_webhook_stand_in(Vec::new()).await;
+
+    let stream_group_id = create_webhook_stream_group(&public_event_client, webhook_port)
+        .await
+        .unwrap();
+    assert!(public_server
+        .webhooks
+        .lock()
+        .await
+        .contains_key(&stream_group_id));
+
+    // Removed in the underlaying system without this instance, e.g. by another instance
+    event_handler
+        .delete_stream_group(stream_group_id.clone())
+        .await
+        .unwrap();
+
+    // The delivery stops instead of retrying and forgets the webhook
+    tokio::time::timeout(time::Duration::from_secs(5), async {
+        while public_server
+            .webhooks
+            .lock()
+            .await
+            .contains_key(&stream_group_id)
+        {
+            tokio::time::sleep(time::Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(event_handler.list_webhooks().await.unwrap().is_empty());
+}
+
+// Sends an authenticated request to the json api of the gateway, returns the status and the JSON body
+async fn gateway_request(method: hyper::Method, uri: String) -> (u16, serde_json::Value) {
+    let request = hyper::Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(TOKEN_METADATA_NAME, "test")
+        .body(hyper::Body::empty())
+        .unwrap();
+    let response = hyper::Client::new().request(request).await.unwrap();
+    let status = response.status().as_u16();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    if body.is_empty() {
+        return (status, serde_json::Value::Null);
+    }
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn dead_letters_memory() {
+    let (internal_events_handler, public_server, public_event_client) = start_servers(
+        MemoryEventHandler::with_ack_wait(time::Duration::from_millis(200)),
+    )
+    .await;
+    let gateway_port = start_http_gateway(public_event_client.clone(), public_server).await;
+
+    let mut create_request = Request::new(CreateEventStreamingGroupRequest {
+        resource: ResourceType::Project as i32,
+        resource_id: "project_id".to_string(),
+        include_subresource: true,
+        ..Default::default()
+    });
+    create_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+    create_request
+        .metadata_mut()
+        .append(MAX_DELIVER_METADATA_NAME, "2".parse().unwrap());
+    let stream_group_id = public_event_client
+        .clone()
+        .create_event_streaming_group(create_request)
+        .await
+        .unwrap()
+        .into_inner()
+        .stream_group_id;
+    let dead_letters_uri = format!(
+        "http://127.0.0.1:{}/v1/stream-groups/{}/dead-letters",
+        gateway_port, stream_group_id
+    );
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+
+    // The message is never acknowledged, after its second delivery it becomes a dead letter
+    let (ack_sender, mut output_stream) =
+        open_read_stream(&public_event_client, stream_group_id.clone(), &[]).await;
+    for _ in 0..2 {
+        let (_, notifications) = next_notifications(&mut output_stream).await;
+        assert_eq!(resource_ids(notifications), vec!["project_id"]);
+    }
+
+    let list = async {
+        loop {
+            let (status, body) =
+                gateway_request(hyper::Method::GET, dead_letters_uri.clone()).await;
+            assert_eq!(status, 200);
+            if !body["dead_letters"].as_array().unwrap().is_empty() {
+                return body;
+            }
+            tokio::time::sleep(time::Duration::from_millis(50)).await;
+        }
     };
+    let body = tokio::time::timeout(time::Duration::from_secs(10), list)
+        .await
+        .unwrap();
+    let dead_letter = body["dead_letters"][0].clone();
+    assert_eq!(dead_letter["deliveries"], 2);
+    assert_eq!(dead_letter["notification"]["resource_id"], "project_id");
+    let dead_letter_uri = format!("{}/{}", dead_letters_uri, dead_letter["id"]);
 
-    let server_addr_port = SERVICE_ENDPOINT_PORT.read().unwrap().clone();
+    let unauthenticated = hyper::Client::new()
+        .get(dead_letter_uri.parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(unauthenticated.status(), 401);
+    assert_eq!(
+        gateway_request(hyper::Method::GET, dead_letter_uri.clone()).await,
+        (200, dead_letter)
+    );
 
-    let authz_client =
-        InternalAuthorizeServiceClient::connect(format!("http://127.0.0.1:{}", server_addr_port))
+    // A requeued dead letter is delivered to the stream group again
+    let requeue_uri = format!("{}/requeue", dead_letter_uri);
+    assert_eq!(
+        gateway_request(hyper::Method::POST, requeue_uri.clone())
             .await
-            .unwrap();
+            .0,
+        204
+    );
+    assert_eq!(
+        gateway_request(hyper::Method::POST, requeue_uri).await.0,
+        404
+    );
+    let (chunk_id, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+    ack_sender.send(vec![chunk_id]).await.unwrap();
 
-    let resource_client =
-        ResourceInfoServiceClient::connect(format!("http://127.0.0.1:{}", server_addr_port))
+    assert_eq!(
+        gateway_request(hyper::Method::DELETE, dead_letter_uri)
             .await
-            .unwrap();
+            .0,
+        404
+    );
+    assert_eq!(
+        gateway_request(hyper::Method::DELETE, dead_letters_uri).await,
+        (200, serde_json::json!({ "purged": 0 }))
+    );
+}
 
-    let internal_event_client =
-        InternalEventServiceClient::connect(format!("http://127.0.0.1:{}", server_addr_port))
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn inactivity_ttl_memory() {
+    let (internal_events_handler, public_server, public_event_client) =
+        start_servers(MemoryEventHandler::new()).await;
+    tokio::spawn(
+        public_server
+            .clone()
+            .expire_stream_groups(time::Duration::from_millis(50)),
+    );
+    let gateway_port = start_http_gateway(public_event_client.clone(), public_server).await;
+
+    let mut create_request = Request::new(CreateEventStreamingGroupRequest {
+        resource: ResourceType::Project as i32,
+        resource_id: "project_id".to_string(),
+        include_subresource: true,
+        ..Default::default()
+    });
+    create_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+    create_request
+        .metadata_mut()
+        .append(INACTIVITY_TTL_METADATA_NAME, "300".parse().unwrap());
+    let expiring_stream_group_id = public_event_client
+        .clone()
+        .create_event_streaming_group(create_request)
+        .await
+        .unwrap()
+        .into_inner()
+        .stream_group_id;
+    let permanent_stream_group_id = create_stream_group(
+        &public_event_client,
+        ResourceType::Project,
+        "project_id",
+        true,
+    )
+    .await;
+
+    emit_event(
+        &internal_events_handler,
+        ResourceType::Project,
+        "project_id",
+        EventType::Updated,
+        Relation::default(),
+    )
+    .await;
+    let notifications =
+        read_stream_group(&public_event_client, expiring_stream_group_id.clone(), 1).await;
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+
+    // Once the stream group was not read for its ttl, its record is removed from the internal event service
+    let dead_letters_uri = format!(
+        "http://127.0.0.1:{}/v1/stream-groups/{}/dead-letters",
+        gateway_port, expiring_stream_group_id
+    );
+    let expired = async {
+        while gateway_request(hyper::Method::GET, dead_letters_uri.clone())
             .await
-            .unwrap();
+            .0
+            != 404
+        {
+            tokio::time::sleep(time::Duration::from_millis(50)).await;
+        }
+    };
+    tokio::time::timeout(time::Duration::from_secs(10), expired)
+        .await
+        .unwrap();
 
-    let public_events_handler = PublicServer {
-        event_handler: event_handler,
-        internal_authz_client: authz_client,
-        resource_client: resource_client,
-        internal_events_client: internal_event_client,
+    let delete_stream_group = |stream_group_id: String| {
+        let mut delete_request = Request::new(DeleteEventStreamingGroupRequest { stream_group_id });
+        delete_request
+            .metadata_mut()
+            .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+        let mut client = public_event_client.clone();
+        async move { client.delete_event_streaming_group(delete_request).await }
     };
+    assert_eq!(
+        delete_stream_group(expiring_stream_group_id)
+            .await
+            .unwrap_err()
+            .code(),
+        tonic::Code::NotFound
+    );
+    delete_stream_group(permanent_stream_group_id)
+        .await
+        .unwrap();
+}
 
-    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-    let notification_server_port = listener.local_addr().unwrap().port();
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn paused_reader_keep_alive_memory() {
+    let (internal_events_handler, public_server, public_event_client) = start_servers(
+        MemoryEventHandler::with_ack_wait(time::Duration::from_secs(30)),
+    )
+    .await;
+    tokio::spawn(
+        public_server
+            .clone()
+            .expire_stream_groups(time::Duration::from_millis(50)),
+    );
+    let gateway_port = start_http_gateway(public_event_client.clone(), public_server).await;
 
-    tokio::spawn(async move {
-        Server::builder()
-            .add_service(UpdateNotificationServiceServer::new(public_events_handler))
-            .serve_with_incoming(TcpListenerStream::new(listener))
-            .await
-            .unwrap();
+    let mut create_request = Request::new(CreateEventStreamingGroupRequest {
+        resource: ResourceType::Project as i32,
+        resource_id: "project_id".to_string(),
+        include_subresource: true,
+        ..Default::default()
     });
+    create_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+    create_request
+        .metadata_mut()
+        .append(INACTIVITY_TTL_METADATA_NAME, "300".parse().unwrap());
+    let stream_group_id = public_event_client
+        .clone()
+        .create_event_streaming_group(create_request)
+        .await
+        .unwrap()
+        .into_inner()
+        .stream_group_id;
+
+    for _ in 0..2 {
+        emit_event(
+            &internal_events_handler,
+            ResourceType::Project,
+            "project_id",
+            EventType::Updated,
+            Relation::default(),
+        )
+        .await;
+    }
+
+    // The unacknowledged chunk exhausts the in flight window, the reader pauses without fetching
+    let (ack_sender, mut output_stream) = open_read_stream(
+        &public_event_client,
+        stream_group_id.clone(),
+        &[
+            (MAX_BATCH_SIZE_METADATA_NAME, "1"),
+            (MAX_IN_FLIGHT_CHUNKS_METADATA_NAME, "1"),
+        ],
+    )
+    .await;
+    let (_, notifications) = next_notifications(&mut output_stream).await;
+    assert_eq!(resource_ids(notifications), vec!["project_id"]);
+
+    // The paused reader keeps the stream group alive beyond its ttl
+    let dead_letters_uri = format!(
+        "http://127.0.0.1:{}/v1/stream-groups/{}/dead-letters",
+        gateway_port, stream_group_id
+    );
+    tokio::time::sleep(time::Duration::from_secs(1)).await;
+    assert_eq!(
+        gateway_request(hyper::Method::GET, dead_letters_uri.clone())
+            .await
+            .0,
+        200
+    );
+
+    // Without a reader the stream group expires
+    drop(ack_sender);
+    drop(output_stream);
+    let expired = async {
+        while gateway_request(hyper::Method::GET, dead_letters_uri.clone())
+            .await
+            .0
+            != 404
+        {
+            tokio::time::sleep(time::Duration::from_millis(50)).await;
+        }
+    };
+    tokio::time::timeout(time::Duration::from_secs(10), expired)
+        .await
+        .unwrap();
+}
 
+async fn emit_event(
+    internal_events_handler: &InternalServer,
+    resource_type: ResourceType,
+    resource_id: &str,
+    event_type: EventType,
+    relation: Relation,
+) {
     let mut emit_request = Request::new(EmitEventRequest {
-        event_resource: ResourceType::Project as i32,
-        resource_id: "project_id".to_string(),
-        event_type: EventType::All as i32,
-        relations: vec![Relation {
-            ..Default::default()
-        }],
+        event_resource: resource_type as i32,
+        resource_id: resource_id.to_string(),
+        event_type: event_type as i32,
+        relations: vec![relation],
     });
 
     emit_request
         .metadata_mut()
         .insert(INTERNAL_AUTHZ_TOKEN, "test".parse().unwrap());
+    // Every emitted event is a separate one, also if a test or an earlier run emitted an identical event
+    emit_request.metadata_mut().insert(
+        IDEMPOTENCY_KEY_METADATA_NAME,
+        uuid::Uuid::new_v4().to_string().parse().unwrap(),
+    );
 
     internal_events_handler
         .emit_event(emit_request)
         .await
         .unwrap();
+}
 
-    let public_event_client = UpdateNotificationServiceClient::connect(format!(
-        "http://127.0.0.1:{}",
-        notification_server_port
-    ))
-    .await
-    .unwrap();
-
+async fn create_stream_group(
+    public_event_client: &UpdateNotificationServiceClient<Channel>,
+    resource_type: ResourceType,
+    resource_id: &str,
+    include_subresource: bool,
+) -> String {
     let mut create_event_streaming_group_request = Request::new(CreateEventStreamingGroupRequest {
-        include_subresource: true,
-        resource: ResourceType::Project as i32,
-        resource_id: "project_id".to_string(),
+        include_subresource,
+        resource: resource_type as i32,
+        resource_id: resource_id.to_string(),
         ..Default::default()
     });
 
@@ -194,6 +2167,44 @@ async fn full_test_nats() {
         .unwrap()
         .into_inner();
 
+    stream_group.stream_group_id
+}
+
+// Creates a stream group that only delivers events emitted after its creation
+async fn create_new_events_stream_group(
+    public_event_client: &UpdateNotificationServiceClient<Channel>,
+    resource_type: ResourceType,
+    resource_id: &str,
+    include_subresource: bool,
+) -> String {
+    let mut create_request = Request::new(CreateEventStreamingGroupRequest {
+        include_subresource,
+        resource: resource_type as i32,
+        resource_id: resource_id.to_string(),
+        ..Default::default()
+    });
+    create_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+    create_request
+        .metadata_mut()
+        .append(STREAM_START_METADATA_NAME, "new".parse().unwrap());
+
+    public_event_client
+        .clone()
+        .create_event_streaming_group(create_request)
+        .await
+        .unwrap()
+        .into_inner()
+        .stream_group_id
+}
+
+// Reads and acknowledges messages of a stream group until at least the expected number of notifications arrived
+async fn read_stream_group(
+    public_event_client: &UpdateNotificationServiceClient<Channel>,
+    stream_group_id: String,
+    expected: usize,
+) -> Vec<NotificationStreamResponse> {
     // Here be dragons
     // Bidirectional is a bit more complicated since its request needs to be a stream
 
@@ -205,7 +2216,7 @@ async fn full_test_nats() {
         yield ReadStreamGroupMessagesRequest {
             close: false,
             stream_action: Some(StreamAction::Init(NotificationStreamInit {
-                stream_group_id: stream_group.stream_group_id,
+                stream_group_id,
             })),
         };
 
@@ -247,31 +2258,111 @@ async fn full_test_nats() {
         .unwrap();
     let mut output_stream = output_stream_response.into_inner();
 
-    let mut id_counter = 0;
-    while let Some(recv) = output_stream.next().await {
-        let message = recv.unwrap();
-        let response_ids = message.notification;
-        let chunk_id = message.ack_chunk_id;
-        let ids: Vec<String> = response_ids
-            .iter()
-            .map(|x| x.clone().message.unwrap().resource_id)
-            .collect();
-
-        id_counter += ids.len();
-        match input_channel_sender.send(vec![chunk_id]).await {
-            Ok(_) => {}
-            Err(err) => {
-                error!("{}", err);
+    let mut notifications = Vec::new();
+    let read = async {
+        while let Some(recv) = output_stream.next().await {
+            let message = recv.unwrap();
+            // Heartbeats have no chunk to acknowledge
+            if message.ack_chunk_id.is_empty() {
+                continue;
+            }
+            let chunk_id = message.ack_chunk_id;
+            notifications.extend(message.notification);
+
+            match input_channel_sender.send(vec![chunk_id]).await {
+                Ok(_) => {}
+                Err(err) => {
+                    error!("{}", err);
+                    break;
+                }
+            };
+
+            if notifications.len() >= expected {
                 break;
             }
+        }
+    };
+
+    if tokio::time::timeout(time::Duration::from_secs(10), read)
+        .await
+        .is_err()
+    {
+        panic!("timeout while waiting for messages")
+    }
+
+    notifications
+}
+
+// Opens a read stream for a stream group
+// Every entry sent to the returned sender is forwarded as ack message
+async fn open_read_stream(
+    public_event_client: &UpdateNotificationServiceClient<Channel>,
+    stream_group_id: String,
+    metadata: &[(&'static str, &str)],
+) -> (
+    Sender<Vec<String>>,
+    tonic::Streaming<ReadStreamGroupMessagesResponse>,
+) {
+    let (ack_sender, ack_recv): (Sender<Vec<String>>, Receiver<Vec<String>>) =
+        async_channel::unbounded();
+
+    let input = async_stream::stream! {
+        yield ReadStreamGroupMessagesRequest {
+            close: false,
+            stream_action: Some(StreamAction::Init(NotificationStreamInit {
+                stream_group_id,
+            })),
         };
 
-        if id_counter > 0 {
-            break;
+        while let Ok(ack_chunk_id) = ack_recv.recv().await {
+            yield ReadStreamGroupMessagesRequest {
+                close: false,
+                stream_action: Some(StreamAction::Ack(NotficationStreamAck { ack_chunk_id })),
+            };
         }
-    }
+    };
 
-    if id_counter == 0 {
-        panic!("no messages found")
+    let mut stream_request = Request::new(input);
+    stream_request
+        .metadata_mut()
+        .append(TOKEN_METADATA_NAME, "test".parse().unwrap());
+    for (key, value) in metadata {
+        stream_request
+            .metadata_mut()
+            .append(*key, value.parse().unwrap());
     }
+
+    let output_stream = public_event_client
+        .clone()
+        .read_stream_group_messages(stream_request)
+        .await
+        .unwrap()
+        .into_inner();
+
+    (ack_sender, output_stream)
+}
+
+// Reads from a read stream until a non empty chunk arrived and returns its chunk id and notifications
+async fn next_notifications(
+    output_stream: &mut tonic::Streaming<ReadStreamGroupMessagesResponse>,
+) -> (String, Vec<NotificationStreamResponse>) {
+    let read = async {
+        loop {
+            let message = output_stream.next().await.unwrap().unwrap();
+            if !message.notification.is_empty() {
+                return (message.ack_chunk_id, message.notification);
+            }
+        }
+    };
+
+    tokio::time::timeout(time::Duration::from_secs(10), read)
+        .await
+        .unwrap()
+}
+
+fn resource_ids(notifications: Vec<NotificationStreamResponse>) -> Vec<String> {
+    notifications
+        .into_iter()
+        .map(|x| x.message.unwrap().resource_id)
+        .collect()
 }
@@ -1,9 +1,14 @@
 extern crate dotenv;
 use dotenv::dotenv;
-use std::{env, str::FromStr};
+use std::{env, str::FromStr, time::Duration};
 
-use async_nats::ServerAddr;
-use server::server::EventServer;
+use async_nats::{
+    jetstream::stream::{DiscardPolicy, StorageType},
+    ServerAddr,
+};
+use server::server::{EventBackend, EventServer};
+use stream_handler::memory::MemoryStreamConfig;
+use stream_handler::natsio::NatsIOStreamConfig;
 
 use std::io::Write;
 
@@ -32,25 +37,95 @@ async fn main() {
 
     dotenv().ok().unwrap();
     let internal_event_token = env::var("INTERNAL_EVENT_TOKEN").unwrap();
-    let nats_host = env::var("NATS_HOST").unwrap();
-    let nats_port = env::var("NATS_PORT").unwrap();
+    let event_backend = env::var("EVENT_BACKEND").unwrap_or_else(|_| "nats".to_string());
     let event_service_client_host = env::var("EVENT_SERVICE").unwrap();
     let authz_service_client_host = env::var("AUTHZ_SERVICE").unwrap();
     let internal_event_service_host = env::var("INTERNAL_EVENT_SERVER_HOST").unwrap();
     let public_event_service_host = env::var("PUBLIC_EVENT_SERVER_HOST").unwrap();
     let resource_info_service_host = env::var("RESOURCE_INFO_SERVER_HOST").unwrap();
+    let http_gateway_host = env::var("HTTP_GATEWAY_HOST").ok();
 
-    let nats_addr = ServerAddr::from_str(format!("{}:{}", nats_host, nats_port).as_str()).unwrap();
+    let event_backend = match event_backend.as_str() {
+        "nats" => {
+            let nats_host = env::var("NATS_HOST").unwrap();
+            let nats_port = env::var("NATS_PORT").unwrap();
+            let nats_addr =
+                ServerAddr::from_str(format!("{}:{}", nats_host, nats_port).as_str()).unwrap();
+            EventBackend::NatsIO(vec![nats_addr], nats_stream_config())
+        }
+        "memory" => EventBackend::Memory(memory_stream_config()),
+        _ => panic!(
+            "unknown event backend {}, expected nats or memory",
+            event_backend
+        ),
+    };
 
     EventServer::start_server(
         internal_event_token,
-        &[nats_addr],
+        event_backend,
         event_service_client_host,
         authz_service_client_host,
         internal_event_service_host,
         public_event_service_host,
         resource_info_service_host,
+        http_gateway_host,
     )
     .await
     .unwrap();
 }
+
+// Reads the desired configuration of the nats event stream
+// All values are optional and fall back to the defaults of NatsIOStreamConfig
+fn nats_stream_config() -> NatsIOStreamConfig {
+    let mut stream_config = NatsIOStreamConfig::default();
+
+    if let Ok(value) = env::var("NATS_STREAM_STORAGE") {
+        stream_config.storage = match value.as_str() {
+            "file" => StorageType::File,
+            "memory" => StorageType::Memory,
+            _ => panic!("unknown stream storage {}, expected file or memory", value),
+        };
+    }
+    if let Ok(value) = env::var("NATS_STREAM_REPLICAS") {
+        stream_config.replicas = value.parse().unwrap();
+    }
+    if let Ok(value) = env::var("NATS_STREAM_MAX_AGE") {
+        stream_config.max_age = Duration::from_secs(value.parse().unwrap());
+    }
+    if let Ok(value) = env::var("NATS_STREAM_MAX_BYTES") {
+        stream_config.max_bytes = value.parse().unwrap();
+    }
+    if let Ok(value) = env::var("NATS_STREAM_DUPLICATE_WINDOW") {
+        stream_config.duplicate_window = Duration::from_secs(value.parse().unwrap());
+    }
+    if let Ok(value) = env::var("NATS_STREAM_DISCARD") {
+        stream_config.discard = match value.as_str() {
+            "old" => DiscardPolicy::Old,
+            "new" => DiscardPolicy::New,
+            _ => panic!(
+                "unknown stream discard policy {}, expected old or new",
+                value
+            ),
+        };
+    }
+
+    return stream_config;
+}
+
+// Reads the retention of the in-memory event stream
+// All values are optional and fall back to the defaults of MemoryStreamConfig
+fn memory_stream_config() -> MemoryStreamConfig {
+    let mut stream_config = MemoryStreamConfig::default();
+
+    if let Ok(value) = env::var("MEMORY_STREAM_MAX_AGE") {
+        stream_config.max_age = Duration::from_secs(value.parse().unwrap());
+    }
+    if let Ok(value) = env::var("MEMORY_STREAM_MAX_EVENTS") {
+        stream_config.max_events = value.parse().unwrap();
+    }
+    if let Ok(value) = env::var("MEMORY_STREAM_DUPLICATE_WINDOW") {
+        stream_config.duplicate_window = Duration::from_secs(value.parse().unwrap());
+    }
+
+    return stream_config;
+}
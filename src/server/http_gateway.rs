@@ -0,0 +1,759 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use aruna_rust_api::api::notification::services::v1::read_stream_group_messages_request::StreamAction;
+use aruna_rust_api::api::notification::services::v1::update_notification_service_client::UpdateNotificationServiceClient;
+use aruna_rust_api::api::notification::services::v1::{
+    EventNotificationMessage, NotficationStreamAck, NotificationStreamInit,
+    NotificationStreamResponse, ReadStreamGroupMessagesRequest, ReadStreamGroupMessagesResponse,
+};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{TimeZone, Utc};
+use futures::{SinkExt, StreamExt};
+use log::error;
+use prost::Message as ProstMessage;
+use serde::{Deserialize, Serialize};
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::transport::Channel;
+use tonic::{Code, Request, Status, Streaming};
+
+use crate::stream_handler::handler::DeadLetter;
+
+use super::public_event_server::PublicServer;
+use super::server::{
+    ACK_MODE_METADATA_NAME, FLOW_CONTROL_METADATA_NAME, HEARTBEAT_INTERVAL_METADATA_NAME,
+    MAX_BATCH_BYTES_METADATA_NAME, MAX_BATCH_SIZE_METADATA_NAME,
+    MAX_IN_FLIGHT_CHUNKS_METADATA_NAME, MAX_WAIT_METADATA_NAME, TOKEN_METADATA_NAME,
+    UNKNOWN_CHUNK_PREFIX,
+};
+use super::webhook::{RegisteredWebhook, WebhookState};
+
+// Browsers can not set headers for SSE and WebSocket requests, so the token can also be passed as query parameter
+const TOKEN_QUERY_NAME: &str = "token";
+
+// Query parameters that are passed on as metadata of the read stream, they share the name of the metadata
+const FORWARDED_QUERY_NAMES: [&str; 7] = [
+    ACK_MODE_METADATA_NAME,
+    MAX_BATCH_SIZE_METADATA_NAME,
+    MAX_BATCH_BYTES_METADATA_NAME,
+    MAX_WAIT_METADATA_NAME,
+    HEARTBEAT_INTERVAL_METADATA_NAME,
+    MAX_IN_FLIGHT_CHUNKS_METADATA_NAME,
+    FLOW_CONTROL_METADATA_NAME,
+];
+
+// Messages sent to browser clients, encoded as JSON with a type field
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum GatewayMessage {
+    Notifications {
+        ack_chunk_id: String,
+        notifications: Vec<JsonNotification>,
+    },
+    Heartbeat,
+    // An acknowledged chunk id that is unknown to the stream, the stream continues
+    UnknownChunk {
+        ack_chunk_id: String,
+    },
+    // Errors end the stream, the code is the gRPC status code
+    Error {
+        code: i32,
+        message: String,
+    },
+}
+
+// JSON representation of an EventNotificationMessage with its position in the stream
+// Enum values use their protobuf names and the timestamp is formatted as RFC 3339
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct JsonNotification {
+    resource: String,
+    resource_id: String,
+    updated_type: String,
+    sequence: u64,
+    timestamp: Option<String>,
+}
+
+// JSON representation of a dead letter of a stream group
+// The notification is missing if the payload of the dead letter is not a valid EventNotificationMessage
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct JsonDeadLetter {
+    id: u64,
+    subject: String,
+    deliveries: u64,
+    dead_lettered_at: String,
+    notification: Option<JsonNotification>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonDeadLetters {
+    dead_letters: Vec<JsonDeadLetter>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonPurgeResponse {
+    purged: u64,
+}
+
+// JSON representation of the webhook of a stream group, the secret is never returned
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct JsonWebhook {
+    url: String,
+    state: WebhookState,
+}
+
+// Messages browser clients send over a WebSocket to acknowledge chunks or to close the stream
+// Chunk ids can carry the nack and term prefixes of the gRPC api
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+struct JsonStreamRequest {
+    #[serde(default)]
+    ack_chunk_ids: Vec<String>,
+    #[serde(default)]
+    close: bool,
+}
+
+impl From<NotificationStreamResponse> for JsonNotification {
+    fn from(value: NotificationStreamResponse) -> Self {
+        let message = value.message.unwrap_or_default();
+        let timestamp = value.timestamp.and_then(|x| {
+            Utc.timestamp_opt(x.seconds, x.nanos as u32)
+                .single()
+                .map(|x| x.to_rfc3339())
+        });
+
+        return JsonNotification {
+            resource: message.resource().as_str_name().to_string(),
+            resource_id: message.resource_id.clone(),
+            updated_type: message.updated_type().as_str_name().to_string(),
+            sequence: value.sequence,
+            timestamp,
+        };
+    }
+}
+
+impl From<RegisteredWebhook> for JsonWebhook {
+    fn from(value: RegisteredWebhook) -> Self {
+        return JsonWebhook {
+            url: value.webhook.url.to_string(),
+            state: value.state,
+        };
+    }
+}
+
+impl From<DeadLetter> for JsonDeadLetter {
+    fn from(value: DeadLetter) -> Self {
+        let notification = match EventNotificationMessage::decode(value.payload) {
+            Ok(message) => Some(JsonNotification::from(NotificationStreamResponse {
+                message: Some(message),
+                sequence: value.sequence,
+                timestamp: Some(prost_types::Timestamp {
+                    seconds: value.timestamp.timestamp(),
+                    nanos: value.timestamp.timestamp_subsec_nanos() as i32,
+                }),
+            })),
+            Err(err) => {
+                error!("{}", err);
+                None
+            }
+        };
+
+        return JsonDeadLetter {
+            id: value.id,
+            subject: value.subject,
+            deliveries: value.deliveries,
+            dead_lettered_at: value.dead_lettered_at.to_rfc3339(),
+            notification,
+        };
+    }
+}
+
+impl From<ReadStreamGroupMessagesResponse> for GatewayMessage {
+    fn from(value: ReadStreamGroupMessagesResponse) -> Self {
+        // Only heartbeats and unknown chunk reports come without notifications
+        if value.notification.is_empty() {
+            return match value.ack_chunk_id.strip_prefix(UNKNOWN_CHUNK_PREFIX) {
+                Some(chunk_id) => GatewayMessage::UnknownChunk {
+                    ack_chunk_id: chunk_id.to_string(),
+                },
+                None => GatewayMessage::Heartbeat,
+            };
+        }
+
+        return GatewayMessage::Notifications {
+            ack_chunk_id: value.ack_chunk_id,
+            notifications: value
+                .notification
+                .into_iter()
+                .map(JsonNotification::from)
+                .collect(),
+        };
+    }
+}
+
+impl From<Status> for GatewayMessage {
+    fn from(value: Status) -> Self {
+        return GatewayMessage::Error {
+            code: value.code() as i32,
+            message: value.message().to_string(),
+        };
+    }
+}
+
+// Maps the status of a failed read stream request to the http status of the response
+fn http_status(status: &Status) -> StatusCode {
+    return match status.code() {
+        Code::InvalidArgument => StatusCode::BAD_REQUEST,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+}
+
+fn error_response(status: Status) -> Response {
+    return (http_status(&status), Json(GatewayMessage::from(status))).into_response();
+}
+
+fn init_request(stream_group_id: String) -> ReadStreamGroupMessagesRequest {
+    return ReadStreamGroupMessagesRequest {
+        close: false,
+        stream_action: Some(StreamAction::Init(NotificationStreamInit {
+            stream_group_id,
+        })),
+    };
+}
+
+// Builds the read stream request with the token and options of the http request
+#[allow(clippy::result_large_err)]
+fn read_request<T>(
+    input: T,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+) -> Result<Request<T>, Status> {
+    let token = match headers.get(TOKEN_METADATA_NAME) {
+        Some(value) => match value.to_str() {
+            Ok(value) => value.to_string(),
+            Err(err) => {
+                error!("{}", err);
+                return Err(Status::invalid_argument("could not read token"));
+            }
+        },
+        None => match query.get(TOKEN_QUERY_NAME) {
+            Some(value) => value.clone(),
+            None => {
+                return Err(Status::unauthenticated(
+                    "authentication header or token parameter required and was not found",
+                ))
+            }
+        },
+    };
+
+    let mut request = Request::new(input);
+    let metadata_values = std::iter::once((TOKEN_METADATA_NAME, &token)).chain(
+        FORWARDED_QUERY_NAMES
+            .iter()
+            .filter_map(|name| query.get(*name).map(|value| (*name, value))),
+    );
+    for (name, value) in metadata_values {
+        let value = match value.parse::<MetadataValue<_>>() {
+            Ok(value) => value,
+            Err(err) => {
+                error!("{}", err);
+                return Err(Status::invalid_argument(format!("could not read {}", name)));
+            }
+        };
+        request.metadata_mut().insert(name, value);
+    }
+
+    return Ok(request);
+}
+
+// Reads the token of a request to the dead letter api, other query parameters are ignored
+#[allow(clippy::result_large_err)]
+fn request_metadata(
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+) -> Result<MetadataMap, Status> {
+    let (metadata, _, _) = read_request((), headers, query)?.into_parts();
+    return Ok(metadata);
+}
+
+// HTTP gateway for clients that can not use the bidirectional gRPC stream, e.g. browsers
+// Stream groups are read as server-sent events or over a WebSocket, both forward to the public event server
+// The dead letters of stream groups are managed with the JSON api of the gateway
+#[derive(Clone)]
+pub struct HttpGateway {
+    pub public_event_client: UpdateNotificationServiceClient<Channel>,
+    pub public_server: Arc<PublicServer>,
+}
+
+impl HttpGateway {
+    pub fn new(
+        public_event_client: UpdateNotificationServiceClient<Channel>,
+        public_server: Arc<PublicServer>,
+    ) -> Self {
+        return HttpGateway {
+            public_event_client,
+            public_server,
+        };
+    }
+
+    pub fn router(self) -> Router {
+        return Router::new()
+            .route("/v1/stream-groups/:id/sse", get(read_sse))
+            .route("/v1/stream-groups/:id/ws", get(read_websocket))
+            .route(
+                "/v1/stream-groups/:id/dead-letters",
+                get(list_dead_letters).delete(purge_dead_letters),
+            )
+            .route(
+                "/v1/stream-groups/:id/dead-letters/:dead_letter_id",
+                get(get_dead_letter).delete(purge_dead_letter),
+            )
+            .route(
+                "/v1/stream-groups/:id/dead-letters/:dead_letter_id/requeue",
+                post(requeue_dead_letter),
+            )
+            .route("/v1/stream-groups/:id/webhook", get(get_webhook))
+            .route("/v1/stream-groups/:id/webhook/enable", post(enable_webhook))
+            .with_state(self);
+    }
+
+    pub async fn serve(self, host: SocketAddr) -> Result<(), hyper::Error> {
+        return axum::Server::bind(&host)
+            .serve(self.router().into_make_service())
+            .await;
+    }
+}
+
+// Server-sent events can not carry acknowledgements back, so the stream is read in auto ack mode
+async fn read_sse(
+    State(gateway): State<HttpGateway>,
+    Path(stream_group_id): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let input = tokio_stream::iter(vec![init_request(stream_group_id)]);
+    let mut request = match read_request(input, &headers, &query) {
+        Ok(value) => value,
+        Err(status) => return error_response(status),
+    };
+    request
+        .metadata_mut()
+        .insert(ACK_MODE_METADATA_NAME, MetadataValue::from_static("auto"));
+
+    let output = match gateway
+        .public_event_client
+        .clone()
+        .read_stream_group_messages(request)
+        .await
+    {
+        Ok(value) => value.into_inner(),
+        Err(status) => return error_response(status),
+    };
+
+    let events = output.map(|response| {
+        let message = match response {
+            Ok(value) => GatewayMessage::from(value),
+            Err(status) => GatewayMessage::from(status),
+        };
+        return Event::default().json_data(message);
+    });
+
+    return Sse::new(events).into_response();
+}
+
+// The stream is opened before the upgrade, so errors like a failed authorization are regular http errors
+async fn read_websocket(
+    State(gateway): State<HttpGateway>,
+    Path(stream_group_id): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    websocket: WebSocketUpgrade,
+) -> Response {
+    let (input_sender, input_recv) = async_channel::unbounded();
+    let request = match read_request(input_recv, &headers, &query) {
+        Ok(value) => value,
+        Err(status) => return error_response(status),
+    };
+    if let Err(err) = input_sender.try_send(init_request(stream_group_id)) {
+        error!("{}", err);
+        return error_response(Status::internal("could not initiate stream"));
+    }
+
+    let output = match gateway
+        .public_event_client
+        .clone()
+        .read_stream_group_messages(request)
+        .await
+    {
+        Ok(value) => value.into_inner(),
+        Err(status) => return error_response(status),
+    };
+
+    return websocket.on_upgrade(move |socket| forward_websocket(socket, input_sender, output));
+}
+
+async fn list_dead_letters(
+    State(gateway): State<HttpGateway>,
+    Path(stream_group_id): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let metadata = match request_metadata(&headers, &query) {
+        Ok(value) => value,
+        Err(status) => return error_response(status),
+    };
+
+    return match gateway
+        .public_server
+        .list_dead_letters(&metadata, &stream_group_id)
+        .await
+    {
+        Ok(value) => Json(JsonDeadLetters {
+            dead_letters: value.into_iter().map(JsonDeadLetter::from).collect(),
+        })
+        .into_response(),
+        Err(status) => error_response(status),
+    };
+}
+
+async fn get_dead_letter(
+    State(gateway): State<HttpGateway>,
+    Path((stream_group_id, dead_letter_id)): Path<(String, u64)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let metadata = match request_metadata(&headers, &query) {
+        Ok(value) => value,
+        Err(status) => return error_response(status),
+    };
+
+    return match gateway
+        .public_server
+        .get_dead_letter(&metadata, &stream_group_id, dead_letter_id)
+        .await
+    {
+        Ok(value) => Json(JsonDeadLetter::from(value)).into_response(),
+        Err(status) => error_response(status),
+    };
+}
+
+async fn requeue_dead_letter(
+    State(gateway): State<HttpGateway>,
+    Path((stream_group_id, dead_letter_id)): Path<(String, u64)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let metadata = match request_metadata(&headers, &query) {
+        Ok(value) => value,
+        Err(status) => return error_response(status),
+    };
+
+    return match gateway
+        .public_server
+        .requeue_dead_letter(&metadata, &stream_group_id, dead_letter_id)
+        .await
+    {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(status) => error_response(status),
+    };
+}
+
+async fn purge_dead_letters(
+    State(gateway): State<HttpGateway>,
+    Path(stream_group_id): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    return purge(gateway, stream_group_id, None, headers, query).await;
+}
+
+async fn purge_dead_letter(
+    State(gateway): State<HttpGateway>,
+    Path((stream_group_id, dead_letter_id)): Path<(String, u64)>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    return purge(
+        gateway,
+        stream_group_id,
+        Some(dead_letter_id),
+        headers,
+        query,
+    )
+    .await;
+}
+
+async fn purge(
+    gateway: HttpGateway,
+    stream_group_id: String,
+    dead_letter_id: Option<u64>,
+    headers: HeaderMap,
+    query: HashMap<String, String>,
+) -> Response {
+    let metadata = match request_metadata(&headers, &query) {
+        Ok(value) => value,
+        Err(status) => return error_response(status),
+    };
+
+    return match gateway
+        .public_server
+        .purge_dead_letters(&metadata, &stream_group_id, dead_letter_id)
+        .await
+    {
+        Ok(purged) => Json(JsonPurgeResponse { purged }).into_response(),
+        Err(status) => error_response(status),
+    };
+}
+
+async fn get_webhook(
+    State(gateway): State<HttpGateway>,
+    Path(stream_group_id): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let metadata = match request_metadata(&headers, &query) {
+        Ok(value) => value,
+        Err(status) => return error_response(status),
+    };
+
+    return match gateway
+        .public_server
+        .get_webhook(&metadata, &stream_group_id)
+        .await
+    {
+        Ok(value) => Json(JsonWebhook::from(value)).into_response(),
+        Err(status) => error_response(status),
+    };
+}
+
+async fn enable_webhook(
+    State(gateway): State<HttpGateway>,
+    Path(stream_group_id): Path<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    let metadata = match request_metadata(&headers, &query) {
+        Ok(value) => value,
+        Err(status) => return error_response(status),
+    };
+
+    return match gateway
+        .public_server
+        .enable_webhook(&metadata, &stream_group_id)
+        .await
+    {
+        Ok(value) => Json(JsonWebhook::from(value)).into_response(),
+        Err(status) => error_response(status),
+    };
+}
+
+// Forwards the read stream to the WebSocket and the requests of the client back to the read stream
+// The read stream ends as soon as the WebSocket is closed and vice versa
+async fn forward_websocket(
+    socket: WebSocket,
+    input_sender: async_channel::Sender<ReadStreamGroupMessagesRequest>,
+    mut output: Streaming<ReadStreamGroupMessagesResponse>,
+) {
+    let (mut socket_sender, mut socket_recv) = socket.split();
+    let (err_sender, err_recv) = async_channel::bounded(1);
+
+    let input = tokio::spawn(async move {
+        while let Some(message) = socket_recv.next().await {
+            let text = match message {
+                Ok(Message::Text(value)) => value,
+                Ok(Message::Binary(_)) => {
+                    let _ = err_sender
+                        .send(Status::invalid_argument("only text messages are supported"))
+                        .await;
+                    break;
+                }
+                Ok(Message::Close(_)) => break,
+                Ok(_) => continue,
+                Err(err) => {
+                    error!("{}", err);
+                    break;
+                }
+            };
+
+            let request = match serde_json::from_str::<JsonStreamRequest>(&text) {
+                Ok(value) => value,
+                Err(err) => {
+                    let _ = err_sender
+                        .send(Status::invalid_argument(format!(
+                            "could not read stream request: {}",
+                            err
+                        )))
+                        .await;
+                    break;
+                }
+            };
+
+            let request = ReadStreamGroupMessagesRequest {
+                close: request.close,
+                stream_action: Some(StreamAction::Ack(NotficationStreamAck {
+                    ack_chunk_id: request.ack_chunk_ids,
+                })),
+            };
+            if input_sender.send(request).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    loop {
+        let message = tokio::select! {
+            value = output.next() => match value {
+                Some(Ok(value)) => GatewayMessage::from(value),
+                Some(Err(status)) => GatewayMessage::from(status),
+                None => break,
+            },
+            value = err_recv.recv() => match value {
+                Ok(status) => GatewayMessage::from(status),
+                // The client closed the WebSocket
+                Err(_) => break,
+            },
+        };
+        let is_error = matches!(message, GatewayMessage::Error { .. });
+
+        let text = match serde_json::to_string(&message) {
+            Ok(value) => value,
+            Err(err) => {
+                error!("{}", err);
+                break;
+            }
+        };
+        if let Err(err) = socket_sender.send(Message::Text(text)).await {
+            error!("{}", err);
+            break;
+        }
+        if is_error {
+            break;
+        }
+    }
+
+    input.abort();
+    if let Err(err) = socket_sender.close().await {
+        error!("{}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use aruna_rust_api::api::notification::services::v1::{
+        EventNotificationMessage, EventType, NotificationStreamResponse,
+        ReadStreamGroupMessagesResponse,
+    };
+    use aruna_rust_api::api::storage::models::v1::ResourceType;
+    use axum::http::HeaderMap;
+    use tonic::Status;
+
+    use super::{read_request, GatewayMessage, JsonNotification, JsonStreamRequest};
+
+    #[test]
+    fn test_gateway_message_json() {
+        let response = ReadStreamGroupMessagesResponse {
+            notification: vec![NotificationStreamResponse {
+                message: Some(EventNotificationMessage {
+                    resource: ResourceType::Object as i32,
+                    resource_id: "object_id".to_string(),
+                    updated_type: EventType::Created as i32,
+                }),
+                sequence: 3,
+                timestamp: Some(prost_types::Timestamp {
+                    seconds: 0,
+                    nanos: 0,
+                }),
+            }],
+            ack_chunk_id: "chunk_id".to_string(),
+        };
+        assert_eq!(
+            GatewayMessage::from(response),
+            GatewayMessage::Notifications {
+                ack_chunk_id: "chunk_id".to_string(),
+                notifications: vec![JsonNotification {
+                    resource: "RESOURCE_TYPE_OBJECT".to_string(),
+                    resource_id: "object_id".to_string(),
+                    updated_type: "EVENT_TYPE_CREATED".to_string(),
+                    sequence: 3,
+                    timestamp: Some("1970-01-01T00:00:00+00:00".to_string()),
+                }],
+            }
+        );
+
+        assert_eq!(
+            serde_json::to_string(&GatewayMessage::from(
+                ReadStreamGroupMessagesResponse::default()
+            ))
+            .unwrap(),
+            r#"{"type":"heartbeat"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&GatewayMessage::from(ReadStreamGroupMessagesResponse {
+                notification: Vec::new(),
+                ack_chunk_id: "unknown:chunk_id".to_string(),
+            }))
+            .unwrap(),
+            r#"{"type":"unknown_chunk","ack_chunk_id":"chunk_id"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&GatewayMessage::from(Status::not_found("missing"))).unwrap(),
+            r#"{"type":"error","code":5,"message":"missing"}"#
+        );
+    }
+
+    #[test]
+    fn test_stream_request_json() {
+        assert_eq!(
+            serde_json::from_str::<JsonStreamRequest>(r#"{"ack_chunk_ids":["a","nack:b"]}"#)
+                .unwrap(),
+            JsonStreamRequest {
+                ack_chunk_ids: vec!["a".to_string(), "nack:b".to_string()],
+                close: false,
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<JsonStreamRequest>(r#"{"close":true}"#).unwrap(),
+            JsonStreamRequest {
+                ack_chunk_ids: Vec::new(),
+                close: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_read_request() {
+        let mut query = HashMap::new();
+        assert_eq!(
+            read_request((), &HeaderMap::new(), &query)
+                .unwrap_err()
+                .code(),
+            tonic::Code::Unauthenticated
+        );
+
+        query.insert("token".to_string(), "secret".to_string());
+        query.insert("max-batch-size".to_string(), "10".to_string());
+        query.insert("unknown".to_string(), "value".to_string());
+        let request = read_request((), &HeaderMap::new(), &query).unwrap();
+        assert_eq!(request.metadata().get("api-token").unwrap(), "secret");
+        assert_eq!(request.metadata().get("max-batch-size").unwrap(), "10");
+        assert!(request.metadata().get("unknown").is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("api-token", "header_secret".parse().unwrap());
+        let request = read_request((), &headers, &query).unwrap();
+        assert_eq!(
+            request.metadata().get("api-token").unwrap(),
+            "header_secret"
+        );
+    }
+}
@@ -1,12 +1,18 @@
 use aruna_rust_api::api::internal::v1::{internal_event_emitter_service_server, EmitEventResponse};
 use async_trait::async_trait;
-use tonic::{Response, Status};
+use prost::Message;
+use sha2::{Digest, Sha256};
+use tonic::metadata::MetadataValue;
+use tonic::Response;
 
 use log::error;
 
 use crate::stream_handler::handler::EventHandler;
+use crate::utils::utils::NatsIOUtils;
 
-use super::server::INTERNAL_AUTHZ_TOKEN;
+use super::server::{
+    DUPLICATE_EVENT_METADATA_NAME, IDEMPOTENCY_KEY_METADATA_NAME, INTERNAL_AUTHZ_TOKEN,
+};
 
 pub struct InternalServer {
     pub event_handler: Box<dyn EventHandler + Send + Sync>,
@@ -35,6 +41,17 @@ impl internal_event_emitter_service_server::InternalEventEmitterService for Inte
                 ))
             }
         };
+        let idempotency_key = match metadata.get(IDEMPOTENCY_KEY_METADATA_NAME) {
+            Some(value) => match value.to_str() {
+                Ok(value) if !value.is_empty() => Some(value.to_string()),
+                _ => {
+                    return Err(tonic::Status::invalid_argument(
+                        "could not read idempotency key",
+                    ));
+                }
+            },
+            None => None,
+        };
         let inner_request = request.into_inner();
 
         if token != self.internal_token {
@@ -44,24 +61,54 @@ impl internal_event_emitter_service_server::InternalEventEmitterService for Inte
             ));
         };
 
-        let resource_type = inner_request.event_resource().clone();
+        // Emitters without their own key get one derived from the resource, event type and relations,
+        // identical requests within the deduplication window are then treated as retries of the same event
+        let idempotency_key = match idempotency_key {
+            Some(key) => key,
+            None => hex::encode(Sha256::digest(inner_request.encode_to_vec())),
+        };
+
+        let resource_type = inner_request.event_resource();
         let resource_id = inner_request.resource_id.clone();
-        let event_type = inner_request.event_type().clone();
+        let event_type = inner_request.event_type();
 
+        // Validates the ids of all relations first, so that a malformed relation does not leave the event partially registered
+        for relation in &inner_request.relations {
+            if let Err(err) =
+                NatsIOUtils::event_subjects(resource_type, resource_id.clone(), relation)
+            {
+                error!("{}", err);
+                return Err(err);
+            }
+        }
+
+        let mut duplicate = !inner_request.relations.is_empty();
         for relation in inner_request.relations {
             match self
                 .event_handler
-                .register_event(resource_type, resource_id.clone(), event_type, &relation)
+                .register_event(
+                    resource_type,
+                    resource_id.clone(),
+                    event_type,
+                    &relation,
+                    &idempotency_key,
+                )
                 .await
             {
-                Ok(_) => {}
+                Ok(value) => duplicate &= value,
                 Err(err) => {
                     error!("{}", err);
-                    return Err(Status::internal("could not emit event"));
+                    return Err(err);
                 }
             };
         }
 
-        return Ok(Response::new(EmitEventResponse {}));
+        let mut response = Response::new(EmitEventResponse {});
+        response.metadata_mut().insert(
+            DUPLICATE_EVENT_METADATA_NAME,
+            MetadataValue::from_static(if duplicate { "true" } else { "false" }),
+        );
+
+        return Ok(response);
     }
 }
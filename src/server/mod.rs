@@ -1,3 +1,5 @@
+pub mod http_gateway;
 pub mod internal_event_server;
 pub mod public_event_server;
 pub mod server;
+pub mod webhook;
@@ -1,43 +1,922 @@
 use aruna_rust_api::api::internal::v1::internal_authorize_service_client::InternalAuthorizeServiceClient;
-use aruna_rust_api::api::storage::models::v1::ResourceAction;
+use aruna_rust_api::api::storage::models::v1::{ResourceAction, ResourceType};
 
 use aruna_rust_api::api::storage::services::v1::resource_info_service_client::ResourceInfoServiceClient;
 use aruna_rust_api::api::storage::services::v1::GetResourceHierarchyRequest;
 use futures::lock::Mutex;
-use log::error;
+use log::{error, info, warn};
 use std::collections::HashMap;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
 
 use aruna_rust_api::api::internal::v1::internal_event_service_client::InternalEventServiceClient;
 use aruna_rust_api::api::internal::v1::{
-    AuthorizeRequest, CreateStreamGroupRequest, GetStreamGroupRequest,
+    AuthorizeRequest, CreateStreamGroupRequest, DeleteStreamGroupRequest, GetStreamGroupRequest,
+    StreamGroup,
 };
+use aruna_rust_api::api::notification::services::v1::create_event_streaming_group_request::StreamType;
 use aruna_rust_api::api::notification::services::v1::read_stream_group_messages_request::StreamAction;
 use aruna_rust_api::api::notification::services::v1::{
     update_notification_service_server, CreateEventStreamingGroupRequest,
     CreateEventStreamingGroupResponse, DeleteEventStreamingGroupRequest,
-    DeleteEventStreamingGroupResponse, EventNotificationMessage, EventType,
+    DeleteEventStreamingGroupResponse, EventNotificationMessage, EventType, NotficationStreamAck,
     NotificationStreamResponse, ReadStreamGroupMessagesResponse,
 };
-use async_nats::jetstream;
 use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
 use futures::{Stream, StreamExt};
 use prost::Message;
+use tonic::metadata::MetadataMap;
 use tonic::transport::Channel;
 use tonic::{Request, Response, Status};
 
-use crate::stream_handler::handler::EventHandler;
+use crate::stream_handler::handler::{
+    log_redeliveries, DeadLetter, EventHandler, EventStreamMessage, FetchOptions, StoredWebhook,
+    StreamStartPosition,
+};
+use crate::utils::utils::NatsIOUtils;
+
+use super::webhook::{
+    parse_webhook, DeliveryEnd, RegisteredWebhook, WebhookDelivery, WebhookOptions, WebhookState,
+    Webhooks,
+};
+
+use super::server::{
+    ACK_MODE_METADATA_NAME, EPHEMERAL_METADATA_NAME, EVENT_TYPES_METADATA_NAME,
+    FLOW_CONTROL_METADATA_NAME, HEARTBEAT_INTERVAL_METADATA_NAME, INACTIVITY_TTL_METADATA_NAME,
+    MAX_BATCH_BYTES_METADATA_NAME, MAX_BATCH_SIZE_METADATA_NAME, MAX_DELIVER_METADATA_NAME,
+    MAX_IN_FLIGHT_CHUNKS_METADATA_NAME, MAX_WAIT_METADATA_NAME, NACK_CHUNK_PREFIX,
+    STREAM_START_METADATA_NAME, TERMINATE_CHUNK_PREFIX, TOKEN_METADATA_NAME, UNKNOWN_CHUNK_PREFIX,
+};
+
+// Upper limits for the fetch options a client can request for a read stream
+// Chunks have to stay below the default 4MiB message size limit of gRPC
+const MAX_BATCH_SIZE_LIMIT: usize = 1000;
+const MAX_BATCH_BYTES_LIMIT: usize = 3 * 1024 * 1024;
+const MAX_WAIT_LIMIT: Duration = Duration::from_secs(30);
+
+// Upper limit for the deliveries of a message before it becomes a dead letter
+const MAX_DELIVER_LIMIT: u64 = 1000;
+
+// Stream groups with an inactivity ttl expire once they were not read for that long
+// Ephemeral stream groups use the default ttl
+const DEFAULT_EPHEMERAL_TTL: Duration = Duration::from_secs(300);
+const MAX_INACTIVITY_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+// Interval in which stream groups with an inactivity ttl are checked for their expiry
+pub const STREAM_GROUP_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// Heartbeats are responses without notifications and ack chunk id
+// They are sent when a read stream had nothing to send for the heartbeat interval
+const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+const MAX_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(300);
+
+// Maximum number of chunks a read stream waits to be acknowledged, also the default in flight limit
+// Fetching new messages pauses while the limit is reached
+pub const MAX_UNACKED_CHUNKS: usize = 100;
+const UNACKED_CHUNKS_PAUSE: Duration = Duration::from_millis(100);
+
+// A chunk sent to the client that waits for its acknowledgement
+// After the ack wait the messages are redelivered by the event system anyway, the chunk expires then
+// Expired chunks are kept without their messages for another ack wait, so late acks can be told apart from unknown ids
+struct AckChunk {
+    messages: Arc<Vec<Box<dyn EventStreamMessage + Send + Sync>>>,
+    expires_at: Instant,
+    expired: bool,
+}
+
+type AckChunks = Arc<Mutex<HashMap<String, AckChunk>>>;
+
+// Releases the chunks of a read stream as soon as its output stream is dropped
+// The messages of unacknowledged chunks and fetched messages that were not sent yet are handed back for redelivery
+struct AckChunksGuard {
+    ack_chunks: AckChunks,
+    unsent: Vec<Box<dyn EventStreamMessage + Send + Sync>>,
+}
+
+impl Drop for AckChunksGuard {
+    fn drop(&mut self) {
+        let ack_chunks = self.ack_chunks.clone();
+        let unsent = std::mem::take(&mut self.unsent);
+        // Nacks have to be awaited, the drop can not do that itself
+        tokio::spawn(async move {
+            let chunks: Vec<AckChunk> = ack_chunks
+                .lock()
+                .await
+                .drain()
+                .map(|(_, chunk)| chunk)
+                .collect();
+            let chunk_msgs = chunks.iter().flat_map(|chunk| chunk.messages.iter());
+            for msg in unsent.iter().chain(chunk_msgs) {
+                if let Err(err) = msg.nack(None).await {
+                    error!("{}", err);
+                }
+            }
+        });
+    }
+}
+
+// How the messages of a read stream are acknowledged
+// In auto mode the client only sends the init request and every message is acknowledged as soon as
+// its chunk was handed to the transport, messages that are lost on the way are never redelivered (at-most-once)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AckMode {
+    Manual,
+    Auto,
+}
+
+// Flow control of a read stream
+// In adaptive mode the in flight window starts with a single chunk, grows by one chunk for each
+// acknowledged chunk and is halved when chunks are nacked or expire, but never exceeds the maximum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FlowControl {
+    max_in_flight_chunks: usize,
+    adaptive: bool,
+}
 
-use super::server::TOKEN_METADATA_NAME;
+impl FlowControl {
+    fn initial_window(&self) -> usize {
+        if self.adaptive {
+            return 1;
+        }
+        return self.max_in_flight_chunks;
+    }
+
+    fn grow(&self, window: &AtomicUsize) {
+        if self.adaptive {
+            let _ = window.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                Some((x + 1).min(self.max_in_flight_chunks))
+            });
+        }
+    }
+
+    fn shrink(&self, window: &AtomicUsize) {
+        if self.adaptive {
+            let _ = window.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                Some((x / 2).max(1))
+            });
+        }
+    }
+}
 
 // Server to handle the outgoing notifications for users
 pub struct PublicServer {
     pub internal_events_client: InternalEventServiceClient<Channel>,
     pub internal_authz_client: InternalAuthorizeServiceClient<Channel>,
     pub resource_client: ResourceInfoServiceClient<Channel>,
-    pub event_handler: Box<dyn EventHandler + Send + Sync>,
+    // Shared with the webhook deliveries, which store the state of their webhook
+    pub event_handler: Arc<dyn EventHandler + Send + Sync>,
+    // Senders to terminate the active read streams of each stream group, used when a stream group is deleted
+    pub active_streams: Arc<Mutex<HashMap<String, Vec<async_channel::Sender<Status>>>>>,
+    pub webhook_options: WebhookOptions,
+    pub webhooks: Webhooks,
+    // Token of this service for the internal event service, used to delete expired stream groups
+    pub internal_token: String,
+}
+
+impl PublicServer {
+    // Registers a stream or webhook delivery so it can be terminated when the stream group is deleted
+    // Senders of streams that already ended are removed on the way
+    async fn register_active_stream(
+        &self,
+        stream_group_id: String,
+    ) -> async_channel::Receiver<Status> {
+        let (terminate_sender, terminate_recv) = async_channel::bounded(1);
+        let mut active_streams = self.active_streams.lock().await;
+        let senders = active_streams.entry(stream_group_id).or_default();
+        senders.retain(|sender| !sender.is_closed());
+        senders.push(terminate_sender);
+        return terminate_recv;
+    }
+
+    // Ends all read streams and webhook deliveries of a stream group with the given status
+    async fn terminate_active_streams(&self, stream_group_id: &str, status: Status) {
+        if let Some(senders) = self.active_streams.lock().await.remove(stream_group_id) {
+            for sender in senders {
+                // Streams that already ended have dropped their receiver, the error can be ignored
+                let _ = sender.try_send(status.clone());
+            }
+        }
+    }
+
+    // Stores a webhook with its stream group, so its delivery can be resumed by every instance
+    async fn store_webhook(
+        &self,
+        stream_group_id: &str,
+        registered_webhook: &RegisteredWebhook,
+    ) -> Result<(), Status> {
+        return match self
+            .event_handler
+            .store_webhook(
+                stream_group_id.to_string(),
+                &StoredWebhook::from(registered_webhook),
+            )
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                error!("{}", err);
+                Err(Status::internal("could not store webhook"))
+            }
+        };
+    }
+
+    // Reads the webhook stored with a stream group
+    async fn stored_webhook(&self, stream_group_id: &str) -> Result<RegisteredWebhook, Status> {
+        let stored_webhook = match self
+            .event_handler
+            .get_webhook(stream_group_id.to_string())
+            .await
+        {
+            Ok(Some(value)) => value,
+            Ok(None) => return Err(Status::not_found("stream group has no webhook")),
+            Err(err) => {
+                error!("{}", err);
+                return Err(Status::internal("could not read webhook"));
+            }
+        };
+
+        return match RegisteredWebhook::try_from(stored_webhook) {
+            Ok(value) => Ok(value),
+            Err(err) => {
+                error!("{}", err);
+                Err(Status::internal("could not read webhook"))
+            }
+        };
+    }
+
+    // Starts the delivery of a registered webhook
+    // The webhook is stored as disabled once its delivery gives up and forgotten once its stream group is gone
+    async fn start_webhook_delivery(
+        &self,
+        stream_group_id: String,
+        registered_webhook: RegisteredWebhook,
+    ) -> Result<(), Status> {
+        let stream_handler = match self
+            .event_handler
+            .create_event_stream_handler(stream_group_id.clone(), registered_webhook.fetch_options)
+            .await
+        {
+            Ok(value) => value,
+            Err(err) => {
+                error!("{}", err);
+                return Err(tonic::Status::internal(
+                    "could not create stream group handler",
+                ));
+            }
+        };
+        let terminate_recv = self.register_active_stream(stream_group_id.clone()).await;
+        let delivery = WebhookDelivery::new(
+            stream_group_id.clone(),
+            registered_webhook.webhook,
+            stream_handler,
+            self.webhook_options,
+            terminate_recv,
+        );
+
+        let webhooks = self.webhooks.clone();
+        let event_handler = self.event_handler.clone();
+        tokio::spawn(async move {
+            match delivery.run().await {
+                DeliveryEnd::Disabled => {
+                    let registered_webhook = match webhooks.lock().await.get_mut(&stream_group_id) {
+                        Some(value) => {
+                            value.state = WebhookState::Disabled;
+                            value.clone()
+                        }
+                        None => return,
+                    };
+                    // Disabled webhooks are not resumed at startup until they are enabled again
+                    if let Err(err) = event_handler
+                        .store_webhook(stream_group_id, &StoredWebhook::from(&registered_webhook))
+                        .await
+                    {
+                        error!("{}", err);
+                    }
+                }
+                DeliveryEnd::Terminated => {}
+                DeliveryEnd::StreamGroupNotFound => {
+                    webhooks.lock().await.remove(&stream_group_id);
+                }
+            }
+        });
+
+        return Ok(());
+    }
+
+    // Starts the deliveries of all active webhooks stored with the stream groups, called once at startup
+    // Instances sharing the underlaying system all resume the same webhooks,
+    // their deliveries read from the same stream group, so each message is still delivered once
+    pub async fn resume_webhooks(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for (stream_group_id, stored_webhook) in self.event_handler.list_webhooks().await? {
+            let registered_webhook = match RegisteredWebhook::try_from(stored_webhook) {
+                Ok(value) => value,
+                Err(err) => {
+                    error!("webhook of stream group {}: {}", stream_group_id, err);
+                    continue;
+                }
+            };
+            if registered_webhook.state == WebhookState::Disabled {
+                continue;
+            }
+
+            self.webhooks
+                .lock()
+                .await
+                .insert(stream_group_id.clone(), registered_webhook.clone());
+            if let Err(status) = self
+                .start_webhook_delivery(stream_group_id.clone(), registered_webhook)
+                .await
+            {
+                error!("webhook of stream group {}: {}", stream_group_id, status);
+                self.webhooks.lock().await.remove(&stream_group_id);
+                continue;
+            }
+            info!("webhook of stream group {} resumed", stream_group_id);
+        }
+
+        return Ok(());
+    }
+
+    // Removes a stream group whose creation failed after its internal record was created
+    // It can be partially created in the underlaying system, so it is removed there as well
+    // Errors are only logged, the creation already failed
+    async fn rollback_stream_group(&self, stream_group_id: &str) {
+        if let Err(err) = self
+            .event_handler
+            .delete_stream_group(stream_group_id.to_string())
+            .await
+        {
+            error!("{}", err);
+        }
+        if let Err(err) = self
+            .internal_events_client
+            .clone()
+            .delete_stream_group(DeleteStreamGroupRequest {
+                token: self.internal_token.clone(),
+                stream_group_id: stream_group_id.to_string(),
+            })
+            .await
+        {
+            error!("{}", err);
+        }
+    }
+
+    // Removes stream groups that expired in the underlaying system from the internal event service
+    // The underlaying system reports each expiry once, deletions that failed are retried on the next check
+    // Runs until the server stops
+    pub async fn expire_stream_groups(self: Arc<Self>, check_interval: Duration) {
+        let mut interval = tokio::time::interval(check_interval);
+        let mut retries: Vec<String> = Vec::new();
+
+        loop {
+            interval.tick().await;
+
+            let mut stream_group_ids = std::mem::take(&mut retries);
+            match self.event_handler.expired_stream_groups().await {
+                Ok(value) => stream_group_ids.extend(value),
+                Err(err) => error!("{}", err),
+            }
+
+            for stream_group_id in stream_group_ids {
+                // Removes what the underlaying system keeps beyond the expiry, e.g. dead letters
+                if let Err(err) = self
+                    .event_handler
+                    .delete_stream_group(stream_group_id.clone())
+                    .await
+                {
+                    error!("{}", err);
+                    retries.push(stream_group_id);
+                    continue;
+                }
+
+                // Stream groups deleted through the api are already gone from the internal event service
+                match self
+                    .internal_events_client
+                    .clone()
+                    .delete_stream_group(DeleteStreamGroupRequest {
+                        token: self.internal_token.clone(),
+                        stream_group_id: stream_group_id.clone(),
+                    })
+                    .await
+                {
+                    Ok(_) => {}
+                    Err(status) if status.code() == tonic::Code::NotFound => {}
+                    Err(err) => {
+                        error!("{}", err);
+                        retries.push(stream_group_id);
+                        continue;
+                    }
+                }
+
+                self.webhooks.lock().await.remove(&stream_group_id);
+                self.terminate_active_streams(
+                    &stream_group_id,
+                    Status::not_found("stream group expired"),
+                )
+                .await;
+                info!("stream group {} expired", stream_group_id);
+            }
+        }
+    }
+
+    // Reads a stream group from the internal event service and checks that the caller can perform the action on its resource
+    async fn authorize_stream_group(
+        &self,
+        metadata: &MetadataMap,
+        stream_group_id: &str,
+        resource_action: ResourceAction,
+    ) -> Result<StreamGroup, Status> {
+        let token = match metadata.get(TOKEN_METADATA_NAME) {
+            Some(value) => match value.to_str() {
+                Ok(value) => value.to_string(),
+                Err(err) => {
+                    error!("{}", err);
+                    return Err(tonic::Status::invalid_argument("could not read token"));
+                }
+            },
+            None => {
+                return Err(tonic::Status::unauthenticated(
+                    "authentication header required and was not found",
+                ))
+            }
+        };
+
+        let stream_group = match self
+            .internal_events_client
+            .clone()
+            .get_stream_group(GetStreamGroupRequest {
+                stream_group_id: stream_group_id.to_string(),
+                token,
+            })
+            .await
+        {
+            Ok(value) => match value.into_inner().stream_group {
+                Some(value) => value,
+                None => return Err(Status::internal("internal error reading stream group")),
+            },
+            Err(err) => {
+                error!("{}", err);
+                if err.code() == tonic::Code::NotFound || err.code() == tonic::Code::InvalidArgument
+                {
+                    return Err(tonic::Status::not_found("stream group not found"));
+                }
+                return Err(tonic::Status::internal(
+                    "internal error requesting stream group",
+                ));
+            }
+        };
+
+        let mut authz_request = Request::new(AuthorizeRequest {
+            resource: stream_group.resource_type,
+            resource_action: resource_action as i32,
+            resource_id: stream_group.resource_id.clone(),
+        });
+
+        authz_request.metadata_mut().clone_from(metadata);
+
+        let authorized = match self
+            .internal_authz_client
+            .clone()
+            .authorize(authz_request)
+            .await
+        {
+            Ok(value) => value,
+            Err(err) => {
+                error!("{}", err);
+                return Err(Status::internal("internal error during authz call"));
+            }
+        };
+
+        if !authorized.into_inner().ok {
+            return Err(tonic::Status::new(
+                tonic::Code::PermissionDenied,
+                "unsufficient permissions",
+            ));
+        };
+
+        return Ok(stream_group);
+    }
+
+    // The dead letter api is not part of the gRPC service, it is only served by the http gateway
+    // Listing and inspecting dead letters requires read permissions on the resource of the stream group,
+    // requeueing and purging them write permissions
+    pub async fn list_dead_letters(
+        &self,
+        metadata: &MetadataMap,
+        stream_group_id: &str,
+    ) -> Result<Vec<DeadLetter>, Status> {
+        let stream_group = self
+            .authorize_stream_group(metadata, stream_group_id, ResourceAction::Read)
+            .await?;
+
+        return match self.event_handler.list_dead_letters(stream_group.id).await {
+            Ok(value) => Ok(value),
+            Err(err) => {
+                error!("{}", err);
+                Err(Status::internal("could not read dead letters"))
+            }
+        };
+    }
+
+    pub async fn get_dead_letter(
+        &self,
+        metadata: &MetadataMap,
+        stream_group_id: &str,
+        dead_letter_id: u64,
+    ) -> Result<DeadLetter, Status> {
+        let stream_group = self
+            .authorize_stream_group(metadata, stream_group_id, ResourceAction::Read)
+            .await?;
+
+        return match self
+            .event_handler
+            .get_dead_letter(stream_group.id, dead_letter_id)
+            .await
+        {
+            Ok(Some(value)) => Ok(value),
+            Ok(None) => Err(Status::not_found("dead letter not found")),
+            Err(err) => {
+                error!("{}", err);
+                Err(Status::internal("could not read dead letter"))
+            }
+        };
+    }
+
+    pub async fn requeue_dead_letter(
+        &self,
+        metadata: &MetadataMap,
+        stream_group_id: &str,
+        dead_letter_id: u64,
+    ) -> Result<(), Status> {
+        let stream_group = self
+            .authorize_stream_group(metadata, stream_group_id, ResourceAction::Update)
+            .await?;
+
+        return match self
+            .event_handler
+            .requeue_dead_letter(stream_group.id, dead_letter_id)
+            .await
+        {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(Status::not_found("dead letter not found")),
+            Err(err) => {
+                error!("{}", err);
+                Err(Status::internal("could not requeue dead letter"))
+            }
+        };
+    }
+
+    // Purges a single dead letter or all dead letters of the stream group and returns the number of purged ones
+    pub async fn purge_dead_letters(
+        &self,
+        metadata: &MetadataMap,
+        stream_group_id: &str,
+        dead_letter_id: Option<u64>,
+    ) -> Result<u64, Status> {
+        let stream_group = self
+            .authorize_stream_group(metadata, stream_group_id, ResourceAction::Update)
+            .await?;
+
+        return match self
+            .event_handler
+            .purge_dead_letters(stream_group.id, dead_letter_id)
+            .await
+        {
+            Ok(0) if dead_letter_id.is_some() => Err(Status::not_found("dead letter not found")),
+            Ok(value) => Ok(value),
+            Err(err) => {
+                error!("{}", err);
+                Err(Status::internal("could not purge dead letters"))
+            }
+        };
+    }
+
+    // The webhook api is served by the http gateway like the dead letter api
+    // The webhook is read from its stream group, so every instance knows it, reading the state of a webhook
+    // requires read permissions on the resource of the stream group, enabling it write permissions
+    pub async fn get_webhook(
+        &self,
+        metadata: &MetadataMap,
+        stream_group_id: &str,
+    ) -> Result<RegisteredWebhook, Status> {
+        let stream_group = self
+            .authorize_stream_group(metadata, stream_group_id, ResourceAction::Read)
+            .await?;
+
+        return self.stored_webhook(&stream_group.id).await;
+    }
+
+    // Restarts the delivery of a disabled webhook on this instance, active webhooks are left as they are
+    pub async fn enable_webhook(
+        &self,
+        metadata: &MetadataMap,
+        stream_group_id: &str,
+    ) -> Result<RegisteredWebhook, Status> {
+        let stream_group = self
+            .authorize_stream_group(metadata, stream_group_id, ResourceAction::Update)
+            .await?;
+
+        let mut registered_webhook = self.stored_webhook(&stream_group.id).await?;
+        if registered_webhook.state == WebhookState::Active {
+            return Ok(registered_webhook);
+        }
+        registered_webhook.state = WebhookState::Active;
+        self.store_webhook(&stream_group.id, &registered_webhook)
+            .await?;
+
+        self.webhooks
+            .lock()
+            .await
+            .insert(stream_group.id.clone(), registered_webhook.clone());
+        if let Err(status) = self
+            .start_webhook_delivery(stream_group.id.clone(), registered_webhook.clone())
+            .await
+        {
+            self.webhooks.lock().await.remove(&stream_group.id);
+            registered_webhook.state = WebhookState::Disabled;
+            // The webhook was already stored as active, it would be resumed at the next startup otherwise
+            if let Err(status) = self
+                .store_webhook(&stream_group.id, &registered_webhook)
+                .await
+            {
+                error!("{}", status);
+            }
+            return Err(status);
+        }
+        info!("webhook of stream group {} enabled", stream_group.id);
+
+        return Ok(registered_webhook);
+    }
+}
+
+// The action requested for an ack chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkAction {
+    Ack,
+    // Redeliver the messages of the chunk, after the delay if one is given
+    Nack(Option<Duration>),
+    // Never redeliver the messages of the chunk
+    Terminate,
+}
+
+// Parses an entry of ack_chunk_id into the chunk id and the requested action
+// Since the ack message only contains chunk ids, the action is encoded as prefix of the id:
+// <chunk_id> acknowledges, nack:<chunk_id> redelivers immediately, nack:<delay_ms>:<chunk_id> redelivers after the delay
+// and term:<chunk_id> terminates the messages of the chunk
+#[allow(clippy::result_large_err)]
+fn parse_chunk_action(value: &str) -> Result<(String, ChunkAction), Status> {
+    if let Some(value) = value.strip_prefix(TERMINATE_CHUNK_PREFIX) {
+        return Ok((value.to_string(), ChunkAction::Terminate));
+    }
+
+    let value = match value.strip_prefix(NACK_CHUNK_PREFIX) {
+        Some(value) => value,
+        None => return Ok((value.to_string(), ChunkAction::Ack)),
+    };
+
+    return match value.split_once(':') {
+        Some((delay, chunk_id)) => match delay.parse::<u64>() {
+            Ok(delay) => Ok((
+                chunk_id.to_string(),
+                ChunkAction::Nack(Some(Duration::from_millis(delay))),
+            )),
+            Err(_) => Err(Status::invalid_argument(format!(
+                "invalid nack delay {:?}, expected milliseconds",
+                delay
+            ))),
+        },
+        None => Ok((value.to_string(), ChunkAction::Nack(None))),
+    };
+}
+
+// Determines the start position of a new stream group
+// The stream type of the request covers all events, a sequence and a timestamp,
+// new events only and the last event per subject can be requested with the stream start metadata instead
+#[allow(clippy::result_large_err)]
+fn parse_stream_start_position(
+    stream_type: Option<&StreamType>,
+    stream_start: Option<&str>,
+) -> Result<StreamStartPosition, Status> {
+    let metadata_position = match stream_start {
+        None => None,
+        Some("all") => Some(StreamStartPosition::All),
+        Some("new") => Some(StreamStartPosition::New),
+        Some("last-per-subject") => Some(StreamStartPosition::LastPerSubject),
+        Some(value) => {
+            return Err(Status::invalid_argument(format!(
+                "invalid stream start {:?}, expected all, new or last-per-subject",
+                value
+            )))
+        }
+    };
+
+    return match (stream_type, metadata_position) {
+        (None, None) | (Some(StreamType::StreamAll(_)), None) => Ok(StreamStartPosition::All),
+        (None, Some(position)) | (Some(StreamType::StreamAll(_)), Some(position)) => Ok(position),
+        (Some(StreamType::StreamFromSequence(value)), None) => match value.sequence {
+            0 => Err(Status::invalid_argument("stream sequences start at 1")),
+            sequence => Ok(StreamStartPosition::FromSequence(sequence)),
+        },
+        (Some(StreamType::StreamFromDate(value)), None) => {
+            let timestamp = match &value.timestamp {
+                Some(value) => value,
+                None => return Err(Status::invalid_argument("stream start date is missing")),
+            };
+            match Utc
+                .timestamp_opt(timestamp.seconds, timestamp.nanos.max(0) as u32)
+                .single()
+            {
+                Some(value) => Ok(StreamStartPosition::FromTimestamp(value)),
+                None => Err(Status::invalid_argument("invalid stream start date")),
+            }
+        }
+        (Some(_), Some(_)) => Err(Status::invalid_argument(
+            "stream start metadata can only be combined with streaming all events",
+        )),
+    };
+}
+
+// Parses the comma separated event types of a new stream group
+// Missing event types are treated as all event types
+#[allow(clippy::result_large_err)]
+fn parse_event_types(event_types: Option<&str>) -> Result<Vec<EventType>, Status> {
+    let event_types = match event_types {
+        Some(value) => value,
+        None => return Ok(vec![EventType::All]),
+    };
+
+    let mut parsed_event_types = Vec::new();
+    for event_type in event_types.split(',') {
+        let parsed_event_type = match event_type.trim().to_lowercase().as_str() {
+            "created" => EventType::Created,
+            "available" => EventType::Available,
+            "updated" => EventType::Updated,
+            "metadata-updated" => EventType::MetadataUpdated,
+            "deleted" => EventType::Deleted,
+            "all" => EventType::All,
+            _ => {
+                return Err(Status::invalid_argument(format!(
+                    "invalid event type {:?}, expected created, available, updated, metadata-updated, deleted or all",
+                    event_type
+                )))
+            }
+        };
+        if !parsed_event_types.contains(&parsed_event_type) {
+            parsed_event_types.push(parsed_event_type);
+        }
+    }
+
+    return Ok(parsed_event_types);
+}
+
+// Reads a numeric metadata value that has to be between 1 and the given limit
+#[allow(clippy::result_large_err)]
+fn parse_limited_metadata(
+    metadata: &MetadataMap,
+    name: &str,
+    limit: u64,
+) -> Result<Option<u64>, Status> {
+    let value = match metadata.get(name) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    return match value.to_str().ok().and_then(|x| x.parse::<u64>().ok()) {
+        Some(value) if value >= 1 && value <= limit => Ok(Some(value)),
+        _ => Err(Status::invalid_argument(format!(
+            "{} has to be a number between 1 and {}",
+            name, limit
+        ))),
+    };
+}
+
+// Determines the fetch options of a read stream from the request metadata
+// Options that are not set keep their default value
+#[allow(clippy::result_large_err)]
+fn parse_fetch_options(metadata: &MetadataMap) -> Result<FetchOptions, Status> {
+    let mut fetch_options = FetchOptions::default();
+
+    if let Some(value) = parse_limited_metadata(
+        metadata,
+        MAX_BATCH_SIZE_METADATA_NAME,
+        MAX_BATCH_SIZE_LIMIT as u64,
+    )? {
+        fetch_options.max_messages = value as usize;
+    }
+    if let Some(value) = parse_limited_metadata(
+        metadata,
+        MAX_BATCH_BYTES_METADATA_NAME,
+        MAX_BATCH_BYTES_LIMIT as u64,
+    )? {
+        fetch_options.max_bytes = value as usize;
+    }
+    if let Some(value) = parse_limited_metadata(
+        metadata,
+        MAX_WAIT_METADATA_NAME,
+        MAX_WAIT_LIMIT.as_millis() as u64,
+    )? {
+        fetch_options.max_wait = Duration::from_millis(value);
+    }
+
+    return Ok(fetch_options);
+}
+
+// Determines the heartbeat interval of a read stream from the request metadata
+#[allow(clippy::result_large_err)]
+fn parse_heartbeat_interval(metadata: &MetadataMap) -> Result<Duration, Status> {
+    return match parse_limited_metadata(
+        metadata,
+        HEARTBEAT_INTERVAL_METADATA_NAME,
+        MAX_HEARTBEAT_INTERVAL.as_millis() as u64,
+    )? {
+        Some(value) => Ok(Duration::from_millis(value)),
+        None => Ok(DEFAULT_HEARTBEAT_INTERVAL),
+    };
+}
+
+// Determines the inactivity ttl of a new stream group from the request metadata
+// An explicit ttl takes precedence over the ephemeral flag, stream groups without either never expire
+#[allow(clippy::result_large_err)]
+fn parse_inactivity_ttl(metadata: &MetadataMap) -> Result<Option<Duration>, Status> {
+    if let Some(value) = parse_limited_metadata(
+        metadata,
+        INACTIVITY_TTL_METADATA_NAME,
+        MAX_INACTIVITY_TTL.as_millis() as u64,
+    )? {
+        return Ok(Some(Duration::from_millis(value)));
+    }
+
+    return match metadata.get(EPHEMERAL_METADATA_NAME) {
+        Some(value) => match value.to_str() {
+            Ok("true") => Ok(Some(DEFAULT_EPHEMERAL_TTL)),
+            Ok("false") => Ok(None),
+            _ => Err(Status::invalid_argument(format!(
+                "{} has to be true or false",
+                EPHEMERAL_METADATA_NAME
+            ))),
+        },
+        None => Ok(None),
+    };
+}
+
+// Determines the acknowledgement mode of a read stream from the request metadata
+#[allow(clippy::result_large_err)]
+fn parse_ack_mode(metadata: &MetadataMap) -> Result<AckMode, Status> {
+    return match metadata.get(ACK_MODE_METADATA_NAME) {
+        Some(value) => match value.to_str() {
+            Ok("manual") => Ok(AckMode::Manual),
+            Ok("auto") => Ok(AckMode::Auto),
+            _ => Err(Status::invalid_argument(format!(
+                "{} has to be manual or auto",
+                ACK_MODE_METADATA_NAME
+            ))),
+        },
+        None => Ok(AckMode::Manual),
+    };
+}
+
+// Determines the flow control of a read stream from the request metadata
+#[allow(clippy::result_large_err)]
+fn parse_flow_control(metadata: &MetadataMap) -> Result<FlowControl, Status> {
+    let max_in_flight_chunks = parse_limited_metadata(
+        metadata,
+        MAX_IN_FLIGHT_CHUNKS_METADATA_NAME,
+        MAX_UNACKED_CHUNKS as u64,
+    )?
+    .map(|x| x as usize)
+    .unwrap_or(MAX_UNACKED_CHUNKS);
+
+    let adaptive = match metadata.get(FLOW_CONTROL_METADATA_NAME) {
+        Some(value) => match value.to_str() {
+            Ok("fixed") => false,
+            Ok("adaptive") => true,
+            _ => {
+                return Err(Status::invalid_argument(format!(
+                    "{} has to be fixed or adaptive",
+                    FLOW_CONTROL_METADATA_NAME
+                )))
+            }
+        },
+        None => false,
+    };
+
+    return Ok(FlowControl {
+        max_in_flight_chunks,
+        adaptive,
+    });
+}
+
+// Forwards an error of the input handler to the output stream
+// A failed send means that the output stream is already gone, the error is only logged then
+async fn report_error(err_sender: &async_channel::Sender<Status>, status: Status) {
+    error!("{}", status.message());
+    match err_sender.send(status).await {
+        Ok(_) => {}
+        Err(err) => error!("could not report error to closed stream: {}", err),
+    }
 }
 
 // The type definition for the outgoing response stream
@@ -71,6 +950,58 @@ impl update_notification_service_server::UpdateNotificationService for PublicSer
         };
         let inner_request = request.into_inner();
 
+        if inner_request.resource() == ResourceType::Unspecified {
+            return Err(Status::invalid_argument(
+                "resource type needs to be specified",
+            ));
+        }
+
+        if inner_request.resource() != ResourceType::All {
+            NatsIOUtils::validate_id(&inner_request.resource_id)?;
+        }
+
+        let stream_start = match metadata.get(STREAM_START_METADATA_NAME) {
+            Some(value) => match value.to_str() {
+                Ok(value) => Some(value),
+                Err(err) => {
+                    error!("{}", err);
+                    return Err(tonic::Status::invalid_argument(
+                        "could not read stream start",
+                    ));
+                }
+            },
+            None => None,
+        };
+        let start_position =
+            parse_stream_start_position(inner_request.stream_type.as_ref(), stream_start)?;
+
+        let event_types = match metadata.get(EVENT_TYPES_METADATA_NAME) {
+            Some(value) => match value.to_str() {
+                Ok(value) => parse_event_types(Some(value))?,
+                Err(err) => {
+                    error!("{}", err);
+                    return Err(tonic::Status::invalid_argument(
+                        "could not read event types",
+                    ));
+                }
+            },
+            None => parse_event_types(None)?,
+        };
+        // Stream groups with a webhook are pushed to the endpoint with the fetch limits of the request
+        let webhook = parse_webhook(&metadata, &self.webhook_options)?;
+        let fetch_options = parse_fetch_options(&metadata)?;
+        let max_deliver =
+            parse_limited_metadata(&metadata, MAX_DELIVER_METADATA_NAME, MAX_DELIVER_LIMIT)?;
+        let inactivity_ttl = parse_inactivity_ttl(&metadata)?;
+
+        // The internal record can only store a single event type, a filter with multiple types is stored as all there
+        // The underlaying system persists the full list with the stream group and applies the filter on delivery,
+        // it is the source of truth for the event types, the internal record is not read for filtering
+        let stream_group_event_type = match event_types.as_slice() {
+            [event_type] => *event_type,
+            _ => EventType::All,
+        };
+
         let mut authz_request = Request::new(AuthorizeRequest {
             resource: inner_request.resource,
             resource_action: ResourceAction::Read as i32,
@@ -124,11 +1055,11 @@ impl update_notification_service_server::UpdateNotificationService for PublicSer
             .internal_events_client
             .clone()
             .create_stream_group(CreateStreamGroupRequest {
-                event_type: EventType::All as i32,
-                resource_type: inner_request.resource.clone(),
-                notify_on_sub_resource: inner_request.include_subresource.clone(),
+                event_type: stream_group_event_type as i32,
+                resource_type: inner_request.resource,
+                notify_on_sub_resource: inner_request.include_subresource,
                 resource_id: inner_request.resource_id.clone(),
-                token: token,
+                token: token.clone(),
             })
             .await
         {
@@ -142,43 +1073,139 @@ impl update_notification_service_server::UpdateNotificationService for PublicSer
             }
         };
 
-        let hierarchy = match hiearchies.get(0) {
-            Some(value) => value,
-            None => {
-                return Err(Status::internal(
-                    "no hierarchy found, cannot create query string",
-                ))
-            }
-        };
-
         match self
             .event_handler
             .create_stream_group(
                 stream_group.id.clone(),
-                &hierarchy,
+                &hiearchies,
                 inner_request.resource(),
                 inner_request.resource_id,
                 inner_request.include_subresource,
+                &event_types,
+                start_position,
+                max_deliver,
+                inactivity_ttl,
             )
             .await
         {
             Ok(_) => {}
             Err(err) => {
                 error!("{}", err);
+                self.rollback_stream_group(&stream_group.id).await;
+                // Errors caused by the request itself are passed to the client
+                if let Some(status) = err.downcast_ref::<Status>() {
+                    if status.code() == tonic::Code::InvalidArgument {
+                        return Err(status.clone());
+                    }
+                }
                 return Err(tonic::Status::internal("could not create stream group"));
             }
         };
 
+        if let Some(webhook) = webhook {
+            let registered_webhook = RegisteredWebhook {
+                webhook,
+                fetch_options,
+                state: WebhookState::Active,
+            };
+            // Stored before the delivery starts, the stream group is rolled back if the webhook is lost
+            if let Err(status) = self
+                .store_webhook(&stream_group.id, &registered_webhook)
+                .await
+            {
+                self.rollback_stream_group(&stream_group.id).await;
+                return Err(status);
+            }
+            self.webhooks
+                .lock()
+                .await
+                .insert(stream_group.id.clone(), registered_webhook.clone());
+            if let Err(status) = self
+                .start_webhook_delivery(stream_group.id.clone(), registered_webhook)
+                .await
+            {
+                self.webhooks.lock().await.remove(&stream_group.id);
+                self.rollback_stream_group(&stream_group.id).await;
+                return Err(status);
+            }
+        }
+
         return Ok(Response::new(CreateEventStreamingGroupResponse {
             stream_group_id: stream_group.id.clone(),
         }));
     }
 
+    // Deletes a stream group from the internal event service and the underlaying notification system
+    // Active streams reading from the stream group are terminated
     async fn delete_event_streaming_group(
         &self,
-        _request: tonic::Request<DeleteEventStreamingGroupRequest>,
+        request: tonic::Request<DeleteEventStreamingGroupRequest>,
     ) -> Result<tonic::Response<DeleteEventStreamingGroupResponse>, tonic::Status> {
-        todo!()
+        let metadata = request.metadata().clone();
+
+        let token = match metadata.get(TOKEN_METADATA_NAME) {
+            Some(value) => match value.to_str() {
+                Ok(value) => value.to_string(),
+                Err(err) => {
+                    error!("{}", err);
+                    return Err(tonic::Status::invalid_argument("could not read token"));
+                }
+            },
+            None => {
+                return Err(tonic::Status::unauthenticated(
+                    "authentication header required and was not found",
+                ))
+            }
+        };
+        // Deleting a stream group requires write permissions on its resource, reading it only read permissions
+        let stream_group = self
+            .authorize_stream_group(
+                &metadata,
+                &request.into_inner().stream_group_id,
+                ResourceAction::Update,
+            )
+            .await?;
+
+        // The stream group is removed from the notification system first,
+        // a failed deletion can then be retried as long as the stream group is still known to the internal event service
+        match self
+            .event_handler
+            .delete_stream_group(stream_group.id.clone())
+            .await
+        {
+            Ok(_) => {}
+            Err(err) => {
+                error!("{}", err);
+                return Err(tonic::Status::internal("could not delete stream group"));
+            }
+        };
+
+        match self
+            .internal_events_client
+            .clone()
+            .delete_stream_group(DeleteStreamGroupRequest {
+                token,
+                stream_group_id: stream_group.id.clone(),
+            })
+            .await
+        {
+            Ok(_) => {}
+            // The stream group expired in the meantime and its record was deleted by the expiry check
+            Err(status) if status.code() == tonic::Code::NotFound => {}
+            Err(err) => {
+                error!("{}", err);
+                return Err(tonic::Status::internal("could not delete stream group"));
+            }
+        };
+
+        self.webhooks.lock().await.remove(&stream_group.id);
+        self.terminate_active_streams(
+            &stream_group.id,
+            Status::not_found("stream group was deleted"),
+        )
+        .await;
+
+        return Ok(Response::new(DeleteEventStreamingGroupResponse {}));
     }
 
     type ReadStreamGroupMessagesStream = ResponseStream;
@@ -199,20 +1226,12 @@ impl update_notification_service_server::UpdateNotificationService for PublicSer
     ) -> Result<tonic::Response<Self::ReadStreamGroupMessagesStream>, tonic::Status> {
         let metadata = request.metadata().clone();
 
-        let token = match metadata.get(TOKEN_METADATA_NAME) {
-            Some(value) => match value.to_str() {
-                Ok(value) => value.to_string(),
-                Err(err) => {
-                    error!("{}", err);
-                    return Err(tonic::Status::invalid_argument("could not read token"));
-                }
-            },
-            None => {
-                return Err(tonic::Status::unauthenticated(
-                    "authentication header required and was not found",
-                ))
-            }
-        };
+        let mut fetch_options = parse_fetch_options(&metadata)?;
+        let heartbeat_interval = parse_heartbeat_interval(&metadata)?;
+        // Heartbeats are only sent between fetches, a longer fetch wait would delay them beyond their interval
+        fetch_options.max_wait = fetch_options.max_wait.min(heartbeat_interval);
+        let flow_control = parse_flow_control(&metadata)?;
+        let ack_mode = parse_ack_mode(&metadata)?;
 
         let mut stream = request.into_inner();
         let initial_msg = match stream.next().await {
@@ -246,63 +1265,13 @@ impl update_notification_service_server::UpdateNotificationService for PublicSer
             }
         };
 
-        let stream_group = match self
-            .internal_events_client
-            .clone()
-            .get_stream_group(GetStreamGroupRequest {
-                stream_group_id: init.stream_group_id,
-                token: token.clone(),
-            })
-            .await
-        {
-            Ok(value) => {
-                let value = match value.into_inner().stream_group {
-                    Some(value) => value,
-                    None => return Err(Status::internal("internal error reading stream group")),
-                };
-                value
-            }
-            Err(err) => {
-                error!("{}", err);
-                return Err(tonic::Status::internal(
-                    "internal error requesting stream group",
-                ));
-            }
-        };
-
-        let mut authz_request = Request::new(AuthorizeRequest {
-            resource: stream_group.resource_type.clone(),
-            resource_action: stream_group.event_type.clone(),
-            resource_id: stream_group.resource_id.clone(),
-        });
-
-        authz_request.metadata_mut().clone_from(&metadata);
-
-        match self
-            .internal_authz_client
-            .clone()
-            .authorize(authz_request)
-            .await
-        {
-            Ok(value) => {
-                let authorized = value.into_inner().ok;
-                if !authorized {
-                    return Err(tonic::Status::permission_denied(
-                        "not allowed to perform call",
-                    ));
-                }
-            }
-            Err(err) => {
-                error!("{}", err);
-                return Err(tonic::Status::internal(
-                    "internal error when authorizing request",
-                ));
-            }
-        }
+        let stream_group = self
+            .authorize_stream_group(&metadata, &init.stream_group_id, ResourceAction::Read)
+            .await?;
 
         let stream_group_handler = match self
             .event_handler
-            .create_event_stream_handler(stream_group.id)
+            .create_event_stream_handler(stream_group.id.clone(), fetch_options)
             .await
         {
             Ok(value) => value,
@@ -315,90 +1284,243 @@ impl update_notification_service_server::UpdateNotificationService for PublicSer
         };
 
         // Hashmap to store the send chunks and acknowledge them later
-        let ack_chunks: Arc<Mutex<HashMap<String, Arc<Vec<jetstream::Message>>>>> =
-            Arc::new(Mutex::new(HashMap::new()));
+        let ack_chunks: AckChunks = Arc::new(Mutex::new(HashMap::new()));
 
         // Global variable to track if a close request was send
         // Is used to synchronize between input and output streams
         let close = Arc::new(AtomicBool::new(false));
 
+        // Number of chunks that can wait for their acknowledgement before fetching pauses
+        // Only changes with adaptive flow control
+        let window = Arc::new(AtomicUsize::new(flow_control.initial_window()));
+
         let (err_sender, err_recv) = async_channel::bounded(10);
+        // Acks of unknown chunk ids are reported to the client without ending the stream
+        let (unknown_chunk_sender, unknown_chunk_recv) =
+            async_channel::bounded::<String>(MAX_UNACKED_CHUNKS);
+
+        let terminate_recv = self.register_active_stream(stream_group.id.clone()).await;
+        let stream_group_id = stream_group.id;
+
+        // Closed when the output stream is dropped, e.g. when the client disconnects
+        // Is used to tear down the input handler
+        let (disconnect_sender, disconnect_recv) = async_channel::bounded::<()>(1);
 
         let cloned_close = close.clone();
         let cloned_ack_chunks = ack_chunks.clone();
+        let cloned_window = window.clone();
         // Spawns the handler that handles the incoming request from the client
         tokio::spawn(async move {
-            while let Some(ack_request) = stream.next().await {
+            loop {
+                let ack_request = tokio::select! {
+                    value = stream.next() => value,
+                    _ = disconnect_recv.recv() => break,
+                };
+                let ack_request = match ack_request {
+                    Some(value) => value,
+                    // Clients in auto ack mode have nothing to send after the init request
+                    // The stream lasts until the client disconnects then
+                    None if ack_mode == AckMode::Auto => {
+                        let _ = disconnect_recv.recv().await;
+                        break;
+                    }
+                    None => break,
+                };
                 let ack_request_unwrapped = match ack_request {
                     Ok(value) => value,
                     Err(err) => {
                         error!("{}", err);
-                        err_sender
-                            .send(Status::internal("error reading from input stream"))
-                            .await
-                            .unwrap();
+                        report_error(
+                            &err_sender,
+                            Status::internal("error reading from input stream"),
+                        )
+                        .await;
                         break;
                     }
                 };
-                if ack_request_unwrapped.close {
-                    cloned_close.store(true, Ordering::Relaxed);
-                }
+                let close_requested = ack_request_unwrapped.close;
 
-                let ack = match ack_request_unwrapped.stream_action.unwrap() {
-                    StreamAction::Init(_) => {
+                // Requests without chunks to acknowledge are handled like an empty ack, so a close is still applied
+                let ack = match ack_request_unwrapped.stream_action {
+                    Some(StreamAction::Init(_)) => {
                         // Init can only be called once and will otherwise yield an error
                         // The error needs to be propagated to the response channel
-                        // This send should never fail to send, otherwise it should panic
-                        err_sender
-                            .send(Status::invalid_argument(
-                                "init can only be used once in request",
-                            ))
-                            .await
-                            .unwrap();
+                        report_error(
+                            &err_sender,
+                            Status::invalid_argument("init can only be used once in request"),
+                        )
+                        .await;
                         break;
                     }
-                    StreamAction::Ack(value) => value,
+                    Some(StreamAction::Ack(value)) if ack_mode == AckMode::Auto => {
+                        if !value.ack_chunk_id.is_empty() {
+                            report_error(
+                                &err_sender,
+                                Status::invalid_argument(
+                                    "chunks can not be acknowledged in auto ack mode",
+                                ),
+                            )
+                            .await;
+                        }
+                        NotficationStreamAck::default()
+                    }
+                    Some(StreamAction::Ack(value)) => value,
+                    // A close request does not need to carry an action
+                    None if close_requested => NotficationStreamAck::default(),
+                    None => {
+                        report_error(
+                            &err_sender,
+                            Status::invalid_argument("could not read stream action value"),
+                        )
+                        .await;
+                        NotficationStreamAck::default()
+                    }
                 };
 
-                // Ackndowledge the messages in a chunk based on their ids
+                // Ackndowledge, nack or terminate the messages in a chunk based on their ids
                 // Could be parallelized if performance becomes an issue
                 let chunk_ids = ack.ack_chunk_id;
                 for chunk_id in chunk_ids {
-                    let mut ack_chunks = cloned_ack_chunks.lock().await;
-                    let msg_chunks = ack_chunks.remove(&chunk_id).unwrap();
+                    // Clients that acknowledge every chunk id they receive send the reports back as well
+                    if chunk_id.starts_with(UNKNOWN_CHUNK_PREFIX) {
+                        continue;
+                    }
+                    let (chunk_id, action) = match parse_chunk_action(&chunk_id) {
+                        Ok(value) => value,
+                        Err(err) => {
+                            report_error(&err_sender, err).await;
+                            continue;
+                        }
+                    };
+
+                    // An expired chunk was redelivered by the event system already, acknowledging it late is not an error
+                    // Unknown chunk ids are reported back, neither ends the stream
+                    let removed_chunk = cloned_ack_chunks.lock().await.remove(&chunk_id);
+                    let msg_chunks = match removed_chunk {
+                        Some(value) if value.expired => {
+                            warn!("ack chunk with id {} expired", chunk_id);
+                            continue;
+                        }
+                        Some(value) => value.messages,
+                        None => {
+                            warn!("ack chunk with id {} is unknown", chunk_id);
+                            if let Err(err) = unknown_chunk_sender.send(chunk_id).await {
+                                error!("could not report unknown chunk to closed stream: {}", err);
+                            }
+                            continue;
+                        }
+                    };
+                    match action {
+                        ChunkAction::Ack | ChunkAction::Terminate => {
+                            flow_control.grow(&cloned_window)
+                        }
+                        ChunkAction::Nack(_) => flow_control.shrink(&cloned_window),
+                    }
                     for msg in msg_chunks.iter() {
-                        match msg.ack().await {
+                        let result = match action {
+                            ChunkAction::Ack => msg.ack().await,
+                            ChunkAction::Nack(delay) => msg.nack(delay).await,
+                            ChunkAction::Terminate => msg.terminate().await,
+                        };
+                        match result {
                             Ok(_) => {}
                             Err(err) => {
                                 error!("{}", err);
-                                err_sender
-                                    .send(Status::internal(format!(
+                                report_error(
+                                    &err_sender,
+                                    Status::internal(format!(
                                         "error when acknowledging ack chunk with id {}",
                                         chunk_id,
-                                    )))
-                                    .await
-                                    .unwrap();
+                                    )),
+                                )
+                                .await;
                             }
                         };
                     }
                 }
+
+                // The stream is closed after the acknowledgements of the close request were handled,
+                // otherwise its chunks could be handed back for redelivery while their acks are still pending
+                if close_requested {
+                    cloned_close.store(true, Ordering::Relaxed);
+                }
             }
+
+            // The client closed its side of the stream or went away
+            // The output stream has to stop as well, otherwise it would keep fetching messages for nobody
+            cloned_close.store(true, Ordering::Relaxed);
         });
 
         // async_stream::stream!
         // Output stream
         // This will read messages from an underlaying event stream service and return them to the client
         let output = async_stream::stream! {
+            let mut ack_chunks_guard = AckChunksGuard {
+                ack_chunks: ack_chunks.clone(),
+                unsent: Vec::new(),
+            };
+            let _disconnect_sender = disconnect_sender;
+            let mut last_response = Instant::now();
+
             // Iterate until a close is requested
             while !close.load(Ordering::Relaxed) {
+                // Terminates the stream if the stream group was deleted
+                if let Ok(status) = terminate_recv.try_recv() {
+                    yield Err(status);
+                    break;
+                }
+
                 // Check if any error occured in request handling
-                match err_recv.try_recv() {
-                    Ok(err) => {
-                        yield Err(err);
+                // An error status ends the response stream, so fetching stops as well
+                if let Ok(err) = err_recv.try_recv() {
+                    yield Err(err);
+                    break;
+                };
+
+                while let Ok(chunk_id) = unknown_chunk_recv.try_recv() {
+                    last_response = Instant::now();
+                    yield Ok(ReadStreamGroupMessagesResponse {
+                        notification: Vec::new(),
+                        ack_chunk_id: format!("{}{}", UNKNOWN_CHUNK_PREFIX, chunk_id),
+                    });
+                }
+
+                // Lets the client know that the stream is still alive while there is nothing to send
+                if last_response.elapsed() >= heartbeat_interval {
+                    last_response = Instant::now();
+                    yield Ok(ReadStreamGroupMessagesResponse {
+                        notification: Vec::new(),
+                        ack_chunk_id: String::new(),
+                    });
+                }
+
+                // Expires chunks and pauses while the in flight window is exhausted
+                let unacked_chunks = {
+                    let now = Instant::now();
+                    let ack_wait = stream_group_handler.ack_wait();
+                    let mut ack_chunks = ack_chunks.lock().await;
+                    ack_chunks.retain(|_, chunk| !chunk.expired || chunk.expires_at + ack_wait > now);
+                    let mut expired_chunks = 0;
+                    for chunk in ack_chunks.values_mut() {
+                        if !chunk.expired && chunk.expires_at <= now {
+                            chunk.expired = true;
+                            chunk.messages = Arc::new(Vec::new());
+                            expired_chunks += 1;
+                        }
+                    }
+                    if expired_chunks > 0 {
+                        flow_control.shrink(&window);
                     }
-                    //An error here indicates and empty stream and can be ignored
-                    Err(_) => {}
+                    ack_chunks.values().filter(|chunk| !chunk.expired).count()
                 };
+                if unacked_chunks >= window.load(Ordering::Relaxed) {
+                    // The stream group must not expire while its reader is paused
+                    if let Err(err) = stream_group_handler.keep_alive().await {
+                        error!("{}", err);
+                    }
+                    tokio::time::sleep(UNACKED_CHUNKS_PAUSE).await;
+                    continue;
+                }
 
                 // chunk id for message chunk send to the client
                 // Used to ack the messages in that chunk
@@ -409,34 +1531,110 @@ impl update_notification_service_server::UpdateNotificationService for PublicSer
                     Err(err) => {
                         error!("{}", err);
                         yield Err(Status::internal("error reading from event system"));
-                        continue;
+                        break;
                     }
                 };
 
-                let msgs = Arc::new(msgs);
-                ack_chunks
-                    .lock()
-                    .await
-                    .insert(chunk_id.to_string(), msgs.clone());
+                // Idle periods produce no data messages, only heartbeats
+                if msgs.is_empty() {
+                    continue;
+                }
+                log_redeliveries(&stream_group_id, &msgs);
+
+                // The stream was closed while fetching, the unsent messages are handed back for redelivery
+                if close.load(Ordering::Relaxed) {
+                    for msg in msgs.iter() {
+                        match msg.nack(None).await {
+                            Ok(_) => {}
+                            Err(err) => error!("{}", err),
+                        }
+                    }
+                    break;
+                }
+
+                let decoded_msgs = msgs
+                    .iter()
+                    .map(|x| EventNotificationMessage::decode(x.payload()))
+                    .collect::<Vec<_>>();
+
+                // Corrupt messages can never be delivered and are terminated
+                // The other messages of the chunk were not sent and are handed back for redelivery
+                if let Some(position) = decoded_msgs.iter().position(|x| x.is_err()) {
+                    for (msg, decoded_msg) in msgs.iter().zip(decoded_msgs.iter()) {
+                        let result = match decoded_msg {
+                            Ok(_) => msg.nack(None).await,
+                            Err(err) => {
+                                error!("{}", err);
+                                msg.terminate().await
+                            }
+                        };
+                        match result {
+                            Ok(_) => {}
+                            Err(err) => error!("{}", err),
+                        }
+                    }
+                    yield Err(Status::data_loss(format!(
+                        "could not decode event message with sequence {}",
+                        msgs[position].sequence()
+                    )));
+                    break;
+                }
+
                 let event_notfication_msgs: Vec<NotificationStreamResponse> = msgs
                     .iter()
-                    .map(|x| {
-                        let message_bytes = x.payload.clone();
-                        let event_msg = EventNotificationMessage::decode(message_bytes).unwrap();
+                    .zip(decoded_msgs)
+                    .map(|(x, event_msg)| {
+                        let timestamp = x.timestamp();
                         NotificationStreamResponse {
-                            message: Some(event_msg),
-                            sequence: 0,
-                            timestamp: None,
+                            message: event_msg.ok(),
+                            sequence: x.sequence(),
+                            timestamp: Some(prost_types::Timestamp {
+                                seconds: timestamp.timestamp(),
+                                nanos: timestamp.timestamp_subsec_nanos() as i32,
+                            }),
                         }
                     })
                     .collect::<Vec<NotificationStreamResponse>>();
 
+                // Auto acknowledged chunks are not tracked, the messages are acknowledged after the chunk was sent
+                // Until then they are kept by the guard, so they are handed back if the stream is dropped on the way
+                if ack_mode == AckMode::Auto {
+                    last_response = Instant::now();
+                    ack_chunks_guard.unsent = msgs;
+                    yield Ok(ReadStreamGroupMessagesResponse {
+                        notification: event_notfication_msgs,
+                        ack_chunk_id: String::new(),
+                    });
+                    for msg in std::mem::take(&mut ack_chunks_guard.unsent).iter() {
+                        match msg.ack().await {
+                            Ok(_) => {}
+                            Err(err) => error!("{}", err),
+                        }
+                    }
+                    continue;
+                }
+
+                ack_chunks.lock().await.insert(
+                    chunk_id.to_string(),
+                    AckChunk {
+                        messages: Arc::new(msgs),
+                        expires_at: Instant::now() + stream_group_handler.ack_wait(),
+                        expired: false,
+                    },
+                );
+
                 let response = ReadStreamGroupMessagesResponse {
                     notification: event_notfication_msgs,
                     ack_chunk_id: chunk_id.to_string(),
                 };
+                last_response = Instant::now();
                 yield Ok(response)
             }
+
+            // Errors of the input handler that occured right before the stream was closed
+            while let Ok(err) = err_recv.try_recv() {
+                yield Err(err);
+            }
         };
 
         Ok(Response::new(
@@ -444,3 +1642,305 @@ impl update_notification_service_server::UpdateNotificationService for PublicSer
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use aruna_rust_api::api::notification::services::v1::{
+        create_event_streaming_group_request::StreamType, EventType, StreamAll, StreamFromDate,
+        StreamFromSequence,
+    };
+    use chrono::{TimeZone, Utc};
+
+    use tonic::metadata::MetadataMap;
+
+    use crate::stream_handler::handler::{FetchOptions, StreamStartPosition};
+
+    use super::{
+        parse_ack_mode, parse_chunk_action, parse_event_types, parse_fetch_options,
+        parse_flow_control, parse_heartbeat_interval, parse_inactivity_ttl,
+        parse_stream_start_position, AckMode, ChunkAction, FlowControl, DEFAULT_EPHEMERAL_TTL,
+        DEFAULT_HEARTBEAT_INTERVAL, MAX_UNACKED_CHUNKS,
+    };
+
+    #[test]
+    fn test_parse_chunk_action() {
+        assert_eq!(
+            parse_chunk_action("chunk_id").unwrap(),
+            ("chunk_id".to_string(), ChunkAction::Ack)
+        );
+        assert_eq!(
+            parse_chunk_action("nack:chunk_id").unwrap(),
+            ("chunk_id".to_string(), ChunkAction::Nack(None))
+        );
+        assert_eq!(
+            parse_chunk_action("nack:1500:chunk_id").unwrap(),
+            (
+                "chunk_id".to_string(),
+                ChunkAction::Nack(Some(Duration::from_millis(1500)))
+            )
+        );
+        assert_eq!(
+            parse_chunk_action("term:chunk_id").unwrap(),
+            ("chunk_id".to_string(), ChunkAction::Terminate)
+        );
+        assert_eq!(
+            parse_chunk_action("nack:soon:chunk_id").unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_parse_stream_start_position() {
+        let stream_all = StreamType::StreamAll(StreamAll {});
+        let from_sequence = StreamType::StreamFromSequence(StreamFromSequence { sequence: 5 });
+        let from_date = StreamType::StreamFromDate(StreamFromDate {
+            timestamp: Some(prost_types::Timestamp {
+                seconds: 1_000_000,
+                nanos: 500,
+            }),
+        });
+
+        assert_eq!(
+            parse_stream_start_position(None, None).unwrap(),
+            StreamStartPosition::All
+        );
+        assert_eq!(
+            parse_stream_start_position(Some(&stream_all), Some("new")).unwrap(),
+            StreamStartPosition::New
+        );
+        assert_eq!(
+            parse_stream_start_position(None, Some("last-per-subject")).unwrap(),
+            StreamStartPosition::LastPerSubject
+        );
+        assert_eq!(
+            parse_stream_start_position(Some(&from_sequence), None).unwrap(),
+            StreamStartPosition::FromSequence(5)
+        );
+        assert_eq!(
+            parse_stream_start_position(Some(&from_date), None).unwrap(),
+            StreamStartPosition::FromTimestamp(Utc.timestamp_opt(1_000_000, 500).unwrap())
+        );
+
+        for (stream_type, stream_start) in [
+            (None, Some("now")),
+            (Some(&from_sequence), Some("new")),
+            (
+                Some(&StreamType::StreamFromSequence(StreamFromSequence {
+                    sequence: 0,
+                })),
+                None,
+            ),
+            (
+                Some(&StreamType::StreamFromDate(StreamFromDate {
+                    timestamp: None,
+                })),
+                None,
+            ),
+        ] {
+            assert_eq!(
+                parse_stream_start_position(stream_type, stream_start)
+                    .unwrap_err()
+                    .code(),
+                tonic::Code::InvalidArgument
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_event_types() {
+        assert_eq!(parse_event_types(None).unwrap(), vec![EventType::All]);
+        assert_eq!(
+            parse_event_types(Some("created, Deleted,created")).unwrap(),
+            vec![EventType::Created, EventType::Deleted]
+        );
+        assert_eq!(
+            parse_event_types(Some("metadata-updated")).unwrap(),
+            vec![EventType::MetadataUpdated]
+        );
+        assert_eq!(
+            parse_event_types(Some("created,removed"))
+                .unwrap_err()
+                .code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_parse_fetch_options() {
+        assert_eq!(
+            parse_fetch_options(&MetadataMap::new()).unwrap(),
+            FetchOptions::default()
+        );
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert("max-batch-size", "10".parse().unwrap());
+        metadata.insert("max-batch-bytes", "4096".parse().unwrap());
+        metadata.insert("max-wait-ms", "50".parse().unwrap());
+        assert_eq!(
+            parse_fetch_options(&metadata).unwrap(),
+            FetchOptions {
+                max_messages: 10,
+                max_bytes: 4096,
+                max_wait: Duration::from_millis(50),
+            }
+        );
+
+        for (name, value) in [
+            ("max-batch-size", "0"),
+            ("max-batch-size", "1001"),
+            ("max-batch-bytes", "many"),
+            ("max-wait-ms", "60000"),
+        ] {
+            let mut metadata = MetadataMap::new();
+            metadata.insert(name, value.parse().unwrap());
+            assert_eq!(
+                parse_fetch_options(&metadata).unwrap_err().code(),
+                tonic::Code::InvalidArgument
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_ack_mode() {
+        assert_eq!(
+            parse_ack_mode(&MetadataMap::new()).unwrap(),
+            AckMode::Manual
+        );
+
+        for (value, expected) in [("manual", AckMode::Manual), ("auto", AckMode::Auto)] {
+            let mut metadata = MetadataMap::new();
+            metadata.insert("ack-mode", value.parse().unwrap());
+            assert_eq!(parse_ack_mode(&metadata).unwrap(), expected);
+        }
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert("ack-mode", "none".parse().unwrap());
+        assert_eq!(
+            parse_ack_mode(&metadata).unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_parse_flow_control() {
+        assert_eq!(
+            parse_flow_control(&MetadataMap::new()).unwrap(),
+            FlowControl {
+                max_in_flight_chunks: MAX_UNACKED_CHUNKS,
+                adaptive: false,
+            }
+        );
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert("max-in-flight-chunks", "8".parse().unwrap());
+        metadata.insert("flow-control", "adaptive".parse().unwrap());
+        assert_eq!(
+            parse_flow_control(&metadata).unwrap(),
+            FlowControl {
+                max_in_flight_chunks: 8,
+                adaptive: true,
+            }
+        );
+
+        for (name, value) in [
+            ("max-in-flight-chunks", "0"),
+            ("max-in-flight-chunks", "101"),
+            ("flow-control", "dynamic"),
+        ] {
+            let mut metadata = MetadataMap::new();
+            metadata.insert(name, value.parse().unwrap());
+            assert_eq!(
+                parse_flow_control(&metadata).unwrap_err().code(),
+                tonic::Code::InvalidArgument
+            );
+        }
+    }
+
+    #[test]
+    fn test_flow_control_window() {
+        let fixed = FlowControl {
+            max_in_flight_chunks: 4,
+            adaptive: false,
+        };
+        let window = AtomicUsize::new(fixed.initial_window());
+        fixed.shrink(&window);
+        fixed.grow(&window);
+        assert_eq!(window.load(Ordering::Relaxed), 4);
+
+        let adaptive = FlowControl {
+            max_in_flight_chunks: 4,
+            adaptive: true,
+        };
+        let window = AtomicUsize::new(adaptive.initial_window());
+        assert_eq!(window.load(Ordering::Relaxed), 1);
+        for expected in [2, 3, 4, 4] {
+            adaptive.grow(&window);
+            assert_eq!(window.load(Ordering::Relaxed), expected);
+        }
+        for expected in [2, 1, 1] {
+            adaptive.shrink(&window);
+            assert_eq!(window.load(Ordering::Relaxed), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_heartbeat_interval() {
+        assert_eq!(
+            parse_heartbeat_interval(&MetadataMap::new()).unwrap(),
+            DEFAULT_HEARTBEAT_INTERVAL
+        );
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert("heartbeat-interval-ms", "500".parse().unwrap());
+        assert_eq!(
+            parse_heartbeat_interval(&metadata).unwrap(),
+            Duration::from_millis(500)
+        );
+
+        for value in ["0", "300001", "often"] {
+            let mut metadata = MetadataMap::new();
+            metadata.insert("heartbeat-interval-ms", value.parse().unwrap());
+            assert_eq!(
+                parse_heartbeat_interval(&metadata).unwrap_err().code(),
+                tonic::Code::InvalidArgument
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_inactivity_ttl() {
+        assert_eq!(parse_inactivity_ttl(&MetadataMap::new()).unwrap(), None);
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert("ephemeral", "true".parse().unwrap());
+        assert_eq!(
+            parse_inactivity_ttl(&metadata).unwrap(),
+            Some(DEFAULT_EPHEMERAL_TTL)
+        );
+        metadata.insert("inactivity-ttl-ms", "60000".parse().unwrap());
+        assert_eq!(
+            parse_inactivity_ttl(&metadata).unwrap(),
+            Some(Duration::from_secs(60))
+        );
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert("ephemeral", "false".parse().unwrap());
+        assert_eq!(parse_inactivity_ttl(&metadata).unwrap(), None);
+
+        for (name, value) in [
+            ("ephemeral", "yes"),
+            ("inactivity-ttl-ms", "0"),
+            ("inactivity-ttl-ms", "604800001"),
+        ] {
+            let mut metadata = MetadataMap::new();
+            metadata.insert(name, value.parse().unwrap());
+            assert_eq!(
+                parse_inactivity_ttl(&metadata).unwrap_err().code(),
+                tonic::Code::InvalidArgument
+            );
+        }
+    }
+}
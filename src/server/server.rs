@@ -4,34 +4,88 @@ use aruna_rust_api::api::{
         internal_event_emitter_service_server::InternalEventEmitterServiceServer,
         internal_event_service_client::InternalEventServiceClient,
     },
-    notification::services::v1::update_notification_service_server::UpdateNotificationServiceServer,
+    notification::services::v1::{
+        update_notification_service_client::UpdateNotificationServiceClient,
+        update_notification_service_server::UpdateNotificationServiceServer,
+    },
     storage::services::v1::resource_info_service_client::ResourceInfoServiceClient,
 };
 use async_nats::ServerAddr;
-use futures::future::try_join;
-use log::error;
-use tonic::transport::Server;
+use futures::future::try_join3;
+use futures::TryFutureExt;
+use log::{error, warn};
+use std::sync::Arc;
+use tonic::transport::{Channel, Server};
 
-use crate::stream_handler::natsio::NatsIOEventHandler;
+use crate::stream_handler::handler::EventHandler;
+use crate::stream_handler::memory::{MemoryEventHandler, MemoryStreamConfig};
+use crate::stream_handler::natsio::{NatsIOEventHandler, NatsIOStreamConfig};
 
-use super::{internal_event_server::InternalServer, public_event_server::PublicServer};
+use super::{
+    http_gateway::HttpGateway,
+    internal_event_server::InternalServer,
+    public_event_server::{PublicServer, STREAM_GROUP_EXPIRY_CHECK_INTERVAL},
+    webhook::WebhookOptions,
+};
 
 pub const TOKEN_METADATA_NAME: &str = "api-token";
 pub const INTERNAL_AUTHZ_TOKEN: &str = "internal-token";
+pub const IDEMPOTENCY_KEY_METADATA_NAME: &str = "idempotency-key";
+pub const DUPLICATE_EVENT_METADATA_NAME: &str = "duplicate-event";
+// Start position of a new stream group that is not covered by the stream type of the request: all, new or last-per-subject
+pub const STREAM_START_METADATA_NAME: &str = "stream-start";
+// Comma separated event types a new stream group is limited to, e.g. created,deleted
+pub const EVENT_TYPES_METADATA_NAME: &str = "event-types";
+// Fetch limits of a read stream: maximum messages and bytes per chunk and the maximum wait for a chunk in milliseconds
+pub const MAX_BATCH_SIZE_METADATA_NAME: &str = "max-batch-size";
+pub const MAX_BATCH_BYTES_METADATA_NAME: &str = "max-batch-bytes";
+pub const MAX_WAIT_METADATA_NAME: &str = "max-wait-ms";
+// Interval in milliseconds of the heartbeats an idle read stream sends
+pub const HEARTBEAT_INTERVAL_METADATA_NAME: &str = "heartbeat-interval-ms";
+// Flow control of a read stream: maximum chunks waiting for their acknowledgement and the mode, fixed or adaptive
+pub const MAX_IN_FLIGHT_CHUNKS_METADATA_NAME: &str = "max-in-flight-chunks";
+pub const FLOW_CONTROL_METADATA_NAME: &str = "flow-control";
+// Acknowledgement mode of a read stream, manual or auto
+pub const ACK_MODE_METADATA_NAME: &str = "ack-mode";
+// Deliveries of a message of a new stream group after which it is moved to the dead letters of the stream group
+pub const MAX_DELIVER_METADATA_NAME: &str = "max-deliver";
+// Inactivity ttl of a new stream group in milliseconds, the stream group is deleted once it was not read for that long
+// Ephemeral stream groups (true or false) use a default ttl of 5 minutes
+pub const INACTIVITY_TTL_METADATA_NAME: &str = "inactivity-ttl-ms";
+pub const EPHEMERAL_METADATA_NAME: &str = "ephemeral";
+// Endpoint and signing secret of a stream group that is pushed to a webhook instead of being read
+pub const WEBHOOK_URL_METADATA_NAME: &str = "webhook-url";
+pub const WEBHOOK_SECRET_METADATA_NAME: &str = "webhook-secret";
+// Prefixes of ack chunk ids that negatively acknowledge or terminate a chunk instead of acknowledging it
+pub const NACK_CHUNK_PREFIX: &str = "nack:";
+pub const TERMINATE_CHUNK_PREFIX: &str = "term:";
+// Prefix of the ack chunk id of a response without notifications that reports an ack for an unknown chunk id
+pub const UNKNOWN_CHUNK_PREFIX: &str = "unknown:";
+
+// The underlaying event system used to store and distribute events
+pub enum EventBackend {
+    // Nats.io Jetstream, requires a set of reachable nats servers
+    // The event stream is provisioned with the given configuration
+    NatsIO(Vec<ServerAddr>, NatsIOStreamConfig),
+    // In-process event handler, events are not persisted and not shared between instances
+    // The events are retained within the given limits
+    Memory(MemoryStreamConfig),
+}
 
 pub struct EventServer {}
 
 impl EventServer {
+    #[allow(clippy::too_many_arguments)]
     pub async fn start_server(
         internal_event_token: String,
-        nats_hosts: &[ServerAddr],
+        event_backend: EventBackend,
         internal_event_service_client_host: String,
         authz_event_service_client_host: String,
         internal_event_emitter_service_server_host: String,
         resource_host: String,
         public_event_server_host: String,
+        http_gateway_host: Option<String>,
     ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
-        let nats_client = async_nats::connect(nats_hosts).await?;
         let internal_event_service_client =
             match InternalEventServiceClient::connect(internal_event_service_client_host).await {
                 Ok(value) => value,
@@ -57,19 +111,73 @@ impl EventServer {
             }
         };
 
-        let event_handler = Box::new(NatsIOEventHandler::new(nats_client).await?);
+        match event_backend {
+            EventBackend::NatsIO(nats_hosts, stream_config) => {
+                let nats_client = async_nats::connect(nats_hosts).await?;
+                let event_handler = NatsIOEventHandler::new(nats_client, stream_config).await?;
+                EventServer::serve(
+                    event_handler,
+                    internal_event_token,
+                    internal_event_service_client,
+                    internal_authz_service_client,
+                    resource_client,
+                    internal_event_emitter_service_server_host,
+                    public_event_server_host,
+                    http_gateway_host,
+                )
+                .await
+            }
+            EventBackend::Memory(stream_config) => {
+                EventServer::serve(
+                    MemoryEventHandler::with_stream_config(stream_config),
+                    internal_event_token,
+                    internal_event_service_client,
+                    internal_authz_service_client,
+                    resource_client,
+                    internal_event_emitter_service_server_host,
+                    public_event_server_host,
+                    http_gateway_host,
+                )
+                .await
+            }
+        }
+    }
 
+    // Serves the internal and public event server with a shared event handler
+    // The http gateway is only served if a host is given
+    #[allow(clippy::too_many_arguments)]
+    async fn serve<T: EventHandler + Clone + Send + Sync + 'static>(
+        event_handler: T,
+        internal_event_token: String,
+        internal_event_service_client: InternalEventServiceClient<Channel>,
+        internal_authz_service_client: InternalAuthorizeServiceClient<Channel>,
+        resource_client: ResourceInfoServiceClient<Channel>,
+        internal_event_emitter_service_server_host: String,
+        public_event_server_host: String,
+        http_gateway_host: Option<String>,
+    ) -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
         let internal_event_server = InternalServer {
-            event_handler: event_handler.clone(),
-            internal_token: internal_event_token,
+            event_handler: Box::new(event_handler.clone()),
+            internal_token: internal_event_token.clone(),
         };
 
-        let public_event_server = PublicServer {
+        let public_event_server = Arc::new(PublicServer {
             internal_events_client: internal_event_service_client.clone(),
             internal_authz_client: internal_authz_service_client.clone(),
-            event_handler: event_handler.clone(),
+            event_handler: Arc::new(event_handler),
             resource_client: resource_client.clone(),
-        };
+            active_streams: Default::default(),
+            webhook_options: WebhookOptions::default(),
+            webhooks: Default::default(),
+            internal_token: internal_event_token,
+        });
+        // Webhooks registered before the restart, also with other instances, are delivered by this instance as well
+        public_event_server.resume_webhooks().await?;
+        tokio::spawn(
+            public_event_server
+                .clone()
+                .expire_stream_groups(STREAM_GROUP_EXPIRY_CHECK_INTERVAL),
+        );
 
         let internal_event_server_service = Server::builder()
             .add_service(InternalEventEmitterServiceServer::new(
@@ -78,14 +186,43 @@ impl EventServer {
             .serve(internal_event_emitter_service_server_host.parse().unwrap());
 
         let public_event_server_service = Server::builder()
-            .add_service(UpdateNotificationServiceServer::new(public_event_server))
+            .add_service(UpdateNotificationServiceServer::from_arc(
+                public_event_server.clone(),
+            ))
             .serve(public_event_server_host.parse().unwrap());
 
-        match try_join(internal_event_server_service, public_event_server_service).await {
-            Ok(value) => value,
+        // The gateway forwards to the public event server, so it connects lazily once the server is up
+        let http_gateway_service = async move {
+            let http_gateway_host = match http_gateway_host {
+                Some(value) => value,
+                None => {
+                    // The dead letter api is only served by the gateway
+                    warn!("http gateway disabled, dead letters can not be managed without HTTP_GATEWAY_HOST");
+                    return Ok(());
+                }
+            };
+            let channel = Channel::from_shared(format!("http://{}", public_event_server_host))?
+                .connect_lazy();
+            HttpGateway::new(
+                UpdateNotificationServiceClient::new(channel),
+                public_event_server,
+            )
+            .serve(http_gateway_host.parse()?)
+            .await?;
+            return Ok::<(), Box<dyn std::error::Error + Sync + Send>>(());
+        };
+
+        match try_join3(
+            internal_event_server_service.err_into(),
+            public_event_server_service.err_into(),
+            http_gateway_service,
+        )
+        .await
+        {
+            Ok(_) => {}
             Err(err) => {
                 error!("{}", err);
-                return Err(Box::new(err));
+                return Err(err);
             }
         };
         return Ok(());
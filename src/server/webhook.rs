@@ -0,0 +1,586 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use aruna_rust_api::api::notification::services::v1::{
+    EventNotificationMessage, NotificationStreamResponse,
+};
+use chrono::Utc;
+use futures::lock::Mutex;
+use hmac::{Hmac, Mac};
+use hyper::client::connect::dns::Name;
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::{Body, Client, Uri};
+use hyper_rustls::HttpsConnector;
+use log::{error, info};
+use prost::Message;
+use serde::Serialize;
+use sha2::Sha256;
+use tonic::metadata::MetadataMap;
+use tonic::Status;
+
+use crate::stream_handler::handler::{
+    log_redeliveries, EventStreamHandler, EventStreamMessage, FetchOptions, StoredWebhook,
+    StreamGroupNotFound,
+};
+
+use super::http_gateway::JsonNotification;
+use super::server::{WEBHOOK_SECRET_METADATA_NAME, WEBHOOK_URL_METADATA_NAME};
+
+// Headers of a webhook request, the signature is the hex encoded HMAC-SHA256 of "<timestamp>.<body>"
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "x-aruna-signature";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "x-aruna-timestamp";
+
+const WEBHOOK_MIN_SECRET_LENGTH: usize = 16;
+
+// Retry and disable behaviour of the webhook delivery, shared by all webhooks of a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebhookOptions {
+    // Attempts to deliver a batch before it is handed back to the stream group
+    pub max_attempts: u32,
+    // Wait before the first retry, doubled with every retry up to the maximum backoff
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub request_timeout: Duration,
+    // Consecutive batches that could not be delivered before the webhook is disabled
+    pub max_failed_batches: u32,
+    // Allows plain http urls and loopback or private addresses, only meant for tests
+    pub allow_insecure: bool,
+}
+
+impl Default for WebhookOptions {
+    fn default() -> Self {
+        return WebhookOptions {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            request_timeout: Duration::from_secs(10),
+            max_failed_batches: 3,
+            allow_insecure: false,
+        };
+    }
+}
+
+// An endpoint the messages of a stream group are pushed to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    pub url: Uri,
+    pub secret: String,
+}
+
+// Whether the webhook of a stream group delivers its messages or was disabled after too many failed batches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookState {
+    Active,
+    Disabled,
+}
+
+// A webhook together with the fetch limits of its delivery
+// Registrations are stored with their stream group in the underlaying system, see StoredWebhook
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisteredWebhook {
+    pub webhook: Webhook,
+    pub fetch_options: FetchOptions,
+    pub state: WebhookState,
+}
+
+impl From<&RegisteredWebhook> for StoredWebhook {
+    fn from(registered_webhook: &RegisteredWebhook) -> Self {
+        return StoredWebhook {
+            url: registered_webhook.webhook.url.to_string(),
+            secret: registered_webhook.webhook.secret.clone(),
+            fetch_options: registered_webhook.fetch_options,
+            disabled: registered_webhook.state == WebhookState::Disabled,
+        };
+    }
+}
+
+// The url was validated by parse_webhook before it was stored, only a changed stored value fails to parse
+impl TryFrom<StoredWebhook> for RegisteredWebhook {
+    type Error = hyper::http::uri::InvalidUri;
+
+    fn try_from(stored_webhook: StoredWebhook) -> Result<Self, Self::Error> {
+        return Ok(RegisteredWebhook {
+            webhook: Webhook {
+                url: stored_webhook.url.parse()?,
+                secret: stored_webhook.secret,
+            },
+            fetch_options: stored_webhook.fetch_options,
+            state: match stored_webhook.disabled {
+                true => WebhookState::Disabled,
+                false => WebhookState::Active,
+            },
+        });
+    }
+}
+
+// Webhooks delivered by this instance by stream group id
+// Each instance resumes the stored webhooks at startup, see PublicServer::resume_webhooks
+pub type Webhooks = Arc<Mutex<HashMap<String, RegisteredWebhook>>>;
+
+// The reason a webhook delivery ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryEnd {
+    // Too many batches in a row could not be delivered
+    Disabled,
+    // The stream group was deleted or expired through this instance
+    Terminated,
+    // The stream group no longer exists in the underlaying system, e.g. it was removed by another instance
+    StreamGroupNotFound,
+}
+
+// Body of a webhook request
+#[derive(Debug, Serialize)]
+struct WebhookPayload {
+    stream_group_id: String,
+    notifications: Vec<JsonNotification>,
+}
+
+// Reads the webhook of a new stream group from the request metadata
+// Stream groups without a webhook url are read by clients instead
+// Only https urls of public addresses are accepted unless the options allow insecure webhooks
+#[allow(clippy::result_large_err)]
+pub fn parse_webhook(
+    metadata: &MetadataMap,
+    options: &WebhookOptions,
+) -> Result<Option<Webhook>, Status> {
+    let read_metadata = |name: &str| -> Result<Option<String>, Status> {
+        return match metadata.get(name) {
+            Some(value) => match value.to_str() {
+                Ok(value) => Ok(Some(value.to_string())),
+                Err(err) => {
+                    error!("{}", err);
+                    Err(Status::invalid_argument(format!("could not read {}", name)))
+                }
+            },
+            None => Ok(None),
+        };
+    };
+
+    let (url, secret) = match (
+        read_metadata(WEBHOOK_URL_METADATA_NAME)?,
+        read_metadata(WEBHOOK_SECRET_METADATA_NAME)?,
+    ) {
+        (Some(url), Some(secret)) => (url, secret),
+        (None, None) => return Ok(None),
+        _ => {
+            return Err(Status::invalid_argument(format!(
+                "{} and {} have to be set together",
+                WEBHOOK_URL_METADATA_NAME, WEBHOOK_SECRET_METADATA_NAME
+            )))
+        }
+    };
+
+    let url = match url.parse::<Uri>() {
+        Ok(value) => value,
+        Err(err) => {
+            error!("{}", err);
+            return Err(Status::invalid_argument("could not parse webhook url"));
+        }
+    };
+    let scheme_allowed = match url.scheme_str() {
+        Some("https") => true,
+        Some("http") => options.allow_insecure,
+        _ => false,
+    };
+    let host = match url.host() {
+        Some(value) if scheme_allowed => value,
+        _ => {
+            return Err(Status::invalid_argument(
+                "webhook url has to be an absolute https url",
+            ))
+        }
+    };
+    // Host names are checked once they are resolved, see PublicResolver
+    if let Ok(ip) = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        if !options.allow_insecure && !is_public_ip(ip) {
+            return Err(Status::invalid_argument(
+                "webhook url must not point to a loopback or private address",
+            ));
+        }
+    }
+
+    if secret.len() < WEBHOOK_MIN_SECRET_LENGTH {
+        return Err(Status::invalid_argument(format!(
+            "webhook secret has to be at least {} characters long",
+            WEBHOOK_MIN_SECRET_LENGTH
+        )));
+    }
+
+    return Ok(Some(Webhook { url, secret }));
+}
+
+// Whether an address can be reached from the public internet
+// Webhooks must not be used to send requests to the network of the server
+fn is_public_ip(ip: IpAddr) -> bool {
+    return match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            // 100.64.0.0/10 is shared address space of carrier grade NATs
+            let shared = octets[0] == 100 && (octets[1] & 0xc0) == 64;
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            None => {
+                let segments = ip.segments();
+                // fc00::/7 are unique local and fe80::/10 link local addresses
+                let unique_local = (segments[0] & 0xfe00) == 0xfc00;
+                let link_local = (segments[0] & 0xffc0) == 0xfe80;
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || unique_local
+                    || link_local)
+            }
+        },
+    };
+}
+
+// Resolves the host names of webhook urls and drops all addresses that are not public
+// Checking the resolved addresses instead of the url prevents names that point into the network of the server
+#[derive(Debug, Clone, Copy)]
+struct PublicResolver {
+    allow_insecure: bool,
+}
+
+impl Service<Name> for PublicResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        return Poll::Ready(Ok(()));
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let allow_insecure = self.allow_insecure;
+        return Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| allow_insecure || is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("{} does not resolve to a public address", name),
+                ));
+            }
+
+            return Ok(addrs.into_iter());
+        });
+    }
+}
+
+// Signs the body of a webhook request together with its timestamp, so receivers can reject replayed requests
+pub fn sign_payload(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    // HMAC accepts keys of any length
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    return hex::encode(mac.finalize().into_bytes());
+}
+
+// Pushes the messages of a stream group to its webhook until the stream group is deleted
+// Each fetched batch is sent as one request and acknowledged once the endpoint accepted it
+// Batches that could not be delivered are nacked, the webhook is disabled after too many failed batches in a row
+pub struct WebhookDelivery {
+    pub stream_group_id: String,
+    pub webhook: Webhook,
+    pub stream_handler: Box<dyn EventStreamHandler + Send + Sync>,
+    pub options: WebhookOptions,
+    // Receives a status when the stream group is deleted
+    pub terminate_recv: async_channel::Receiver<Status>,
+    client: Client<HttpsConnector<HttpConnector<PublicResolver>>>,
+}
+
+impl WebhookDelivery {
+    pub fn new(
+        stream_group_id: String,
+        webhook: Webhook,
+        stream_handler: Box<dyn EventStreamHandler + Send + Sync>,
+        options: WebhookOptions,
+        terminate_recv: async_channel::Receiver<Status>,
+    ) -> Self {
+        let mut http_connector = HttpConnector::new_with_resolver(PublicResolver {
+            allow_insecure: options.allow_insecure,
+        });
+        http_connector.enforce_http(false);
+        let connector = hyper_rustls::HttpsConnectorBuilder::new().with_webpki_roots();
+        let connector = match options.allow_insecure {
+            true => connector.https_or_http(),
+            false => connector.https_only(),
+        }
+        .enable_http1()
+        .wrap_connector(http_connector);
+
+        return WebhookDelivery {
+            stream_group_id,
+            webhook,
+            stream_handler,
+            options,
+            terminate_recv,
+            client: Client::builder().build(connector),
+        };
+    }
+
+    fn terminated(&self) -> bool {
+        return !self.terminate_recv.is_empty() || self.terminate_recv.is_closed();
+    }
+
+    // Runs until the webhook is disabled or its stream group is gone
+    pub async fn run(self) -> DeliveryEnd {
+        let mut failed_batches = 0;
+
+        while !self.terminated() {
+            let msgs = match self.stream_handler.get_stream_group_msgs().await {
+                Ok(value) => value,
+                Err(err) => {
+                    // Fetching from a removed stream group can not succeed anymore
+                    if err.downcast_ref::<StreamGroupNotFound>().is_some() {
+                        info!(
+                            "webhook of stream group {} stopped: {}",
+                            self.stream_group_id, err
+                        );
+                        return DeliveryEnd::StreamGroupNotFound;
+                    }
+                    error!("{}", err);
+                    tokio::time::sleep(self.options.initial_backoff).await;
+                    continue;
+                }
+            };
+            if msgs.is_empty() {
+                continue;
+            }
+            log_redeliveries(&self.stream_group_id, &msgs);
+
+            // Corrupt messages can never be delivered and are terminated
+            let mut notifications = Vec::with_capacity(msgs.len());
+            let mut delivered_msgs = Vec::with_capacity(msgs.len());
+            for msg in msgs {
+                match EventNotificationMessage::decode(msg.payload()) {
+                    Ok(value) => {
+                        let timestamp = msg.timestamp();
+                        notifications.push(JsonNotification::from(NotificationStreamResponse {
+                            message: Some(value),
+                            sequence: msg.sequence(),
+                            timestamp: Some(prost_types::Timestamp {
+                                seconds: timestamp.timestamp(),
+                                nanos: timestamp.timestamp_subsec_nanos() as i32,
+                            }),
+                        }));
+                        delivered_msgs.push(msg);
+                    }
+                    Err(err) => {
+                        error!("{}", err);
+                        if let Err(err) = msg.terminate().await {
+                            error!("{}", err);
+                        }
+                    }
+                }
+            }
+            if delivered_msgs.is_empty() {
+                continue;
+            }
+
+            let body = match serde_json::to_vec(&WebhookPayload {
+                stream_group_id: self.stream_group_id.clone(),
+                notifications,
+            }) {
+                Ok(value) => value,
+                Err(err) => {
+                    error!("{}", err);
+                    continue;
+                }
+            };
+
+            if self.deliver(body, &delivered_msgs).await {
+                failed_batches = 0;
+                for msg in delivered_msgs.iter() {
+                    if let Err(err) = msg.ack().await {
+                        error!("{}", err);
+                    }
+                }
+                continue;
+            }
+
+            // The batch stays in the stream group and can be read by clients or a later delivery
+            for msg in delivered_msgs.iter() {
+                if let Err(err) = msg.nack(None).await {
+                    error!("{}", err);
+                }
+            }
+
+            failed_batches += 1;
+            if failed_batches >= self.options.max_failed_batches {
+                error!(
+                    "webhook of stream group {} disabled after {} failed batches",
+                    self.stream_group_id, failed_batches
+                );
+                return DeliveryEnd::Disabled;
+            }
+        }
+
+        info!("webhook of stream group {} stopped", self.stream_group_id);
+        return DeliveryEnd::Terminated;
+    }
+
+    // Sends a batch with exponential backoff between the attempts
+    // Returns whether the endpoint accepted the batch
+    async fn deliver(
+        &self,
+        body: Vec<u8>,
+        msgs: &[Box<dyn EventStreamMessage + Send + Sync>],
+    ) -> bool {
+        let mut backoff = self.options.initial_backoff;
+
+        for attempt in 1..=self.options.max_attempts {
+            match self.post(body.clone()).await {
+                Ok(_) => return true,
+                Err(err) => error!(
+                    "webhook of stream group {} failed attempt {}: {}",
+                    self.stream_group_id, attempt, err
+                ),
+            }
+            if attempt == self.options.max_attempts {
+                break;
+            }
+
+            // Keeps the messages from being redelivered and the stream group from expiring while waiting for the next attempt
+            for msg in msgs {
+                if let Err(err) = msg.in_progress().await {
+                    error!("{}", err);
+                }
+            }
+            if let Err(err) = self.stream_handler.keep_alive().await {
+                error!("{}", err);
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = self.terminate_recv.recv() => return false,
+            }
+            backoff = (backoff * 2).min(self.options.max_backoff);
+        }
+
+        return false;
+    }
+
+    async fn post(&self, body: Vec<u8>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let timestamp = Utc::now().timestamp();
+        let signature = sign_payload(&self.webhook.secret, timestamp, &body);
+
+        let request = hyper::Request::post(self.webhook.url.clone())
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .header(WEBHOOK_TIMESTAMP_HEADER, timestamp)
+            .header(WEBHOOK_SIGNATURE_HEADER, format!("sha256={}", signature))
+            .body(Body::from(body))?;
+
+        let response =
+            tokio::time::timeout(self.options.request_timeout, self.client.request(request))
+                .await??;
+        if !response.status().is_success() {
+            return Err(format!("webhook responded with {}", response.status()).into());
+        }
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::metadata::MetadataMap;
+
+    use super::{parse_webhook, sign_payload, Webhook, WebhookOptions};
+
+    #[test]
+    fn test_parse_webhook() {
+        let options = WebhookOptions::default();
+        assert_eq!(parse_webhook(&MetadataMap::new(), &options).unwrap(), None);
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert("webhook-url", "https://example.com/hook".parse().unwrap());
+        metadata.insert("webhook-secret", "0123456789abcdef".parse().unwrap());
+        assert_eq!(
+            parse_webhook(&metadata, &options).unwrap(),
+            Some(Webhook {
+                url: "https://example.com/hook".parse().unwrap(),
+                secret: "0123456789abcdef".to_string(),
+            })
+        );
+
+        for (url, secret) in [
+            (Some("https://example.com/hook"), None),
+            (None, Some("0123456789abcdef")),
+            (Some("ftp://example.com/hook"), Some("0123456789abcdef")),
+            (Some("http://example.com/hook"), Some("0123456789abcdef")),
+            (Some("https://127.0.0.1/hook"), Some("0123456789abcdef")),
+            (Some("https://10.0.0.1/hook"), Some("0123456789abcdef")),
+            (
+                Some("https://169.254.169.254/hook"),
+                Some("0123456789abcdef"),
+            ),
+            (Some("https://[::1]/hook"), Some("0123456789abcdef")),
+            (
+                Some("https://[::ffff:192.168.0.1]/hook"),
+                Some("0123456789abcdef"),
+            ),
+            (Some("/hook"), Some("0123456789abcdef")),
+            (Some("https://example.com/hook"), Some("short")),
+        ] {
+            let mut metadata = MetadataMap::new();
+            if let Some(url) = url {
+                metadata.insert("webhook-url", url.parse().unwrap());
+            }
+            if let Some(secret) = secret {
+                metadata.insert("webhook-secret", secret.parse().unwrap());
+            }
+            assert_eq!(
+                parse_webhook(&metadata, &options).unwrap_err().code(),
+                tonic::Code::InvalidArgument
+            );
+        }
+
+        // Tests deliver to plain http endpoints on the loopback interface
+        let mut metadata = MetadataMap::new();
+        metadata.insert("webhook-url", "http://127.0.0.1:8080/hook".parse().unwrap());
+        metadata.insert("webhook-secret", "0123456789abcdef".parse().unwrap());
+        let options = WebhookOptions {
+            allow_insecure: true,
+            ..Default::default()
+        };
+        assert!(parse_webhook(&metadata, &options).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_sign_payload() {
+        // Reference value computed with: printf '1700000000.{}' | openssl dgst -sha256 -hmac 0123456789abcdef
+        assert_eq!(
+            sign_payload("0123456789abcdef", 1700000000, b"{}"),
+            "e4f8e2ecae2295b2ddb2f0b5584c8275e226c0ebe9b3b819e70156bb67122e3e"
+        );
+        assert_ne!(
+            sign_payload("0123456789abcdef", 1700000000, b"{}"),
+            sign_payload("0123456789abcdef", 1700000001, b"{}")
+        );
+    }
+}
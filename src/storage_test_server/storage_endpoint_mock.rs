@@ -4,7 +4,7 @@ use aruna_rust_api::api::{
     internal::v1::{
         internal_authorize_service_server::InternalAuthorizeService,
         internal_event_service_server::InternalEventService, AuthorizeResponse,
-        CreateStreamGroupResponse, GetStreamGroupResponse, StreamGroup,
+        CreateStreamGroupResponse, DeleteStreamGroupResponse, GetStreamGroupResponse, StreamGroup,
     },
     storage::services::v1::{
         resource_info_service_server::ResourceInfoService, GetResourceHierarchyResponse, Hierarchy,
@@ -78,12 +78,21 @@ impl InternalEventService for StorageEndpointMock {
 
     async fn delete_stream_group(
         &self,
-        _request: tonic::Request<aruna_rust_api::api::internal::v1::DeleteStreamGroupRequest>,
+        request: tonic::Request<aruna_rust_api::api::internal::v1::DeleteStreamGroupRequest>,
     ) -> Result<
         tonic::Response<aruna_rust_api::api::internal::v1::DeleteStreamGroupResponse>,
         tonic::Status,
     > {
-        todo!()
+        let inner_request = request.into_inner();
+        match self.stream_groups.lock() {
+            Ok(mut value) => match value.remove(&inner_request.stream_group_id) {
+                Some(_) => {}
+                None => return Err(tonic::Status::not_found("stream group id not found")),
+            },
+            Err(_) => return Err(tonic::Status::internal("error locking stream_group map")),
+        };
+
+        return Ok(Response::new(DeleteStreamGroupResponse {}));
     }
 
     async fn get_shared_revision(
@@ -125,7 +134,11 @@ impl ResourceInfoService for ResourceInfoMock {
     > {
         let inner_request = request.into_inner();
         let hierarchy = match inner_request.resource_type() {
-            aruna_rust_api::api::storage::models::v1::ResourceType::Unspecified => todo!(),
+            aruna_rust_api::api::storage::models::v1::ResourceType::Unspecified => {
+                return Err(tonic::Status::invalid_argument(
+                    "resource type needs to be specified",
+                ))
+            }
             aruna_rust_api::api::storage::models::v1::ResourceType::Project => Hierarchy {
                 project_id: "project_id".to_string(),
                 ..Default::default()
@@ -138,16 +151,36 @@ impl ResourceInfoService for ResourceInfoMock {
             aruna_rust_api::api::storage::models::v1::ResourceType::ObjectGroup => Hierarchy {
                 project_id: "project_id".to_string(),
                 collection_id: "collection_id".to_string(),
-                object_id: "object_id".to_string(),
+                object_group_ids: vec!["shared_object_group_id".to_string()],
                 ..Default::default()
             },
             aruna_rust_api::api::storage::models::v1::ResourceType::Object => Hierarchy {
                 project_id: "project_id".to_string(),
                 collection_id: "collection_id".to_string(),
-                object_id: "object_id".to_string(),
+                object_id: "shared_object_id".to_string(),
                 ..Default::default()
             },
-            aruna_rust_api::api::storage::models::v1::ResourceType::All => todo!(),
+            // All returns the hierarchies of every resource the caller can read
+            aruna_rust_api::api::storage::models::v1::ResourceType::All => {
+                return Ok(Response::new(GetResourceHierarchyResponse {
+                    hierarchies: vec![
+                        Hierarchy {
+                            project_id: "project_id".to_string(),
+                            ..Default::default()
+                        },
+                        Hierarchy {
+                            project_id: "other_project_id".to_string(),
+                            ..Default::default()
+                        },
+                        // Only a single collection of this project is readable
+                        Hierarchy {
+                            project_id: "shared_project_id".to_string(),
+                            collection_id: "readable_collection_id".to_string(),
+                            ..Default::default()
+                        },
+                    ],
+                }));
+            }
         };
 
         return Ok(Response::new(GetResourceHierarchyResponse {
@@ -3,41 +3,270 @@ use aruna_rust_api::api::{
     notification::services::v1::EventType,
     storage::{models::v1::ResourceType, services::v1::Hierarchy},
 };
-use async_nats::jetstream::Message;
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use prost::bytes::Bytes;
+use std::fmt;
+use std::time::Duration;
+
+use crate::utils::utils::ResourcePath;
+
+// The position in the event stream from which a new stream group starts to deliver events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamStartPosition {
+    // All events that are still retained
+    All,
+    // Only events registered after the stream group was created
+    New,
+    // Events starting at the given stream sequence
+    FromSequence(u64),
+    // Events registered at or after the given time
+    FromTimestamp(DateTime<Utc>),
+    // The last retained event of each subject, followed by all new events
+    LastPerSubject,
+}
+
+// Limits of a single fetch of messages from a stream group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FetchOptions {
+    // Maximum number of messages returned by a fetch
+    pub max_messages: usize,
+    // Maximum size of the message payloads returned by a fetch in bytes, 0 means unlimited
+    pub max_bytes: usize,
+    // Maximum time a fetch waits for messages before it returns an empty batch
+    pub max_wait: Duration,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        return FetchOptions {
+            max_messages: 200,
+            max_bytes: 0,
+            max_wait: Duration::from_millis(250),
+        };
+    }
+}
+
+// The webhook of a stream group as it is stored with the stream group in the underlaying system
+// Every instance sharing the underlaying system can resume its delivery from there, e.g. after a restart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredWebhook {
+    pub url: String,
+    pub secret: String,
+    pub fetch_options: FetchOptions,
+    pub disabled: bool,
+}
+
+// Maximum number of dead letters returned when the dead letters of a stream group are listed
+pub const MAX_LISTED_DEAD_LETTERS: usize = 1000;
+
+// A message that was moved out of its stream group after it exceeded the maximum deliveries of the stream group
+// Dead letters are kept until they are requeued to the stream group or purged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter {
+    // Identifies the dead letter within its stream group, ids are ascending in the order messages died
+    pub id: u64,
+    // The subject, sequence and publish time of the original message
+    pub subject: String,
+    pub sequence: u64,
+    pub timestamp: DateTime<Utc>,
+    // The payload of the message, an encoded EventNotificationMessage
+    pub payload: Bytes,
+    // Number of deliveries of the message before it was moved
+    pub deliveries: u64,
+    pub dead_lettered_at: DateTime<Utc>,
+}
+
+// Checks if an event type passes the event type filter of a stream group
+// An empty filter or a filter containing EventType::All passes every event, events of type All pass every filter
+pub fn event_type_matches(event_types: &[EventType], event_type: EventType) -> bool {
+    return event_types.is_empty()
+        || event_type == EventType::All
+        || event_types
+            .iter()
+            .any(|x| *x == EventType::All || *x == event_type);
+}
+
+// Logs the messages of a fetched batch that are delivered again, e.g. after their ack wait expired or a nack
+pub fn log_redeliveries(stream_group_id: &str, msgs: &[Box<dyn EventStreamMessage + Send + Sync>]) {
+    for msg in msgs.iter().filter(|msg| msg.redelivery_count() > 0) {
+        match msg.resource_path() {
+            Ok(resource_path) => log::debug!(
+                "redelivering message {} of {:?} {} to stream group {}, redelivery {}",
+                msg.sequence(),
+                resource_path.resource_type(),
+                resource_path.resource_id(),
+                stream_group_id,
+                msg.redelivery_count()
+            ),
+            Err(err) => log::error!("{}", err),
+        }
+    }
+}
+
+// Error returned if an event could not be published to all of its subjects
+// Lists every failed subject together with the last error returned for it
+#[derive(Debug, Clone)]
+pub struct EventPublishError {
+    pub total_subjects: usize,
+    pub failed_subjects: Vec<(String, String)>,
+}
+
+impl fmt::Display for EventPublishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failed = self
+            .failed_subjects
+            .iter()
+            .map(|(subject, err)| format!("{} ({})", subject, err))
+            .collect::<Vec<String>>()
+            .join(", ");
+        write!(
+            f,
+            "could not publish event to {} of {} subjects: {}",
+            self.failed_subjects.len(),
+            self.total_subjects,
+            failed
+        )
+    }
+}
+
+impl std::error::Error for EventPublishError {}
+
+// Publish failures are transient failures of the event system, emitters are expected to retry
+impl From<EventPublishError> for tonic::Status {
+    fn from(err: EventPublishError) -> Self {
+        return tonic::Status::unavailable(err.to_string());
+    }
+}
+
+// Error returned by stream handlers once their stream group was deleted or expired in the underlaying system
+// Fetching from the stream group again can not succeed, in contrast to other fetch errors
+#[derive(Debug, Clone)]
+pub struct StreamGroupNotFound {
+    pub stream_group_id: String,
+}
+
+impl fmt::Display for StreamGroupNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stream group {} not found", self.stream_group_id)
+    }
+}
+
+impl std::error::Error for StreamGroupNotFound {}
 
 // An Event handler is the main connection of the underlaying event message system like Nats.io
 #[async_trait]
 pub trait EventHandler {
     // Registers an event into the system
+    // Returns only after the event was persisted for every subject
+    // The idempotency key identifies the event, registering an event with a known key within the
+    // duplicate window of the underlaying system has no effect
+    // Returns true if the event was detected as such a duplicate
     async fn register_event(
         &self,
         resource_type: ResourceType,
         resource_id: String,
         event_type: EventType,
         relation: &Relation,
-    ) -> Result<(), tonic::Status>;
+        idempotency_key: &str,
+    ) -> Result<bool, tonic::Status>;
 
     // Creates a stream group
     // A stream group is a entity of the underlaying event streaming system can be used
     // to load balance a set of incoming messages based on an individual query across multiple
     // client
     // This corresponds to a consumer in Nats.io Jetstream https://docs.nats.io/nats-concepts/jetstream
+    // The hierarchies are used to resolve the parent resources of the queried resource
+    // Only events of the given event types are delivered, see event_type_matches
+    // Messages that were delivered max_deliver times without being acknowledged become dead letters,
+    // without max_deliver messages are redelivered until they are acknowledged
+    // A stream group with an inactive threshold is removed by the underlaying system once no stream handler
+    // fetched messages from it or kept it alive for that long, see expired_stream_groups
+    #[allow(clippy::too_many_arguments)]
     async fn create_stream_group(
         &self,
         stream_group_id: String,
-        hierarchy: &Hierarchy,
+        hierarchies: &[Hierarchy],
         resource_type: ResourceType,
         resource_id: String,
         include_subresources: bool,
+        event_types: &[EventType],
+        start_position: StreamStartPosition,
+        max_deliver: Option<u64>,
+        inactive_threshold: Option<Duration>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    // Returns the stream groups the underlaying system removed after their inactive threshold
+    // since the last call, the underlaying system is the only record of the expiry
+    // Each stream group is returned once, also to multiple servers sharing the underlaying system
+    // Stream groups that were deleted explicitly in the meantime may be returned as well
+    async fn expired_stream_groups(
+        &self,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>>;
+
+    // Deletes a stream group, its delivery state and its dead letters in the underlaying system
+    // Deleting a stream group that does not exist is not an error
+    async fn delete_stream_group(
+        &self,
+        stream_group_id: String,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
     // Creates an event stream handler depending on th underlaying system
     // The handler is connected to a stream group to load-balance messages
+    // Each fetch of the handler is limited by the fetch options
     async fn create_event_stream_handler(
         &self,
         stream_group_id: String,
+        fetch_options: FetchOptions,
     ) -> Result<Box<dyn EventStreamHandler + Send + Sync>, Box<dyn std::error::Error + Send + Sync>>;
+
+    // Lists the oldest dead letters of a stream group, at most MAX_LISTED_DEAD_LETTERS
+    async fn list_dead_letters(
+        &self,
+        stream_group_id: String,
+    ) -> Result<Vec<DeadLetter>, Box<dyn std::error::Error + Send + Sync>>;
+
+    // Gets a single dead letter of a stream group, None if the stream group has no dead letter with the id
+    async fn get_dead_letter(
+        &self,
+        stream_group_id: String,
+        dead_letter_id: u64,
+    ) -> Result<Option<DeadLetter>, Box<dyn std::error::Error + Send + Sync>>;
+
+    // Hands a dead letter back to its stream group, where it is delivered again before new messages
+    // The requeued message starts over with its deliveries, returns false if the dead letter does not exist
+    async fn requeue_dead_letter(
+        &self,
+        stream_group_id: String,
+        dead_letter_id: u64,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+
+    // Deletes a single dead letter or all dead letters of a stream group if no id is given
+    // Returns the number of deleted dead letters
+    async fn purge_dead_letters(
+        &self,
+        stream_group_id: String,
+        dead_letter_id: Option<u64>,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>>;
+
+    // Stores the webhook of a stream group with the stream group, replacing a previously stored webhook
+    // Returns StreamGroupNotFound if the stream group does not exist
+    async fn store_webhook(
+        &self,
+        stream_group_id: String,
+        webhook: &StoredWebhook,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    // Gets the stored webhook of a stream group, None if the stream group has no webhook or does not exist
+    async fn get_webhook(
+        &self,
+        stream_group_id: String,
+    ) -> Result<Option<StoredWebhook>, Box<dyn std::error::Error + Send + Sync>>;
+
+    // Lists the stored webhooks of all stream groups by their stream group id
+    async fn list_webhooks(
+        &self,
+    ) -> Result<Vec<(String, StoredWebhook)>, Box<dyn std::error::Error + Send + Sync>>;
 }
 
 // An EventStreamHandler handles the message stream based on StreamGroups
@@ -45,9 +274,100 @@ pub trait EventHandler {
 pub trait EventStreamHandler {
     // Gets a batch of messages from the underlaying event system
     // This call expected to return after a certain timeout even if no messages are available
-    // This is currently specific to nats.io and needs to be generalized
-    // TODO: Generalize
+    // Returns StreamGroupNotFound once the stream group was deleted or expired
     async fn get_stream_group_msgs(
         &self,
-    ) -> Result<Vec<Message>, Box<dyn std::error::Error + Send + Sync>>;
+    ) -> Result<
+        Vec<Box<dyn EventStreamMessage + Send + Sync>>,
+        Box<dyn std::error::Error + Send + Sync>,
+    >;
+
+    // Time after which an unacknowledged message is redelivered by the underlaying event system
+    fn ack_wait(&self) -> Duration;
+
+    // Keeps the stream group from expiring after its inactive threshold without fetching messages
+    // Used while a reader is attached but does not fetch, e.g. while its flow control pauses fetching
+    // Can be called frequently, implementations only contact the underlaying system when needed
+    async fn keep_alive(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+// A single message delivered by an EventStreamHandler
+// Wraps the message type of the underlaying event system so it can be handled without knowing the system
+#[async_trait]
+pub trait EventStreamMessage {
+    // The payload of the message, an encoded EventNotificationMessage
+    fn payload(&self) -> Bytes;
+
+    // The subject the event was published to
+    fn subject(&self) -> String;
+
+    // The resource path the event was published under, parsed from the subject
+    #[allow(clippy::result_large_err)]
+    fn resource_path(&self) -> Result<ResourcePath, tonic::Status>;
+
+    // The sequence of the message in the underlaying event stream
+    // Sequences are unique and ascending in the order the events were stored
+    fn sequence(&self) -> u64;
+
+    // The time the event was stored in the underlaying event system
+    fn timestamp(&self) -> DateTime<Utc>;
+
+    // The number of times this message was delivered before, 0 on the first delivery
+    fn redelivery_count(&self) -> u64;
+
+    // Acknowledges the message against the underlaying event system
+    async fn ack(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    // Negatively acknowledges the message, it will be redelivered after the optional delay
+    async fn nack(
+        &self,
+        delay: Option<Duration>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    // Terminates the message, it will not be redelivered regardless of the remaining deliveries
+    // Used for messages that can not be processed at all
+    async fn terminate(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    // Signals that the message is still processed and resets its redelivery timer
+    async fn in_progress(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+#[cfg(test)]
+mod tests {
+    use aruna_rust_api::api::notification::services::v1::EventType;
+
+    use super::{event_type_matches, EventPublishError};
+
+    #[test]
+    fn test_publish_error_status() {
+        let err = EventPublishError {
+            total_subjects: 2,
+            failed_subjects: vec![(
+                "UPDATES.STORAGE._.project_id._".to_string(),
+                "timed out".to_string(),
+            )],
+        };
+
+        let status: tonic::Status = err.into();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        assert_eq!(
+            status.message(),
+            "could not publish event to 1 of 2 subjects: UPDATES.STORAGE._.project_id._ (timed out)"
+        );
+    }
+
+    #[test]
+    fn test_event_type_matches() {
+        assert!(event_type_matches(&[], EventType::Updated));
+        assert!(event_type_matches(&[EventType::All], EventType::Updated));
+        assert!(event_type_matches(
+            &[EventType::Created, EventType::Updated],
+            EventType::Updated
+        ));
+        assert!(!event_type_matches(
+            &[EventType::Deleted],
+            EventType::Updated
+        ));
+        assert!(event_type_matches(&[EventType::Deleted], EventType::All));
+    }
 }
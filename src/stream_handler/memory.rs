@@ -0,0 +1,1940 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use aruna_rust_api::api::internal::v1::Relation;
+use aruna_rust_api::api::notification::services::v1::{EventNotificationMessage, EventType};
+use aruna_rust_api::api::storage::models::v1::ResourceType;
+use aruna_rust_api::api::storage::services::v1::Hierarchy;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use prost::{bytes::Bytes, Message};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::utils::utils::{NatsIOUtils, ResourcePath};
+
+use super::handler::{
+    event_type_matches, DeadLetter, EventHandler, EventStreamHandler, EventStreamMessage,
+    FetchOptions, StoredWebhook, StreamGroupNotFound, StreamStartPosition, MAX_LISTED_DEAD_LETTERS,
+};
+
+// Time after which an unacknowledged message is delivered again, matches the Jetstream default
+const DEFAULT_ACK_WAIT: Duration = Duration::from_secs(30);
+// Time window in which events with the same message id are dropped, matches the Jetstream default
+const DEFAULT_DUPLICATE_WINDOW: Duration = Duration::from_secs(120);
+
+// The retention of the in-memory event stream, mirrors the limits of NatsIOStreamConfig
+// Like a Jetstream stream with the discard policy old, the oldest events are removed once a limit is reached
+#[derive(Debug, Clone)]
+pub struct MemoryStreamConfig {
+    // Maximum age of stored events, zero means unlimited
+    pub max_age: Duration,
+    // Maximum number of stored events, zero means unlimited
+    pub max_events: usize,
+    // Time window in which events with the same idempotency key are dropped
+    pub duplicate_window: Duration,
+}
+
+impl Default for MemoryStreamConfig {
+    fn default() -> Self {
+        return MemoryStreamConfig {
+            max_age: Duration::ZERO,
+            max_events: 0,
+            duplicate_window: DEFAULT_DUPLICATE_WINDOW,
+        };
+    }
+}
+
+// An in-process event handler that keeps all events in memory
+// It uses the same subject scheme as the Nats.io handler and emulates the relevant Jetstream semantics:
+// Stream groups are durable unless they have an inactive threshold, messages of a stream group are load-balanced
+// across all its stream handlers and messages that are not acknowledged within the ack wait time are delivered again
+// All events are lost on restart, it is intended for tests and single-node deployments
+#[derive(Debug, Clone)]
+pub struct MemoryEventHandler {
+    state: Arc<MemoryState>,
+    ack_wait: Duration,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    stream_config: MemoryStreamConfig,
+    events: Mutex<EventLog>,
+    message_ids: Mutex<MessageIds>,
+    stream_groups: Mutex<HashMap<String, Arc<MemoryStreamGroup>>>,
+    // Stream groups removed after their inactive threshold that were not yet returned by expired_stream_groups
+    expired_stream_groups: Mutex<Vec<String>>,
+    new_events: Notify,
+}
+
+// The stored events in order of their sequence, the oldest events are removed by the retention
+#[derive(Debug, Default)]
+struct EventLog {
+    entries: VecDeque<Arc<StoredEvent>>,
+    last_sequence: u64,
+}
+
+impl EventLog {
+    // Sequence of the oldest stored event, or of the next event if none is stored
+    fn first_sequence(&self) -> u64 {
+        return self.last_sequence + 1 - self.entries.len() as u64;
+    }
+
+    fn get(&self, sequence: u64) -> Option<&Arc<StoredEvent>> {
+        return self
+            .entries
+            .get(sequence.checked_sub(self.first_sequence())? as usize);
+    }
+
+    fn push(&mut self, subject: String, payload: Bytes, event_type: EventType) {
+        self.last_sequence += 1;
+        self.entries.push_back(Arc::new(StoredEvent {
+            sequence: self.last_sequence,
+            subject,
+            payload,
+            event_type,
+            timestamp: Utc::now(),
+        }));
+    }
+
+    // Removes the oldest events that exceed the limits of the stream
+    fn apply_retention(&mut self, stream_config: &MemoryStreamConfig) {
+        if stream_config.max_events > 0 {
+            while self.entries.len() > stream_config.max_events {
+                self.entries.pop_front();
+            }
+        }
+        if !stream_config.max_age.is_zero() {
+            let max_age = match chrono::Duration::from_std(stream_config.max_age) {
+                Ok(value) => value,
+                Err(_) => return,
+            };
+            let oldest_timestamp = Utc::now() - max_age;
+            while let Some(event) = self.entries.front() {
+                if event.timestamp >= oldest_timestamp {
+                    break;
+                }
+                self.entries.pop_front();
+            }
+        }
+    }
+}
+
+// The message ids of the duplicate window, kept in order of their publication
+// Expired ids are removed from the front, so each publish only pays for the ids that expired since the last one
+#[derive(Debug, Default)]
+struct MessageIds {
+    published: VecDeque<(Instant, String)>,
+    ids: HashMap<String, Instant>,
+}
+
+impl MessageIds {
+    // Registers a message id, returns false if it was already registered within the duplicate window
+    fn insert(&mut self, message_id: String, now: Instant, duplicate_window: Duration) -> bool {
+        while let Some((published_at, _)) = self.published.front() {
+            if now.duration_since(*published_at) < duplicate_window {
+                break;
+            }
+            if let Some((published_at, message_id)) = self.published.pop_front() {
+                if self.ids.get(&message_id) == Some(&published_at) {
+                    self.ids.remove(&message_id);
+                }
+            }
+        }
+
+        if self.ids.contains_key(&message_id) {
+            return false;
+        }
+        self.ids.insert(message_id.clone(), now);
+        self.published.push_back((now, message_id));
+        return true;
+    }
+}
+
+#[derive(Debug)]
+struct StoredEvent {
+    sequence: u64,
+    subject: String,
+    payload: Bytes,
+    event_type: EventType,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+struct MemoryStreamGroup {
+    query_subjects: Vec<String>,
+    event_types: Vec<EventType>,
+    start_position: StreamStartPosition,
+    ack_wait: Duration,
+    max_deliver: Option<u64>,
+    inactive_threshold: Option<Duration>,
+    cursor: Mutex<StreamGroupCursor>,
+    dead_letters: Mutex<DeadLetters>,
+    activity: Mutex<StreamGroupActivity>,
+    // Notified when a pending message of the stream group becomes due earlier, e.g. on a nack
+    redeliveries: Notify,
+    // Set when the stream group was deleted, stream handlers of the group stop delivering messages
+    deleted: AtomicBool,
+    webhook: Mutex<Option<StoredWebhook>>,
+}
+
+// The delivery state of a stream group
+// next_sequence is the sequence of the next event that was never delivered to the stream group
+// replay contains events before next_sequence that are delivered first, used to start at the last event per subject
+// pending contains all delivered but unacknowledged events by their sequence
+#[derive(Debug)]
+struct StreamGroupCursor {
+    next_sequence: u64,
+    replay: VecDeque<Arc<StoredEvent>>,
+    pending: BTreeMap<u64, PendingDelivery>,
+}
+
+#[derive(Debug)]
+struct PendingDelivery {
+    event: Arc<StoredEvent>,
+    deliveries: u64,
+    redeliver_at: Instant,
+}
+
+// Running fetches and the end of the last fetch or keep alive of a stream group, used to expire inactive stream groups
+#[derive(Debug)]
+struct StreamGroupActivity {
+    fetches: usize,
+    last_active: Instant,
+}
+
+impl MemoryStreamGroup {
+    // A stream group expires once no fetch was running for its inactive threshold, like an inactive Jetstream consumer
+    fn expired(&self, now: Instant) -> bool {
+        let inactive_threshold = match self.inactive_threshold {
+            Some(value) => value,
+            None => return false,
+        };
+        let activity = self.activity.lock().unwrap();
+        return activity.fetches == 0
+            && now.duration_since(activity.last_active) > inactive_threshold;
+    }
+}
+
+// Marks a stream group as active while a fetch is running, also if the fetch is cancelled
+struct FetchActivity<'a> {
+    stream_group: &'a MemoryStreamGroup,
+}
+
+impl<'a> FetchActivity<'a> {
+    fn start(stream_group: &'a MemoryStreamGroup) -> Self {
+        stream_group.activity.lock().unwrap().fetches += 1;
+        return FetchActivity { stream_group };
+    }
+}
+
+impl Drop for FetchActivity<'_> {
+    fn drop(&mut self) {
+        let mut activity = self.stream_group.activity.lock().unwrap();
+        activity.fetches -= 1;
+        activity.last_active = Instant::now();
+    }
+}
+
+// The dead letters of a stream group by their id
+#[derive(Debug, Default)]
+struct DeadLetters {
+    next_id: u64,
+    entries: BTreeMap<u64, MemoryDeadLetter>,
+}
+
+#[derive(Debug)]
+struct MemoryDeadLetter {
+    event: Arc<StoredEvent>,
+    deliveries: u64,
+    dead_lettered_at: DateTime<Utc>,
+}
+
+impl MemoryDeadLetter {
+    fn to_dead_letter(&self, id: u64) -> DeadLetter {
+        return DeadLetter {
+            id,
+            subject: self.event.subject.clone(),
+            sequence: self.event.sequence,
+            timestamp: self.event.timestamp,
+            payload: self.event.payload.clone(),
+            deliveries: self.deliveries,
+            dead_lettered_at: self.dead_lettered_at,
+        };
+    }
+}
+
+impl MemoryEventHandler {
+    pub fn new() -> Self {
+        return MemoryEventHandler::with_ack_wait(DEFAULT_ACK_WAIT);
+    }
+
+    pub fn with_ack_wait(ack_wait: Duration) -> Self {
+        return MemoryEventHandler {
+            state: Arc::new(MemoryState::default()),
+            ack_wait,
+        };
+    }
+
+    pub fn with_stream_config(stream_config: MemoryStreamConfig) -> Self {
+        return MemoryEventHandler {
+            state: Arc::new(MemoryState {
+                stream_config,
+                ..Default::default()
+            }),
+            ack_wait: DEFAULT_ACK_WAIT,
+        };
+    }
+
+    // Stores an event unless an event with the same message id was stored within the duplicate window
+    // Returns true if the event was a duplicate
+    fn publish(
+        &self,
+        subject: String,
+        payload: Bytes,
+        event_type: EventType,
+        message_id: String,
+    ) -> bool {
+        let inserted = self.state.message_ids.lock().unwrap().insert(
+            message_id,
+            Instant::now(),
+            self.state.stream_config.duplicate_window,
+        );
+        if !inserted {
+            return true;
+        }
+
+        let mut events = self.state.events.lock().unwrap();
+        events.push(subject, payload, event_type);
+        events.apply_retention(&self.state.stream_config);
+
+        return false;
+    }
+
+    fn stream_group(&self, stream_group_id: &str) -> Option<Arc<MemoryStreamGroup>> {
+        return self
+            .state
+            .stream_groups
+            .lock()
+            .unwrap()
+            .get(stream_group_id)
+            .cloned();
+    }
+
+    // Removes all stream groups that exceeded their inactive threshold
+    // Their stream handlers stop delivering messages like for deleted stream groups
+    fn remove_expired_stream_groups(&self) {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        self.state
+            .stream_groups
+            .lock()
+            .unwrap()
+            .retain(|stream_group_id, stream_group| {
+                if stream_group.expired(now) {
+                    stream_group.deleted.store(true, Ordering::Relaxed);
+                    expired.push(stream_group_id.clone());
+                    return false;
+                }
+                return true;
+            });
+        self.state
+            .expired_stream_groups
+            .lock()
+            .unwrap()
+            .extend(expired);
+    }
+}
+
+impl Default for MemoryEventHandler {
+    fn default() -> Self {
+        return MemoryEventHandler::new();
+    }
+}
+
+#[async_trait]
+impl EventHandler for MemoryEventHandler {
+    async fn register_event(
+        &self,
+        resource_type: ResourceType,
+        resource_id: String,
+        event_type: EventType,
+        relation: &Relation,
+        idempotency_key: &str,
+    ) -> Result<bool, tonic::Status> {
+        let message = EventNotificationMessage {
+            resource: resource_type as i32,
+            updated_type: event_type as i32,
+            resource_id: resource_id.clone(),
+        };
+
+        let encoded_msg_bytes = Bytes::from(message.encode_to_vec());
+
+        let subjects = NatsIOUtils::event_subjects(resource_type, resource_id, relation)?;
+        let mut duplicate = true;
+        for subject in subjects {
+            let message_id = NatsIOUtils::message_id(idempotency_key, &subject);
+            duplicate &= self.publish(subject, encoded_msg_bytes.clone(), event_type, message_id);
+        }
+
+        self.state.new_events.notify_waiters();
+
+        return Ok(duplicate);
+    }
+
+    async fn create_stream_group(
+        &self,
+        stream_group_id: String,
+        hierarchies: &[Hierarchy],
+        resource_type: ResourceType,
+        resource_id: String,
+        include_subresources: bool,
+        event_types: &[EventType],
+        start_position: StreamStartPosition,
+        max_deliver: Option<u64>,
+        inactive_threshold: Option<Duration>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.remove_expired_stream_groups();
+
+        let query_subjects = NatsIOUtils::stream_group_queries(
+            hierarchies,
+            resource_type,
+            resource_id,
+            include_subresources,
+        )?;
+
+        let mut stream_groups = self.state.stream_groups.lock().unwrap();
+        if let Some(existing) = stream_groups.get(&stream_group_id) {
+            // Creating an existing stream group is idempotent as long as the query does not change
+            if existing.query_subjects != query_subjects
+                || existing.event_types != event_types
+                || existing.start_position != start_position
+                || existing.max_deliver != max_deliver
+                || existing.inactive_threshold != inactive_threshold
+            {
+                return Err(format!(
+                    "stream group {} already exists with a different query",
+                    stream_group_id
+                )
+                .into());
+            }
+            return Ok(());
+        }
+
+        let mut events = self.state.events.lock().unwrap();
+        events.apply_retention(&self.state.stream_config);
+        let mut replay = VecDeque::new();
+        let next_sequence = match start_position {
+            StreamStartPosition::All => events.first_sequence(),
+            StreamStartPosition::New => events.last_sequence + 1,
+            StreamStartPosition::FromSequence(sequence) => sequence.max(events.first_sequence()),
+            StreamStartPosition::FromTimestamp(timestamp) => {
+                match events
+                    .entries
+                    .iter()
+                    .find(|event| event.timestamp >= timestamp)
+                {
+                    Some(event) => event.sequence,
+                    None => events.last_sequence + 1,
+                }
+            }
+            StreamStartPosition::LastPerSubject => {
+                let mut last_per_subject = HashMap::new();
+                for event in events.entries.iter().filter(|event| {
+                    query_subjects
+                        .iter()
+                        .any(|query| NatsIOUtils::subject_matches(query, &event.subject))
+                        && event_type_matches(event_types, event.event_type)
+                }) {
+                    last_per_subject.insert(event.subject.clone(), event.clone());
+                }
+                let mut last_events: Vec<Arc<StoredEvent>> =
+                    last_per_subject.into_values().collect();
+                last_events.sort_by_key(|event| event.sequence);
+                replay.extend(last_events);
+
+                events.last_sequence + 1
+            }
+        };
+        drop(events);
+
+        stream_groups.insert(
+            stream_group_id,
+            Arc::new(MemoryStreamGroup {
+                query_subjects,
+                event_types: event_types.to_vec(),
+                start_position,
+                ack_wait: self.ack_wait,
+                max_deliver,
+                inactive_threshold,
+                cursor: Mutex::new(StreamGroupCursor {
+                    next_sequence,
+                    replay,
+                    pending: BTreeMap::new(),
+                }),
+                dead_letters: Mutex::new(DeadLetters::default()),
+                activity: Mutex::new(StreamGroupActivity {
+                    fetches: 0,
+                    last_active: Instant::now(),
+                }),
+                redeliveries: Notify::new(),
+                deleted: AtomicBool::new(false),
+                webhook: Mutex::new(None),
+            }),
+        );
+
+        return Ok(());
+    }
+
+    async fn delete_stream_group(
+        &self,
+        stream_group_id: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let stream_group = self
+            .state
+            .stream_groups
+            .lock()
+            .unwrap()
+            .remove(&stream_group_id);
+
+        if let Some(stream_group) = stream_group {
+            stream_group.deleted.store(true, Ordering::Relaxed);
+        }
+
+        return Ok(());
+    }
+
+    async fn expired_stream_groups(
+        &self,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        self.remove_expired_stream_groups();
+        return Ok(std::mem::take(
+            &mut *self.state.expired_stream_groups.lock().unwrap(),
+        ));
+    }
+
+    async fn create_event_stream_handler(
+        &self,
+        stream_group_id: String,
+        fetch_options: FetchOptions,
+    ) -> Result<Box<dyn EventStreamHandler + Send + Sync>, Box<dyn std::error::Error + Send + Sync>>
+    {
+        self.remove_expired_stream_groups();
+
+        let stream_group = match self
+            .state
+            .stream_groups
+            .lock()
+            .unwrap()
+            .get(&stream_group_id)
+        {
+            Some(value) => value.clone(),
+            None => return Err(Box::new(StreamGroupNotFound { stream_group_id })),
+        };
+
+        let stream_handler = Box::new(MemoryEventStreamHandler {
+            stream_group_id,
+            state: self.state.clone(),
+            stream_group,
+            fetch_options,
+        });
+
+        return Ok(stream_handler);
+    }
+
+    async fn list_dead_letters(
+        &self,
+        stream_group_id: String,
+    ) -> Result<Vec<DeadLetter>, Box<dyn std::error::Error + Send + Sync>> {
+        let stream_group = match self.stream_group(&stream_group_id) {
+            Some(value) => value,
+            None => return Ok(Vec::new()),
+        };
+
+        let dead_letters = stream_group.dead_letters.lock().unwrap();
+        return Ok(dead_letters
+            .entries
+            .iter()
+            .take(MAX_LISTED_DEAD_LETTERS)
+            .map(|(id, dead_letter)| dead_letter.to_dead_letter(*id))
+            .collect());
+    }
+
+    async fn get_dead_letter(
+        &self,
+        stream_group_id: String,
+        dead_letter_id: u64,
+    ) -> Result<Option<DeadLetter>, Box<dyn std::error::Error + Send + Sync>> {
+        let stream_group = match self.stream_group(&stream_group_id) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+
+        let dead_letters = stream_group.dead_letters.lock().unwrap();
+        return Ok(dead_letters
+            .entries
+            .get(&dead_letter_id)
+            .map(|dead_letter| dead_letter.to_dead_letter(dead_letter_id)));
+    }
+
+    async fn requeue_dead_letter(
+        &self,
+        stream_group_id: String,
+        dead_letter_id: u64,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let stream_group = match self.stream_group(&stream_group_id) {
+            Some(value) => value,
+            None => return Ok(false),
+        };
+
+        let dead_letter = match stream_group
+            .dead_letters
+            .lock()
+            .unwrap()
+            .entries
+            .remove(&dead_letter_id)
+        {
+            Some(value) => value,
+            None => return Ok(false),
+        };
+
+        // The replay queue is delivered before new events and only to this stream group
+        stream_group
+            .cursor
+            .lock()
+            .unwrap()
+            .replay
+            .push_back(dead_letter.event);
+        self.state.new_events.notify_waiters();
+
+        return Ok(true);
+    }
+
+    async fn purge_dead_letters(
+        &self,
+        stream_group_id: String,
+        dead_letter_id: Option<u64>,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let stream_group = match self.stream_group(&stream_group_id) {
+            Some(value) => value,
+            None => return Ok(0),
+        };
+
+        let mut dead_letters = stream_group.dead_letters.lock().unwrap();
+        let purged = match dead_letter_id {
+            Some(id) => dead_letters.entries.remove(&id).map_or(0, |_| 1),
+            None => {
+                let purged = dead_letters.entries.len() as u64;
+                dead_letters.entries.clear();
+                purged
+            }
+        };
+
+        return Ok(purged);
+    }
+
+    async fn store_webhook(
+        &self,
+        stream_group_id: String,
+        webhook: &StoredWebhook,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let stream_group = match self.stream_group(&stream_group_id) {
+            Some(value) => value,
+            None => return Err(Box::new(StreamGroupNotFound { stream_group_id })),
+        };
+
+        *stream_group.webhook.lock().unwrap() = Some(webhook.clone());
+        return Ok(());
+    }
+
+    async fn get_webhook(
+        &self,
+        stream_group_id: String,
+    ) -> Result<Option<StoredWebhook>, Box<dyn std::error::Error + Send + Sync>> {
+        return Ok(self
+            .stream_group(&stream_group_id)
+            .and_then(|stream_group| stream_group.webhook.lock().unwrap().clone()));
+    }
+
+    async fn list_webhooks(
+        &self,
+    ) -> Result<Vec<(String, StoredWebhook)>, Box<dyn std::error::Error + Send + Sync>> {
+        return Ok(self
+            .state
+            .stream_groups
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(stream_group_id, stream_group)| {
+                let webhook = stream_group.webhook.lock().unwrap().clone()?;
+                Some((stream_group_id.clone(), webhook))
+            })
+            .collect());
+    }
+}
+
+// Stream handler of the in-memory event handler
+// All stream handlers of a stream group share the same cursor, each message is only delivered to one of them
+#[derive(Debug, Clone)]
+pub struct MemoryEventStreamHandler {
+    stream_group_id: String,
+    state: Arc<MemoryState>,
+    stream_group: Arc<MemoryStreamGroup>,
+    fetch_options: FetchOptions,
+}
+
+impl MemoryEventStreamHandler {
+    // Marks an event as pending and wraps it into its first delivered message
+    fn first_delivery(
+        &self,
+        cursor: &mut StreamGroupCursor,
+        event: Arc<StoredEvent>,
+        now: Instant,
+    ) -> Box<dyn EventStreamMessage + Send + Sync> {
+        cursor.pending.insert(
+            event.sequence,
+            PendingDelivery {
+                event: event.clone(),
+                deliveries: 1,
+                redeliver_at: now + self.stream_group.ack_wait,
+            },
+        );
+
+        return Box::new(MemoryMessage {
+            event,
+            stream_group: self.stream_group.clone(),
+            redelivery_count: 0,
+        });
+    }
+
+    // Checks if an event with the given payload size still fits into a batch
+    // The first message of a batch always fits, so oversized events are not stuck
+    fn fits_into_batch(&self, messages: usize, bytes: usize, payload_size: usize) -> bool {
+        if messages >= self.fetch_options.max_messages {
+            return false;
+        }
+
+        return messages == 0
+            || self.fetch_options.max_bytes == 0
+            || bytes + payload_size <= self.fetch_options.max_bytes;
+    }
+
+    // Moves pending messages that are due for redelivery but already reached the max deliveries to the dead letters
+    fn move_to_dead_letters(&self, cursor: &mut StreamGroupCursor, now: Instant) {
+        let max_deliver = match self.stream_group.max_deliver {
+            Some(value) => value,
+            None => return,
+        };
+
+        let exhausted: Vec<u64> = cursor
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.redeliver_at <= now && pending.deliveries >= max_deliver)
+            .map(|(sequence, _)| *sequence)
+            .collect();
+        if exhausted.is_empty() {
+            return;
+        }
+
+        let mut dead_letters = self.stream_group.dead_letters.lock().unwrap();
+        for sequence in exhausted {
+            if let Some(pending) = cursor.pending.remove(&sequence) {
+                dead_letters.next_id += 1;
+                let id = dead_letters.next_id;
+                dead_letters.entries.insert(
+                    id,
+                    MemoryDeadLetter {
+                        event: pending.event,
+                        deliveries: pending.deliveries,
+                        dead_lettered_at: Utc::now(),
+                    },
+                );
+            }
+        }
+    }
+
+    // Collects the next batch of messages
+    // Messages with an expired ack wait are redelivered before new messages are delivered
+    fn next_batch(&self) -> Vec<Box<dyn EventStreamMessage + Send + Sync>> {
+        let now = Instant::now();
+        let mut messages: Vec<Box<dyn EventStreamMessage + Send + Sync>> = Vec::new();
+        let mut bytes = 0;
+        let mut cursor = self.stream_group.cursor.lock().unwrap();
+        self.move_to_dead_letters(&mut cursor, now);
+
+        for pending in cursor.pending.values_mut() {
+            if pending.redeliver_at <= now {
+                if !self.fits_into_batch(messages.len(), bytes, pending.event.payload.len()) {
+                    return messages;
+                }
+                bytes += pending.event.payload.len();
+                pending.redeliver_at = now + self.stream_group.ack_wait;
+                pending.deliveries += 1;
+                messages.push(Box::new(MemoryMessage {
+                    event: pending.event.clone(),
+                    stream_group: self.stream_group.clone(),
+                    redelivery_count: pending.deliveries - 1,
+                }));
+            }
+        }
+
+        // Requeued dead letters pass the same event type filter as new events
+        while let Some(event) = cursor.replay.front().cloned() {
+            if !event_type_matches(&self.stream_group.event_types, event.event_type) {
+                cursor.replay.pop_front();
+                continue;
+            }
+            if !self.fits_into_batch(messages.len(), bytes, event.payload.len()) {
+                return messages;
+            }
+            cursor.replay.pop_front();
+            bytes += event.payload.len();
+            messages.push(self.first_delivery(&mut cursor, event, now));
+        }
+
+        // Events removed by the retention are skipped, like by a Jetstream consumer
+        let mut events = self.state.events.lock().unwrap();
+        events.apply_retention(&self.state.stream_config);
+        cursor.next_sequence = cursor.next_sequence.max(events.first_sequence());
+        while let Some(event) = events.get(cursor.next_sequence).cloned() {
+            if !self
+                .stream_group
+                .query_subjects
+                .iter()
+                .any(|query| NatsIOUtils::subject_matches(query, &event.subject))
+                || !event_type_matches(&self.stream_group.event_types, event.event_type)
+            {
+                cursor.next_sequence += 1;
+                continue;
+            }
+
+            if !self.fits_into_batch(messages.len(), bytes, event.payload.len()) {
+                break;
+            }
+            cursor.next_sequence += 1;
+            bytes += event.payload.len();
+            messages.push(self.first_delivery(&mut cursor, event, now));
+        }
+
+        return messages;
+    }
+
+    // The earliest time a pending message is due for redelivery
+    fn next_redelivery(&self) -> Option<Instant> {
+        let cursor = self.stream_group.cursor.lock().unwrap();
+        return cursor
+            .pending
+            .values()
+            .map(|pending| pending.redeliver_at)
+            .min();
+    }
+}
+
+#[async_trait]
+impl EventStreamHandler for MemoryEventStreamHandler {
+    async fn get_stream_group_msgs(
+        &self,
+    ) -> Result<
+        Vec<Box<dyn EventStreamMessage + Send + Sync>>,
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
+        let expires_at = Instant::now() + self.fetch_options.max_wait;
+        let _activity = FetchActivity::start(&self.stream_group);
+
+        loop {
+            if self.stream_group.deleted.load(Ordering::Relaxed) {
+                return Err(Box::new(StreamGroupNotFound {
+                    stream_group_id: self.stream_group_id.clone(),
+                }));
+            }
+
+            // Register for new events and redeliveries before reading, otherwise one in between would be missed
+            let new_events = self.state.new_events.notified();
+            tokio::pin!(new_events);
+            new_events.as_mut().enable();
+            let redeliveries = self.stream_group.redeliveries.notified();
+            tokio::pin!(redeliveries);
+            redeliveries.as_mut().enable();
+
+            let messages = self.next_batch();
+            if !messages.is_empty() {
+                return Ok(messages);
+            }
+
+            // Pending messages become available again at their redelivery time without a notification
+            let wake_at = match self.next_redelivery() {
+                Some(redeliver_at) => redeliver_at.min(expires_at),
+                None => expires_at,
+            };
+            let notified = async {
+                tokio::select! {
+                    _ = new_events => {}
+                    _ = redeliveries => {}
+                }
+            };
+            if tokio::time::timeout_at(wake_at, notified).await.is_err() && wake_at >= expires_at {
+                return Ok(messages);
+            }
+        }
+    }
+
+    fn ack_wait(&self) -> Duration {
+        return self.stream_group.ack_wait;
+    }
+
+    async fn keep_alive(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if self.stream_group.deleted.load(Ordering::Relaxed) {
+            return Err(Box::new(StreamGroupNotFound {
+                stream_group_id: self.stream_group_id.clone(),
+            }));
+        }
+        self.stream_group.activity.lock().unwrap().last_active = Instant::now();
+        return Ok(());
+    }
+}
+
+// A message delivered by the in-memory event handler
+#[derive(Debug)]
+pub struct MemoryMessage {
+    event: Arc<StoredEvent>,
+    stream_group: Arc<MemoryStreamGroup>,
+    redelivery_count: u64,
+}
+
+impl MemoryMessage {
+    // Moves the redelivery time of the message if it is still pending
+    fn redeliver_at(&self, redeliver_at: Instant) {
+        let mut cursor = self.stream_group.cursor.lock().unwrap();
+        if let Some(pending) = cursor.pending.get_mut(&self.event.sequence) {
+            // Waiting fetches only have to recalculate their wake up time if the message is due earlier
+            let earlier = redeliver_at < pending.redeliver_at;
+            pending.redeliver_at = redeliver_at;
+            if earlier {
+                self.stream_group.redeliveries.notify_waiters();
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EventStreamMessage for MemoryMessage {
+    fn payload(&self) -> Bytes {
+        return self.event.payload.clone();
+    }
+
+    fn subject(&self) -> String {
+        return self.event.subject.clone();
+    }
+
+    fn resource_path(&self) -> Result<ResourcePath, tonic::Status> {
+        return NatsIOUtils::parse_subject(&self.event.subject);
+    }
+
+    fn sequence(&self) -> u64 {
+        return self.event.sequence;
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        return self.event.timestamp;
+    }
+
+    fn redelivery_count(&self) -> u64 {
+        return self.redelivery_count;
+    }
+
+    async fn ack(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut cursor = self.stream_group.cursor.lock().unwrap();
+        cursor.pending.remove(&self.event.sequence);
+        return Ok(());
+    }
+
+    async fn nack(
+        &self,
+        delay: Option<Duration>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.redeliver_at(Instant::now() + delay.unwrap_or_default());
+        return Ok(());
+    }
+
+    // A terminated message is dropped like an acknowledged one, it does not become a dead letter
+    async fn terminate(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut cursor = self.stream_group.cursor.lock().unwrap();
+        cursor.pending.remove(&self.event.sequence);
+        return Ok(());
+    }
+
+    async fn in_progress(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.redeliver_at(Instant::now() + self.stream_group.ack_wait);
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use aruna_rust_api::api::{
+        internal::v1::Relation,
+        notification::services::v1::{EventNotificationMessage, EventType},
+        storage::{models::v1::ResourceType, services::v1::Hierarchy},
+    };
+    use prost::Message;
+    use tokio::time::Instant;
+    use uuid::Uuid;
+
+    use crate::stream_handler::handler::{
+        EventHandler, EventStreamMessage, FetchOptions, StoredWebhook, StreamGroupNotFound,
+        StreamStartPosition,
+    };
+
+    use super::{MemoryEventHandler, MemoryStreamConfig};
+
+    fn project_hierarchy() -> Hierarchy {
+        return Hierarchy {
+            project_id: "project_id".to_string(),
+            ..Default::default()
+        };
+    }
+
+    fn collection_relation() -> Relation {
+        return Relation {
+            project: "project_id".to_string(),
+            ..Default::default()
+        };
+    }
+
+    fn resource_ids(messages: &[Box<dyn EventStreamMessage + Send + Sync>]) -> Vec<String> {
+        return messages
+            .iter()
+            .map(|x| {
+                EventNotificationMessage::decode(x.payload())
+                    .unwrap()
+                    .resource_id
+            })
+            .collect();
+    }
+
+    #[tokio::test]
+    async fn test_hierarchical_matching() {
+        let event_handler = MemoryEventHandler::new();
+
+        event_handler
+            .create_stream_group(
+                "project".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                false,
+                &[EventType::All],
+                StreamStartPosition::All,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        event_handler
+            .create_stream_group(
+                "project_sub".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                true,
+                &[EventType::All],
+                StreamStartPosition::All,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        event_handler
+            .register_event(
+                ResourceType::Project,
+                "project_id".to_string(),
+                EventType::Updated,
+                &Relation::default(),
+                &Uuid::new_v4().to_string(),
+            )
+            .await
+            .unwrap();
+        event_handler
+            .register_event(
+                ResourceType::Collection,
+                "collection_id".to_string(),
+                EventType::Created,
+                &collection_relation(),
+                &Uuid::new_v4().to_string(),
+            )
+            .await
+            .unwrap();
+        event_handler
+            .register_event(
+                ResourceType::Project,
+                "other_project_id".to_string(),
+                EventType::Updated,
+                &Relation::default(),
+                &Uuid::new_v4().to_string(),
+            )
+            .await
+            .unwrap();
+
+        let project_handler = event_handler
+            .create_event_stream_handler("project".to_string(), FetchOptions::default())
+            .await
+            .unwrap();
+        let project_sub_handler = event_handler
+            .create_event_stream_handler("project_sub".to_string(), FetchOptions::default())
+            .await
+            .unwrap();
+
+        let project_msgs = project_handler.get_stream_group_msgs().await.unwrap();
+        let project_sub_msgs = project_sub_handler.get_stream_group_msgs().await.unwrap();
+
+        assert_eq!(resource_ids(&project_msgs), vec!["project_id"]);
+        assert_eq!(
+            resource_ids(&project_sub_msgs),
+            vec!["project_id", "collection_id"]
+        );
+
+        let collection_path = project_sub_msgs[1].resource_path().unwrap();
+        assert_eq!(collection_path.resource_type(), ResourceType::Collection);
+        assert_eq!(collection_path.project_id, "project_id");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_load_balancing_and_redelivery() {
+        let event_handler = MemoryEventHandler::with_ack_wait(Duration::from_millis(100));
+
+        event_handler
+            .create_stream_group(
+                "stream_group".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                true,
+                &[EventType::All],
+                StreamStartPosition::All,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let first_handler = event_handler
+            .create_event_stream_handler("stream_group".to_string(), FetchOptions::default())
+            .await
+            .unwrap();
+        // The fetch wait is shorter than the ack wait, a waiting fetch would otherwise get the redeliveries
+        let second_handler = event_handler
+            .create_event_stream_handler(
+                "stream_group".to_string(),
+                FetchOptions {
+                    max_wait: Duration::from_millis(50),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            event_handler
+                .register_event(
+                    ResourceType::Collection,
+                    "collection_id".to_string(),
+                    EventType::Created,
+                    &collection_relation(),
+                    &Uuid::new_v4().to_string(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let first_msgs = first_handler.get_stream_group_msgs().await.unwrap();
+        assert_eq!(first_msgs.len(), 2);
+
+        // All messages are pending on the first handler, the second one has nothing to do
+        let second_msgs = second_handler.get_stream_group_msgs().await.unwrap();
+        assert!(second_msgs.is_empty());
+
+        first_msgs[0].ack().await.unwrap();
+
+        // The unacknowledged message is redelivered once the ack wait expired
+        tokio::time::advance(Duration::from_millis(150)).await;
+        let redelivered_msgs = second_handler.get_stream_group_msgs().await.unwrap();
+        assert_eq!(redelivered_msgs.len(), 1);
+        redelivered_msgs[0].ack().await.unwrap();
+
+        tokio::time::advance(Duration::from_millis(150)).await;
+        assert!(first_handler
+            .get_stream_group_msgs()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_durable_stream_group() {
+        let event_handler = MemoryEventHandler::new();
+
+        event_handler
+            .create_stream_group(
+                "stream_group".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                false,
+                &[EventType::All],
+                StreamStartPosition::All,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        event_handler
+            .register_event(
+                ResourceType::Project,
+                "project_id".to_string(),
+                EventType::Updated,
+                &Relation::default(),
+                &Uuid::new_v4().to_string(),
+            )
+            .await
+            .unwrap();
+
+        let stream_handler = event_handler
+            .create_event_stream_handler("stream_group".to_string(), FetchOptions::default())
+            .await
+            .unwrap();
+        for msg in stream_handler.get_stream_group_msgs().await.unwrap() {
+            msg.ack().await.unwrap();
+        }
+        drop(stream_handler);
+
+        // Recreating the stream group and its handler keeps the delivery state
+        event_handler
+            .create_stream_group(
+                "stream_group".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                false,
+                &[EventType::All],
+                StreamStartPosition::All,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let stream_handler = event_handler
+            .create_event_stream_handler("stream_group".to_string(), FetchOptions::default())
+            .await
+            .unwrap();
+        assert!(stream_handler
+            .get_stream_group_msgs()
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(event_handler
+            .create_event_stream_handler("unknown".to_string(), FetchOptions::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_message_info_and_nack() {
+        let event_handler = MemoryEventHandler::new();
+
+        event_handler
+            .create_stream_group(
+                "stream_group".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                true,
+                &[EventType::All],
+                StreamStartPosition::All,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            event_handler
+                .register_event(
+                    ResourceType::Project,
+                    "project_id".to_string(),
+                    EventType::Updated,
+                    &Relation::default(),
+                    &Uuid::new_v4().to_string(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let stream_handler = event_handler
+            .create_event_stream_handler("stream_group".to_string(), FetchOptions::default())
+            .await
+            .unwrap();
+
+        let msgs = stream_handler.get_stream_group_msgs().await.unwrap();
+        assert_eq!(
+            msgs.iter().map(|x| x.sequence()).collect::<Vec<u64>>(),
+            vec![1, 2]
+        );
+        assert!(msgs[0].timestamp() <= msgs[1].timestamp());
+        assert_eq!(msgs[0].redelivery_count(), 0);
+
+        // A nack without delay makes the message available again immediately
+        msgs[0].nack(None).await.unwrap();
+        msgs[1].in_progress().await.unwrap();
+
+        let redelivered_msgs = stream_handler.get_stream_group_msgs().await.unwrap();
+        assert_eq!(redelivered_msgs.len(), 1);
+        assert_eq!(redelivered_msgs[0].sequence(), 1);
+        assert_eq!(redelivered_msgs[0].redelivery_count(), 1);
+
+        // A delayed nack holds the message back until the delay expired
+        redelivered_msgs[0]
+            .nack(Some(Duration::from_millis(400)))
+            .await
+            .unwrap();
+        assert!(stream_handler
+            .get_stream_group_msgs()
+            .await
+            .unwrap()
+            .is_empty());
+
+        // A waiting fetch wakes up as soon as the message is due again, not only at the end of its wait
+        let waiting_stream_handler = event_handler
+            .create_event_stream_handler(
+                "stream_group".to_string(),
+                FetchOptions {
+                    max_wait: Duration::from_secs(10),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let fetch_start = Instant::now();
+        assert_eq!(
+            waiting_stream_handler
+                .get_stream_group_msgs()
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(fetch_start.elapsed() < Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_register_event() {
+        let event_handler = MemoryEventHandler::new();
+
+        event_handler
+            .create_stream_group(
+                "stream_group".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                true,
+                &[EventType::All],
+                StreamStartPosition::All,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let mut duplicates = Vec::new();
+        for key in ["key", "key", "other_key"] {
+            duplicates.push(
+                event_handler
+                    .register_event(
+                        ResourceType::Collection,
+                        "collection_id".to_string(),
+                        EventType::Created,
+                        &collection_relation(),
+                        key,
+                    )
+                    .await
+                    .unwrap(),
+            );
+        }
+        assert_eq!(duplicates, vec![false, true, false]);
+
+        let stream_handler = event_handler
+            .create_event_stream_handler("stream_group".to_string(), FetchOptions::default())
+            .await
+            .unwrap();
+        let msgs = stream_handler.get_stream_group_msgs().await.unwrap();
+        assert_eq!(resource_ids(&msgs), vec!["collection_id", "collection_id"]);
+    }
+
+    #[tokio::test]
+    async fn test_retention() {
+        let event_handler = MemoryEventHandler::with_stream_config(MemoryStreamConfig {
+            max_age: Duration::from_millis(100),
+            max_events: 2,
+            duplicate_window: Duration::from_millis(100),
+        });
+        let register_event = |resource_id: &str, idempotency_key: &str| {
+            let event_handler = event_handler.clone();
+            let resource_id = resource_id.to_string();
+            let idempotency_key = idempotency_key.to_string();
+            async move {
+                event_handler
+                    .register_event(
+                        ResourceType::Collection,
+                        resource_id,
+                        EventType::Created,
+                        &collection_relation(),
+                        &idempotency_key,
+                    )
+                    .await
+                    .unwrap()
+            }
+        };
+        let read_all = |stream_group_id: &str| {
+            let event_handler = event_handler.clone();
+            let stream_group_id = stream_group_id.to_string();
+            async move {
+                event_handler
+                    .create_stream_group(
+                        stream_group_id.clone(),
+                        &[project_hierarchy()],
+                        ResourceType::Project,
+                        "project_id".to_string(),
+                        true,
+                        &[EventType::All],
+                        StreamStartPosition::All,
+                        None,
+                        None,
+                    )
+                    .await
+                    .unwrap();
+                let stream_handler = event_handler
+                    .create_event_stream_handler(stream_group_id, FetchOptions::default())
+                    .await
+                    .unwrap();
+                resource_ids(&stream_handler.get_stream_group_msgs().await.unwrap())
+            }
+        };
+
+        // The oldest events are removed once the stream holds more than max events
+        for resource_id in ["first", "second", "third"] {
+            assert!(!register_event(resource_id, resource_id).await);
+        }
+        assert_eq!(read_all("by_count").await, vec!["second", "third"]);
+
+        // Keys are only deduplicated within the duplicate window
+        assert!(register_event("third", "third").await);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(!register_event("fourth", "third").await);
+
+        // Events older than the max age are removed
+        assert_eq!(read_all("by_age").await, vec!["fourth"]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_start_positions() {
+        let event_handler = MemoryEventHandler::new();
+
+        for (resource_type, resource_id, relation) in [
+            (ResourceType::Project, "project_id", Relation::default()),
+            (
+                ResourceType::Collection,
+                "collection_id",
+                collection_relation(),
+            ),
+            (ResourceType::Project, "project_id", Relation::default()),
+        ] {
+            event_handler
+                .register_event(
+                    resource_type,
+                    resource_id.to_string(),
+                    EventType::Updated,
+                    &relation,
+                    &Uuid::new_v4().to_string(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let all_msgs = {
+            event_handler
+                .create_stream_group(
+                    "all".to_string(),
+                    &[project_hierarchy()],
+                    ResourceType::Project,
+                    "project_id".to_string(),
+                    true,
+                    &[EventType::All],
+                    StreamStartPosition::All,
+                    None,
+                    None,
+                )
+                .await
+                .unwrap();
+            let stream_handler = event_handler
+                .create_event_stream_handler("all".to_string(), FetchOptions::default())
+                .await
+                .unwrap();
+            stream_handler.get_stream_group_msgs().await.unwrap()
+        };
+        assert_eq!(
+            all_msgs.iter().map(|x| x.sequence()).collect::<Vec<u64>>(),
+            vec![1, 2, 3]
+        );
+
+        let start_positions = [
+            ("new", StreamStartPosition::New),
+            ("sequence", StreamStartPosition::FromSequence(2)),
+            (
+                "timestamp",
+                StreamStartPosition::FromTimestamp(all_msgs[1].timestamp()),
+            ),
+            ("last_per_subject", StreamStartPosition::LastPerSubject),
+        ];
+        let mut stream_handlers = Vec::new();
+        for (stream_group_id, start_position) in start_positions {
+            event_handler
+                .create_stream_group(
+                    stream_group_id.to_string(),
+                    &[project_hierarchy()],
+                    ResourceType::Project,
+                    "project_id".to_string(),
+                    true,
+                    &[EventType::All],
+                    start_position,
+                    None,
+                    None,
+                )
+                .await
+                .unwrap();
+            stream_handlers.push(
+                event_handler
+                    .create_event_stream_handler(
+                        stream_group_id.to_string(),
+                        FetchOptions::default(),
+                    )
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        let mut sequences = Vec::new();
+        for stream_handler in &stream_handlers {
+            let msgs = stream_handler.get_stream_group_msgs().await.unwrap();
+            sequences.push(msgs.iter().map(|x| x.sequence()).collect::<Vec<u64>>());
+        }
+        assert_eq!(sequences, vec![vec![], vec![2, 3], vec![2, 3], vec![2, 3]]);
+
+        // All stream groups continue with new events
+        event_handler
+            .register_event(
+                ResourceType::Project,
+                "project_id".to_string(),
+                EventType::Deleted,
+                &Relation::default(),
+                &Uuid::new_v4().to_string(),
+            )
+            .await
+            .unwrap();
+        for stream_handler in &stream_handlers {
+            let msgs = stream_handler.get_stream_group_msgs().await.unwrap();
+            assert_eq!(
+                msgs.iter().map(|x| x.sequence()).collect::<Vec<u64>>(),
+                vec![4]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_type_filter() {
+        let event_handler = MemoryEventHandler::new();
+
+        event_handler
+            .create_stream_group(
+                "stream_group".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                true,
+                &[EventType::Created, EventType::Deleted],
+                StreamStartPosition::All,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        for event_type in [EventType::Created, EventType::Updated, EventType::Deleted] {
+            event_handler
+                .register_event(
+                    ResourceType::Project,
+                    "project_id".to_string(),
+                    event_type,
+                    &Relation::default(),
+                    &Uuid::new_v4().to_string(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let stream_handler = event_handler
+            .create_event_stream_handler("stream_group".to_string(), FetchOptions::default())
+            .await
+            .unwrap();
+        let msgs = stream_handler.get_stream_group_msgs().await.unwrap();
+        let event_types = msgs
+            .iter()
+            .map(|x| {
+                EventNotificationMessage::decode(x.payload())
+                    .unwrap()
+                    .updated_type()
+            })
+            .collect::<Vec<EventType>>();
+        assert_eq!(event_types, vec![EventType::Created, EventType::Deleted]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fetch_options() {
+        let event_handler = MemoryEventHandler::new();
+
+        event_handler
+            .create_stream_group(
+                "stream_group".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                true,
+                &[EventType::All],
+                StreamStartPosition::All,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        for _ in 0..5 {
+            event_handler
+                .register_event(
+                    ResourceType::Project,
+                    "project_id".to_string(),
+                    EventType::Updated,
+                    &Relation::default(),
+                    &Uuid::new_v4().to_string(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let payload_size = EventNotificationMessage {
+            resource: ResourceType::Project as i32,
+            updated_type: EventType::Updated as i32,
+            resource_id: "project_id".to_string(),
+        }
+        .encoded_len();
+
+        let count_handler = event_handler
+            .create_event_stream_handler(
+                "stream_group".to_string(),
+                FetchOptions {
+                    max_messages: 2,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let bytes_handler = event_handler
+            .create_event_stream_handler(
+                "stream_group".to_string(),
+                FetchOptions {
+                    max_bytes: payload_size * 3,
+                    max_wait: Duration::from_millis(10),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            count_handler.get_stream_group_msgs().await.unwrap().len(),
+            2
+        );
+        assert_eq!(
+            bytes_handler.get_stream_group_msgs().await.unwrap().len(),
+            3
+        );
+
+        // The max wait bounds an empty fetch
+        let started_at = Instant::now();
+        assert!(bytes_handler
+            .get_stream_group_msgs()
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(started_at.elapsed() < Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dead_letters() {
+        let event_handler = MemoryEventHandler::with_ack_wait(Duration::from_millis(50));
+
+        event_handler
+            .create_stream_group(
+                "stream_group".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                true,
+                &[EventType::All],
+                StreamStartPosition::All,
+                Some(2),
+                None,
+            )
+            .await
+            .unwrap();
+
+        for resource_id in ["first_id", "second_id"] {
+            event_handler
+                .register_event(
+                    ResourceType::Collection,
+                    resource_id.to_string(),
+                    EventType::Updated,
+                    &collection_relation(),
+                    &Uuid::new_v4().to_string(),
+                )
+                .await
+                .unwrap();
+        }
+        let stream_handler = event_handler
+            .create_event_stream_handler("stream_group".to_string(), FetchOptions::default())
+            .await
+            .unwrap();
+
+        // The second message is acknowledged on its last delivery, the first one never
+        for deliveries in 1..=2 {
+            let msgs = stream_handler.get_stream_group_msgs().await.unwrap();
+            assert_eq!(resource_ids(&msgs), vec!["first_id", "second_id"]);
+            if deliveries == 2 {
+                msgs[1].ack().await.unwrap();
+            }
+            tokio::time::advance(Duration::from_millis(60)).await;
+        }
+        assert!(stream_handler
+            .get_stream_group_msgs()
+            .await
+            .unwrap()
+            .is_empty());
+
+        let dead_letters = event_handler
+            .list_dead_letters("stream_group".to_string())
+            .await
+            .unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].deliveries, 2);
+        assert_eq!(
+            EventNotificationMessage::decode(dead_letters[0].payload.clone())
+                .unwrap()
+                .resource_id,
+            "first_id"
+        );
+        assert_eq!(
+            event_handler
+                .get_dead_letter("stream_group".to_string(), dead_letters[0].id)
+                .await
+                .unwrap(),
+            Some(dead_letters[0].clone())
+        );
+
+        // A requeued dead letter is delivered again and starts over with its deliveries
+        assert!(event_handler
+            .requeue_dead_letter("stream_group".to_string(), dead_letters[0].id)
+            .await
+            .unwrap());
+        assert!(!event_handler
+            .requeue_dead_letter("stream_group".to_string(), dead_letters[0].id)
+            .await
+            .unwrap());
+        let msgs = stream_handler.get_stream_group_msgs().await.unwrap();
+        assert_eq!(resource_ids(&msgs), vec!["first_id"]);
+        assert_eq!(msgs[0].redelivery_count(), 0);
+
+        tokio::time::advance(Duration::from_millis(60)).await;
+        assert_eq!(
+            stream_handler.get_stream_group_msgs().await.unwrap().len(),
+            1
+        );
+        tokio::time::advance(Duration::from_millis(60)).await;
+        assert!(stream_handler
+            .get_stream_group_msgs()
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(
+            event_handler
+                .purge_dead_letters("stream_group".to_string(), None)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(event_handler
+            .list_dead_letters("stream_group".to_string())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_inactive_threshold() {
+        let event_handler = MemoryEventHandler::new();
+
+        event_handler
+            .create_stream_group(
+                "stream_group".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                true,
+                &[EventType::All],
+                StreamStartPosition::All,
+                None,
+                Some(Duration::from_millis(100)),
+            )
+            .await
+            .unwrap();
+        let stream_handler = event_handler
+            .create_event_stream_handler(
+                "stream_group".to_string(),
+                FetchOptions {
+                    max_wait: Duration::from_millis(150),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        // A running fetch keeps the stream group active even if it waits longer than the inactive threshold
+        assert!(stream_handler
+            .get_stream_group_msgs()
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(event_handler
+            .expired_stream_groups()
+            .await
+            .unwrap()
+            .is_empty());
+
+        // So do keep alives of a reader that does not fetch
+        for _ in 0..4 {
+            tokio::time::advance(Duration::from_millis(50)).await;
+            stream_handler.keep_alive().await.unwrap();
+        }
+        assert!(event_handler
+            .expired_stream_groups()
+            .await
+            .unwrap()
+            .is_empty());
+
+        // An expired stream group is reported once
+        tokio::time::advance(Duration::from_millis(150)).await;
+        assert_eq!(
+            event_handler.expired_stream_groups().await.unwrap(),
+            vec!["stream_group".to_string()]
+        );
+        assert!(event_handler
+            .expired_stream_groups()
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            stream_handler.get_stream_group_msgs().await,
+            Err(err) if err.is::<StreamGroupNotFound>()
+        ));
+        assert!(stream_handler.keep_alive().await.is_err());
+        assert!(event_handler
+            .create_event_stream_handler("stream_group".to_string(), FetchOptions::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stored_webhooks() {
+        let event_handler = MemoryEventHandler::new();
+        let webhook = StoredWebhook {
+            url: "https://example.com/hook".to_string(),
+            secret: "0123456789abcdef".to_string(),
+            fetch_options: FetchOptions::default(),
+            disabled: false,
+        };
+
+        assert!(event_handler
+            .store_webhook("stream_group".to_string(), &webhook)
+            .await
+            .unwrap_err()
+            .is::<StreamGroupNotFound>());
+
+        event_handler
+            .create_stream_group(
+                "stream_group".to_string(),
+                &[project_hierarchy()],
+                ResourceType::Project,
+                "project_id".to_string(),
+                true,
+                &[],
+                StreamStartPosition::All,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            event_handler
+                .get_webhook("stream_group".to_string())
+                .await
+                .unwrap(),
+            None
+        );
+
+        // A stored webhook replaces the previous one
+        event_handler
+            .store_webhook("stream_group".to_string(), &webhook)
+            .await
+            .unwrap();
+        let disabled_webhook = StoredWebhook {
+            disabled: true,
+            ..webhook
+        };
+        event_handler
+            .store_webhook("stream_group".to_string(), &disabled_webhook)
+            .await
+            .unwrap();
+        assert_eq!(
+            event_handler
+                .get_webhook("stream_group".to_string())
+                .await
+                .unwrap(),
+            Some(disabled_webhook.clone())
+        );
+        assert_eq!(
+            event_handler.list_webhooks().await.unwrap(),
+            vec![("stream_group".to_string(), disabled_webhook)]
+        );
+
+        // The webhook is removed together with its stream group
+        event_handler
+            .delete_stream_group("stream_group".to_string())
+            .await
+            .unwrap();
+        assert!(event_handler.list_webhooks().await.unwrap().is_empty());
+    }
+}
@@ -1,58 +1,621 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use aruna_rust_api::api::storage::models::v1::ResourceType;
 use aruna_rust_api::api::storage::services::v1::Hierarchy;
-use async_nats::jetstream::consumer::Config;
-use async_nats::jetstream::stream::Stream;
+use async_nats::jetstream::consumer::{AckPolicy, Config, DeliverPolicy};
+use async_nats::jetstream::context::{
+    ConsumerInfoError, ConsumerInfoErrorKind, CreateStreamErrorKind, GetStreamErrorKind,
+    PublishError, PublishErrorKind,
+};
+use async_nats::jetstream::message::{PublishMessage, StreamMessage};
+use async_nats::jetstream::stream::{self, DiscardPolicy, RetentionPolicy, StorageType, Stream};
+use async_nats::jetstream::stream::{ConsumerErrorKind, RawMessageErrorKind};
+use async_nats::jetstream::ErrorCode;
 use futures::StreamExt;
 
 use aruna_rust_api::api::internal::v1::Relation;
 use aruna_rust_api::api::notification::services::v1::{EventNotificationMessage, EventType};
 
 use async_nats::{
-    jetstream::{consumer, Context},
-    Client,
+    jetstream::{consumer, AckKind, Context},
+    Client, HeaderMap,
 };
 
 use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
 use prost::{bytes::Bytes, Message};
+use serde::{Deserialize, Serialize};
 
-use crate::utils::utils::NatsIOUtils;
+use crate::utils::utils::{NatsIOUtils, ResourcePath};
 
-use super::handler::{EventHandler, EventStreamHandler};
+use super::handler::{
+    event_type_matches, DeadLetter, EventHandler, EventPublishError, EventStreamHandler,
+    EventStreamMessage, FetchOptions, StoredWebhook, StreamGroupNotFound, StreamStartPosition,
+    MAX_LISTED_DEAD_LETTERS,
+};
 
 const DEFAULT_STREAM_NAME: &str = "STORAGE_UPDATES";
+// Consumer metadata key of the event type filter, stored as comma separated EventType numbers
+const EVENT_TYPES_METADATA_KEY: &str = "event_types";
+// Consumer metadata key of the max deliveries before a message becomes a dead letter
+const MAX_DELIVER_METADATA_KEY: &str = "max_deliver";
+// Consumer metadata key of the webhook of a stream group, stored as json, see WebhookMetadata
+const WEBHOOK_METADATA_KEY: &str = "webhook";
+// Stream that keeps the dead letters of all stream groups
+// Each stream group has a subject for its dead letters and one for requeued dead letters,
+// the latter is read by a consumer of the stream group with the same name
+const DEAD_LETTER_STREAM_NAME: &str = "STORAGE_UPDATES_DEAD_LETTERS";
+const DEAD_LETTER_STREAM_SUBJECT: &str = "DEADLETTER.>";
+// Stream that captures the advisories Jetstream publishes when it deletes a consumer of the event stream,
+// e.g. a stream group after its inactive threshold. It is the record of expired stream groups,
+// advisories are kept until a server processed them, also while no server is running
+const CONSUMER_ADVISORY_STREAM_NAME: &str = "STORAGE_UPDATES_CONSUMER_ADVISORIES";
+// Durable consumer of the advisories shared by all servers, each advisory is processed by a single server
+const EXPIRY_CONSUMER_NAME: &str = "STREAM_GROUP_EXPIRY";
+// Maximum number of advisories processed by a single call of expired_stream_groups
+const MAX_EXPIRED_STREAM_GROUPS: usize = 100;
+// Headers of a dead letter that keep the origin of the message
+const ORIGINAL_SUBJECT_HEADER: &str = "Aruna-Original-Subject";
+const ORIGINAL_SEQUENCE_HEADER: &str = "Aruna-Original-Sequence";
+const ORIGINAL_TIMESTAMP_HEADER: &str = "Aruna-Original-Timestamp";
+const DELIVERIES_HEADER: &str = "Aruna-Deliveries";
+// The duplicate window Jetstream applies to streams without one
+const DEFAULT_DUPLICATE_WINDOW: Duration = Duration::from_secs(120);
+// Number of retries for a failed publish before the event is reported as failed
+const PUBLISH_MAX_RETRIES: u32 = 3;
+// Initial wait time between two publish attempts, doubled on each retry
+const PUBLISH_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+// The desired configuration of the Jetstream stream that stores all events
+// The stream is created on startup if it does not exist, an existing stream is reconciled against it
+#[derive(Debug, Clone)]
+pub struct NatsIOStreamConfig {
+    pub storage: StorageType,
+    pub replicas: usize,
+    // Maximum age of stored events, zero means unlimited
+    pub max_age: Duration,
+    // Maximum size of the stream in bytes, -1 means unlimited
+    pub max_bytes: i64,
+    pub discard: DiscardPolicy,
+    // Time window in which events with the same idempotency key are dropped
+    pub duplicate_window: Duration,
+}
+
+impl Default for NatsIOStreamConfig {
+    fn default() -> Self {
+        return NatsIOStreamConfig {
+            storage: StorageType::File,
+            replicas: 1,
+            max_age: Duration::ZERO,
+            max_bytes: -1,
+            discard: DiscardPolicy::Old,
+            duplicate_window: DEFAULT_DUPLICATE_WINDOW,
+        };
+    }
+}
+
+// The subject of the advisory Jetstream publishes when a consumer of the event stream is deleted
+// Without a consumer name the subject matches the advisories of all consumers
+fn consumer_deleted_subject(consumer: Option<&str>) -> String {
+    return format!(
+        "$JS.EVENT.ADVISORY.CONSUMER.DELETED.{}.{}",
+        DEFAULT_STREAM_NAME,
+        consumer.unwrap_or("*")
+    );
+}
+
+fn dead_letter_subject(stream_group_id: &str) -> String {
+    return format!("DEADLETTER.{}.DEAD", stream_group_id);
+}
+
+fn requeue_subject(stream_group_id: &str) -> String {
+    return format!("DEADLETTER.{}.REQUEUED", stream_group_id);
+}
+
+// Reads the subject, sequence and publish time of the original message from the headers of a dead letter
+fn parse_original_headers(headers: &HeaderMap) -> Option<(String, u64, DateTime<Utc>)> {
+    let subject = headers.get(ORIGINAL_SUBJECT_HEADER)?.as_str().to_string();
+    let sequence = headers
+        .get(ORIGINAL_SEQUENCE_HEADER)?
+        .as_str()
+        .parse()
+        .ok()?;
+    let timestamp = DateTime::parse_from_rfc3339(headers.get(ORIGINAL_TIMESTAMP_HEADER)?.as_str())
+        .ok()?
+        .with_timezone(&Utc);
+    return Some((subject, sequence, timestamp));
+}
+
+// Converts a Jetstream publish time given as unix timestamp and its nanoseconds
+fn publish_time(
+    seconds: i64,
+    nanos: u32,
+) -> Result<DateTime<Utc>, Box<dyn std::error::Error + Send + Sync>> {
+    return match Utc.timestamp_opt(seconds, nanos).single() {
+        Some(value) => Ok(value),
+        None => Err("invalid publish time in message info".into()),
+    };
+}
+
+impl NatsIOStreamConfig {
+    fn stream_config(&self) -> stream::Config {
+        return stream::Config {
+            name: DEFAULT_STREAM_NAME.to_string(),
+            subjects: vec![NatsIOUtils::stream_subject()],
+            storage: self.storage,
+            num_replicas: self.replicas,
+            max_age: self.max_age,
+            max_bytes: self.max_bytes,
+            discard: self.discard,
+            duplicate_window: self.duplicate_window,
+            ..Default::default()
+        };
+    }
+
+    // Dead letters are kept until they are requeued or purged, regardless of the limits of the event stream
+    // Only storage and replicas follow the event stream, the limits are set explicitly so they can be reconciled
+    fn dead_letter_stream_config(&self) -> stream::Config {
+        return stream::Config {
+            name: DEAD_LETTER_STREAM_NAME.to_string(),
+            subjects: vec![DEAD_LETTER_STREAM_SUBJECT.to_string()],
+            storage: self.storage,
+            num_replicas: self.replicas,
+            max_age: Duration::ZERO,
+            max_bytes: -1,
+            discard: DiscardPolicy::Old,
+            duplicate_window: DEFAULT_DUPLICATE_WINDOW,
+            ..Default::default()
+        };
+    }
+
+    // Advisories are removed once they are acknowledged
+    // Like the dead letter stream only storage and replicas follow the event stream
+    fn consumer_advisory_stream_config(&self) -> stream::Config {
+        return stream::Config {
+            name: CONSUMER_ADVISORY_STREAM_NAME.to_string(),
+            subjects: vec![consumer_deleted_subject(None)],
+            retention: RetentionPolicy::WorkQueue,
+            storage: self.storage,
+            num_replicas: self.replicas,
+            max_age: Duration::ZERO,
+            max_bytes: -1,
+            discard: DiscardPolicy::Old,
+            duplicate_window: DEFAULT_DUPLICATE_WINDOW,
+            ..Default::default()
+        };
+    }
+}
+
+// Applies the desired settings to an existing stream configuration
+// The storage type is kept since Jetstream can not change it for an existing stream
+fn reconcile_stream(current: &stream::Config, desired: &stream::Config) -> stream::Config {
+    return stream::Config {
+        subjects: desired.subjects.clone(),
+        num_replicas: desired.num_replicas,
+        max_age: desired.max_age,
+        max_bytes: desired.max_bytes,
+        discard: desired.discard,
+        duplicate_window: desired.duplicate_window,
+        ..current.clone()
+    };
+}
+
+// Lists all differences between an existing stream configuration and the desired one
+fn stream_drift(current: &stream::Config, desired: &stream::Config) -> Vec<String> {
+    let mut drift = Vec::new();
+
+    if current.subjects != desired.subjects {
+        drift.push(format!(
+            "subjects: {:?} != {:?}",
+            current.subjects, desired.subjects
+        ));
+    }
+    if current.storage != desired.storage {
+        drift.push(format!(
+            "storage: {:?} != {:?}",
+            current.storage, desired.storage
+        ));
+    }
+    if current.num_replicas != desired.num_replicas {
+        drift.push(format!(
+            "replicas: {} != {}",
+            current.num_replicas, desired.num_replicas
+        ));
+    }
+    if current.max_age != desired.max_age {
+        drift.push(format!(
+            "max_age: {:?} != {:?}",
+            current.max_age, desired.max_age
+        ));
+    }
+    if current.max_bytes != desired.max_bytes {
+        drift.push(format!(
+            "max_bytes: {} != {}",
+            current.max_bytes, desired.max_bytes
+        ));
+    }
+    if current.discard != desired.discard {
+        drift.push(format!(
+            "discard: {:?} != {:?}",
+            current.discard, desired.discard
+        ));
+    }
+    if current.duplicate_window != desired.duplicate_window {
+        drift.push(format!(
+            "duplicate_window: {:?} != {:?}",
+            current.duplicate_window, desired.duplicate_window
+        ));
+    }
+
+    return drift;
+}
 
 #[derive(Debug, Clone)]
 pub struct NatsIOEventHandler {
     jetstream_context: Context,
     stream: Stream,
+    dead_letter_stream: Stream,
+    expiry_consumer: consumer::PullConsumer,
 }
 
 impl NatsIOEventHandler {
     pub async fn new(
         nats_client: Client,
+        stream_config: NatsIOStreamConfig,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let jetstream_context = async_nats::jetstream::new(nats_client);
-        let stream = jetstream_context.get_stream(DEFAULT_STREAM_NAME).await?;
+        let stream =
+            NatsIOEventHandler::provision_stream(&jetstream_context, stream_config.stream_config())
+                .await?;
+        let dead_letter_stream = NatsIOEventHandler::provision_stream(
+            &jetstream_context,
+            stream_config.dead_letter_stream_config(),
+        )
+        .await?;
+        let consumer_advisory_stream = NatsIOEventHandler::provision_stream(
+            &jetstream_context,
+            stream_config.consumer_advisory_stream_config(),
+        )
+        .await?;
+        let expiry_consumer = consumer_advisory_stream
+            .get_or_create_consumer(
+                EXPIRY_CONSUMER_NAME,
+                consumer::pull::Config {
+                    durable_name: Some(EXPIRY_CONSUMER_NAME.to_string()),
+                    ack_policy: AckPolicy::Explicit,
+                    ..Default::default()
+                },
+            )
+            .await?;
 
         let nats = NatsIOEventHandler {
-            jetstream_context: jetstream_context,
-            stream: stream,
+            jetstream_context,
+            stream,
+            dead_letter_stream,
+            expiry_consumer,
         };
         return Ok(nats);
     }
+
+    // Creates a stream if it is missing or reconciles its configuration with the desired one
+    // Used for the event stream, the dead letter stream and the consumer advisory stream
+    // A stream another instance created in the meantime is reconciled like an existing one
+    async fn provision_stream(
+        jetstream_context: &Context,
+        desired: stream::Config,
+    ) -> Result<Stream, Box<dyn std::error::Error + Send + Sync>> {
+        let name = desired.name.clone();
+        let stream = match jetstream_context.get_stream(name.as_str()).await {
+            Ok(value) => value,
+            Err(err) => match err.kind() {
+                GetStreamErrorKind::JetStream(js_err)
+                    if js_err.error_code() == ErrorCode::STREAM_NOT_FOUND =>
+                {
+                    log::info!("stream {} not found, creating it", name);
+                    match jetstream_context.create_stream(desired.clone()).await {
+                        Ok(value) => return Ok(value),
+                        Err(err) => match err.kind() {
+                            CreateStreamErrorKind::JetStream(js_err)
+                                if js_err.error_code() == ErrorCode::STREAM_NAME_EXIST =>
+                            {
+                                log::info!("stream {} was created by another instance", name);
+                                jetstream_context.get_stream(name.as_str()).await?
+                            }
+                            _ => return Err(Box::new(err)),
+                        },
+                    }
+                }
+                _ => return Err(Box::new(err)),
+            },
+        };
+
+        let current = stream.cached_info().config.clone();
+        let drift = stream_drift(&current, &desired);
+        if drift.is_empty() {
+            return Ok(stream);
+        }
+
+        for entry in drift {
+            log::warn!(
+                "configuration drift of stream {} (current != desired): {}",
+                name,
+                entry
+            );
+        }
+        if current.storage != desired.storage {
+            log::error!(
+                "storage type of stream {} can not be changed, the stream has to be recreated manually",
+                name
+            );
+        }
+
+        jetstream_context
+            .update_stream(reconcile_stream(&current, &desired))
+            .await?;
+        log::info!("reconciled configuration of stream {}", name);
+
+        return Ok(jetstream_context.get_stream(name.as_str()).await?);
+    }
+
+    // Publishes a message to a single subject and waits for Jetstream to acknowledge it
+    // The message id is used by Jetstream to drop duplicates, returns true if the message was one
+    // Publishes that failed for a transient reason are retried with exponential backoff, see is_transient_publish_error,
+    // the subject is returned with the last error when all retries failed or the error is not transient
+    async fn publish_with_retry(
+        &self,
+        subject: String,
+        payload: Bytes,
+        message_id: String,
+    ) -> Result<bool, (String, String)> {
+        let mut backoff = PUBLISH_RETRY_BACKOFF;
+        let mut retries = 0;
+        // Set once a publish was sent but its ack failed, the event may have been stored by that attempt
+        // Jetstream reports a later attempt as duplicate then, although the event was not emitted before
+        let mut may_have_landed = false;
+
+        loop {
+            let publish = PublishMessage::build()
+                .payload(payload.clone())
+                .message_id(message_id.as_str());
+            let result = match self
+                .jetstream_context
+                .send_publish(subject.clone(), publish)
+                .await
+            {
+                Ok(ack_future) => match ack_future.await {
+                    Ok(ack) => Ok(ack.duplicate),
+                    Err(err) => {
+                        may_have_landed = true;
+                        Err(err)
+                    }
+                },
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Ok(duplicate) => return Ok(duplicate && !may_have_landed),
+                Err(err) => {
+                    if retries >= PUBLISH_MAX_RETRIES || !is_transient_publish_error(&err) {
+                        return Err((subject, err.to_string()));
+                    }
+                    log::warn!("retrying publish to {}: {}", subject, err);
+                }
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            retries += 1;
+        }
+    }
+
+    // Reads a dead letter of a stream group, None if the message does not exist or belongs to another subject
+    async fn get_dead_letter_message(
+        &self,
+        stream_group_id: &str,
+        dead_letter_id: u64,
+    ) -> Result<Option<StreamMessage>, Box<dyn std::error::Error + Send + Sync>> {
+        let message = match self
+            .dead_letter_stream
+            .get_raw_message(dead_letter_id)
+            .await
+        {
+            Ok(value) => value,
+            Err(err) => match err.kind() {
+                RawMessageErrorKind::NoMessageFound => return Ok(None),
+                _ => return Err(Box::new(err)),
+            },
+        };
+
+        if message.subject.as_str() != dead_letter_subject(stream_group_id) {
+            return Ok(None);
+        }
+        return Ok(Some(message));
+    }
+}
+
+// Checks if a failed publish may succeed when it is retried
+// Timeouts, a closed connection and too many pending acks are transient,
+// errors reported by the server, e.g. a missing stream, missing permissions or a too large payload, are not
+fn is_transient_publish_error(err: &PublishError) -> bool {
+    return matches!(
+        err.kind(),
+        PublishErrorKind::TimedOut | PublishErrorKind::BrokenPipe | PublishErrorKind::MaxAckPending
+    );
+}
+
+// Deletes a consumer of a stream, a consumer that does not exist is not an error
+async fn delete_consumer(
+    stream: &Stream,
+    name: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match stream.delete_consumer(name).await {
+        Ok(_) => {}
+        Err(err) => match err.kind() {
+            ConsumerErrorKind::JetStream(js_err)
+                if js_err.error_code() == ErrorCode::CONSUMER_NOT_FOUND =>
+            {
+                log::info!("consumer {} not found, nothing to delete", name);
+            }
+            _ => return Err(err.into()),
+        },
+    };
+
+    return Ok(());
+}
+
+// The webhook of a stream group as it is stored in the metadata of its consumer
+#[derive(Debug, Serialize, Deserialize)]
+struct WebhookMetadata {
+    url: String,
+    secret: String,
+    max_messages: usize,
+    max_bytes: usize,
+    max_wait_ms: u64,
+    disabled: bool,
+}
+
+impl From<&StoredWebhook> for WebhookMetadata {
+    fn from(webhook: &StoredWebhook) -> Self {
+        return WebhookMetadata {
+            url: webhook.url.clone(),
+            secret: webhook.secret.clone(),
+            max_messages: webhook.fetch_options.max_messages,
+            max_bytes: webhook.fetch_options.max_bytes,
+            max_wait_ms: webhook.fetch_options.max_wait.as_millis() as u64,
+            disabled: webhook.disabled,
+        };
+    }
+}
+
+impl From<WebhookMetadata> for StoredWebhook {
+    fn from(metadata: WebhookMetadata) -> Self {
+        return StoredWebhook {
+            url: metadata.url,
+            secret: metadata.secret,
+            fetch_options: FetchOptions {
+                max_messages: metadata.max_messages,
+                max_bytes: metadata.max_bytes,
+                max_wait: Duration::from_millis(metadata.max_wait_ms),
+            },
+            disabled: metadata.disabled,
+        };
+    }
+}
+
+// Reads the webhook stored with a consumer, None if the stream group has no webhook
+fn consumer_webhook(
+    info: &consumer::Info,
+) -> Result<Option<StoredWebhook>, Box<dyn std::error::Error + Send + Sync>> {
+    return match info.config.metadata.get(WEBHOOK_METADATA_KEY) {
+        Some(value) => Ok(Some(serde_json::from_str::<WebhookMetadata>(value)?.into())),
+        None => Ok(None),
+    };
+}
+
+// Converts a message of the dead letter stream into a dead letter
+fn to_dead_letter(
+    message: StreamMessage,
+) -> Result<DeadLetter, Box<dyn std::error::Error + Send + Sync>> {
+    let (subject, sequence, timestamp) = match parse_original_headers(&message.headers) {
+        Some(value) => value,
+        None => return Err(format!("dead letter {} has invalid headers", message.sequence).into()),
+    };
+    let deliveries = match message.headers.get(DELIVERIES_HEADER) {
+        Some(value) => value.as_str().parse()?,
+        None => 0,
+    };
+
+    return Ok(DeadLetter {
+        id: message.sequence,
+        subject,
+        sequence,
+        timestamp,
+        payload: message.payload,
+        deliveries,
+        dead_lettered_at: publish_time(message.time.unix_timestamp(), message.time.nanosecond())?,
+    });
 }
 
 #[async_trait]
 impl EventHandler for NatsIOEventHandler {
+    async fn delete_stream_group(
+        &self,
+        stream_group_id: String,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        delete_consumer(&self.stream, &stream_group_id).await?;
+        delete_consumer(&self.dead_letter_stream, &stream_group_id).await?;
+        self.dead_letter_stream
+            .purge()
+            .filter(format!("DEADLETTER.{}.>", stream_group_id))
+            .await?;
+
+        return Ok(());
+    }
+
     async fn create_event_stream_handler(
         &self,
         stream_group_id: String,
+        fetch_options: FetchOptions,
     ) -> Result<Box<dyn EventStreamHandler + Send + Sync>, Box<dyn std::error::Error + Send + Sync>>
     {
-        let consumer = self.stream.get_consumer(stream_group_id.as_str()).await?;
-        let stream_handler = Box::new(NatsIOEventStreamHandler { consumer: consumer });
+        let consumer: consumer::PullConsumer =
+            match self.stream.get_consumer(stream_group_id.as_str()).await {
+                Ok(value) => value,
+                Err(err) => match err.downcast_ref::<ConsumerInfoError>() {
+                    Some(info_err) if info_err.kind() == ConsumerInfoErrorKind::NotFound => {
+                        return Err(Box::new(StreamGroupNotFound { stream_group_id }))
+                    }
+                    _ => return Err(err),
+                },
+            };
+
+        let mut event_types = Vec::new();
+        if let Some(value) = consumer
+            .cached_info()
+            .config
+            .metadata
+            .get(EVENT_TYPES_METADATA_KEY)
+        {
+            for event_type in value.split(',') {
+                match EventType::from_i32(event_type.parse()?) {
+                    Some(value) => event_types.push(value),
+                    None => return Err(format!("unknown event type {}", event_type).into()),
+                }
+            }
+        }
+
+        let max_deliver = match consumer
+            .cached_info()
+            .config
+            .metadata
+            .get(MAX_DELIVER_METADATA_KEY)
+        {
+            Some(value) => Some(value.parse::<u64>()?),
+            None => None,
+        };
+
+        // Only stream groups with max deliveries have dead letters that can be requeued
+        let requeue_consumer = match max_deliver {
+            Some(_) => Some(
+                self.dead_letter_stream
+                    .get_consumer(stream_group_id.as_str())
+                    .await?,
+            ),
+            None => None,
+        };
+
+        let stream_handler = Box::new(NatsIOEventStreamHandler {
+            jetstream_context: self.jetstream_context.clone(),
+            stream_group_id,
+            consumer,
+            requeue_consumer,
+            event_types,
+            max_deliver,
+            fetch_options,
+            last_keep_alive: Arc::new(Mutex::new(Instant::now())),
+        });
 
         return Ok(stream_handler);
     }
@@ -63,7 +626,8 @@ impl EventHandler for NatsIOEventHandler {
         resource_id: String,
         event_type: EventType,
         relation: &Relation,
-    ) -> Result<(), tonic::Status> {
+        idempotency_key: &str,
+    ) -> Result<bool, tonic::Status> {
         let message = EventNotificationMessage {
             resource: resource_type as i32,
             updated_type: event_type as i32,
@@ -73,139 +637,727 @@ impl EventHandler for NatsIOEventHandler {
         let encoded_message = message.encode_to_vec();
         let encoded_msg_bytes = Bytes::from(encoded_message);
 
-        let event_resource = resource_type;
-
-        let subjects = match event_resource {
-            aruna_rust_api::api::storage::models::v1::ResourceType::Unspecified => todo!(),
-            aruna_rust_api::api::storage::models::v1::ResourceType::Project => {
-                vec![NatsIOUtils::project_subject(resource_id)]
-            }
-            aruna_rust_api::api::storage::models::v1::ResourceType::Collection => {
-                vec![NatsIOUtils::collection_subject(
-                    relation.project.clone(),
-                    resource_id,
-                )]
-            }
-            aruna_rust_api::api::storage::models::v1::ResourceType::ObjectGroup => {
-                let mut subjects = Vec::new();
-
-                for object_group in &relation.object_groups {
-                    let subject = NatsIOUtils::object_group_subject(
-                        relation.project.clone(),
-                        relation.collection.clone(),
-                        object_group.shared_object_group_id.clone(),
-                        resource_id.clone(),
-                    );
-                    subjects.push(subject)
-                }
-
-                subjects
-            }
-            aruna_rust_api::api::storage::models::v1::ResourceType::Object => {
-                let mut subjects = Vec::new();
-
-                for object_group in &relation.object_groups {
-                    let subject = NatsIOUtils::object_group_subject(
-                        relation.project.clone(),
-                        relation.collection.clone(),
-                        object_group.shared_object_group_id.clone(),
-                        resource_id.clone(),
-                    );
-                    subjects.push(subject)
-                }
-
-                let object_subject = NatsIOUtils::object_subject(
-                    relation.project.clone(),
-                    relation.collection.clone(),
-                    relation.shared_object.clone(),
-                    resource_id,
-                );
-
-                subjects.push(object_subject);
-
-                subjects
-            }
-            aruna_rust_api::api::storage::models::v1::ResourceType::All => todo!(),
-        };
+        let subjects = NatsIOUtils::event_subjects(resource_type, resource_id, relation)?;
 
-        let publish_futures = subjects
-            .into_iter()
-            .map(|x| self.jetstream_context.publish(x, encoded_msg_bytes.clone()));
+        let total_subjects = subjects.len();
+        let publish_futures = subjects.into_iter().map(|x| {
+            let message_id = NatsIOUtils::message_id(idempotency_key, &x);
+            self.publish_with_retry(x, encoded_msg_bytes.clone(), message_id)
+        });
 
         let results = futures::future::join_all(publish_futures).await;
-        let mut errs = Vec::new();
+        let mut failed_subjects = Vec::new();
+        let mut duplicate = true;
         for result in results {
             match result {
-                Ok(_) => {}
+                Ok(value) => duplicate &= value,
                 Err(err) => {
-                    log::error!("{}", err);
-                    errs.push(err);
+                    log::error!("could not publish to {}: {}", err.0, err.1);
+                    failed_subjects.push(err);
                 }
             }
         }
 
-        return Ok(());
+        if !failed_subjects.is_empty() {
+            return Err(EventPublishError {
+                total_subjects,
+                failed_subjects,
+            }
+            .into());
+        }
+
+        return Ok(duplicate);
     }
 
     async fn create_stream_group(
         &self,
         stream_group_id: String,
-        hierarchy: &Hierarchy,
+        hierarchies: &[Hierarchy],
         resource_type: ResourceType,
         resource_id: String,
         include_subresources: bool,
+        event_types: &[EventType],
+        start_position: StreamStartPosition,
+        max_deliver: Option<u64>,
+        inactive_threshold: Option<Duration>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let stream = self
             .jetstream_context
             .get_stream(DEFAULT_STREAM_NAME)
             .await?;
 
-        let query_subject = match resource_type {
-            ResourceType::Unspecified => todo!(),
-            ResourceType::Project => NatsIOUtils::project_query(resource_id, include_subresources),
-            ResourceType::Collection => NatsIOUtils::collection_query(
-                hierarchy.project_id.clone(),
-                resource_id,
-                include_subresources,
-            ),
-            ResourceType::ObjectGroup => todo!(),
-            ResourceType::Object => todo!(),
-            ResourceType::All => todo!(),
+        let mut query_subjects = NatsIOUtils::stream_group_queries(
+            hierarchies,
+            resource_type,
+            resource_id,
+            include_subresources,
+        )?;
+
+        // Multiple filter subjects are only supported by newer Nats.io servers, a single one is set as before
+        let (filter_subject, filter_subjects) = match query_subjects.len() {
+            1 => (query_subjects.remove(0), Vec::new()),
+            _ => (String::new(), query_subjects),
+        };
+
+        let deliver_policy = match start_position {
+            StreamStartPosition::All => DeliverPolicy::All,
+            StreamStartPosition::New => DeliverPolicy::New,
+            StreamStartPosition::FromSequence(start_sequence) => {
+                DeliverPolicy::ByStartSequence { start_sequence }
+            }
+            StreamStartPosition::FromTimestamp(timestamp) => {
+                let nanos = match timestamp.timestamp_nanos_opt() {
+                    Some(value) => value,
+                    None => return Err("start timestamp out of range".into()),
+                };
+                DeliverPolicy::ByStartTime {
+                    start_time: async_nats::datetime::from_nanos(nanos as i128)?,
+                }
+            }
+            StreamStartPosition::LastPerSubject => DeliverPolicy::LastPerSubject,
+        };
+
+        // The event type is not part of the subject, the filter is stored with the consumer and applied on delivery
+        let mut metadata = HashMap::new();
+        if !event_types.is_empty() {
+            let event_types = event_types
+                .iter()
+                .map(|x| (*x as i32).to_string())
+                .collect::<Vec<String>>()
+                .join(",");
+            metadata.insert(EVENT_TYPES_METADATA_KEY.to_string(), event_types);
+        }
+
+        // Messages are moved to the dead letters by the stream handler, not by Jetstream:
+        // A message that was delivered max_deliver times is only known to be exhausted when it is fetched again,
+        // so Jetstream has to deliver it max_deliver + 1 times. The extra delivery is never handed to a client,
+        // check_deliveries moves the message to the dead letters instead. Jetstream does not deliver a message
+        // after that extra delivery, so a message whose move failed stays unacknowledged and is logged by the fetch
+        let consumer_max_deliver = match max_deliver {
+            Some(value) => {
+                metadata.insert(MAX_DELIVER_METADATA_KEY.to_string(), value.to_string());
+                value as i64 + 1
+            }
+            None => 0,
         };
 
+        // Jetstream removes consumers with an inactive threshold once they had no pull requests for that long
+        // and publishes a deletion advisory, see expired_stream_groups
+        let inactive_threshold = inactive_threshold.unwrap_or_default();
+
         let _consumer = stream
             .create_consumer(Config {
-                name: Some(stream_group_id),
-                filter_subject: query_subject,
+                name: Some(stream_group_id.clone()),
+                deliver_policy,
+                metadata: metadata.clone(),
+                filter_subject,
+                filter_subjects,
+                max_deliver: consumer_max_deliver,
+                inactive_threshold,
                 ..Default::default()
             })
             .await?;
 
+        if max_deliver.is_some() {
+            let _requeue_consumer = self
+                .dead_letter_stream
+                .create_consumer(Config {
+                    name: Some(stream_group_id.clone()),
+                    filter_subject: requeue_subject(&stream_group_id),
+                    metadata,
+                    max_deliver: consumer_max_deliver,
+                    inactive_threshold,
+                    ..Default::default()
+                })
+                .await?;
+        }
+
         return Ok(());
     }
+
+    // Jetstream does not tell apart expired and deleted consumers,
+    // the advisories of stream groups deleted through the api are returned as well
+    async fn expired_stream_groups(
+        &self,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let subject_prefix = consumer_deleted_subject(Some(""));
+        let mut advisories = self
+            .expiry_consumer
+            .fetch()
+            .max_messages(MAX_EXPIRED_STREAM_GROUPS)
+            .messages()
+            .await?;
+
+        // Advisories are acknowledged one by one, so a failure does not lose the stream groups collected before
+        let mut stream_group_ids = Vec::new();
+        while let Some(advisory) = advisories.next().await {
+            let advisory = match advisory {
+                Ok(value) => value,
+                Err(err) => {
+                    log::error!("{}", err);
+                    break;
+                }
+            };
+            if let Some(stream_group_id) = advisory.subject.as_str().strip_prefix(&subject_prefix) {
+                stream_group_ids.push(stream_group_id.to_string());
+            }
+            if let Err(err) = advisory.ack().await {
+                log::error!("{}", err);
+            }
+        }
+
+        return Ok(stream_group_ids);
+    }
+
+    async fn list_dead_letters(
+        &self,
+        stream_group_id: String,
+    ) -> Result<Vec<DeadLetter>, Box<dyn std::error::Error + Send + Sync>> {
+        let subject = dead_letter_subject(&stream_group_id);
+        let mut dead_letters = Vec::new();
+        let mut sequence = 1;
+
+        while dead_letters.len() < MAX_LISTED_DEAD_LETTERS {
+            let message = match self
+                .dead_letter_stream
+                .get_first_raw_message_by_subject(subject.as_str(), sequence)
+                .await
+            {
+                Ok(value) => value,
+                Err(err) => match err.kind() {
+                    RawMessageErrorKind::NoMessageFound => break,
+                    _ => return Err(Box::new(err)),
+                },
+            };
+            sequence = message.sequence + 1;
+            dead_letters.push(to_dead_letter(message)?);
+        }
+
+        return Ok(dead_letters);
+    }
+
+    async fn get_dead_letter(
+        &self,
+        stream_group_id: String,
+        dead_letter_id: u64,
+    ) -> Result<Option<DeadLetter>, Box<dyn std::error::Error + Send + Sync>> {
+        return match self
+            .get_dead_letter_message(&stream_group_id, dead_letter_id)
+            .await?
+        {
+            Some(message) => Ok(Some(to_dead_letter(message)?)),
+            None => Ok(None),
+        };
+    }
+
+    async fn requeue_dead_letter(
+        &self,
+        stream_group_id: String,
+        dead_letter_id: u64,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let message = match self
+            .get_dead_letter_message(&stream_group_id, dead_letter_id)
+            .await?
+        {
+            Some(value) => value,
+            None => return Ok(false),
+        };
+
+        // The requeue subject is only read by the stream group, other stream groups do not see the message again
+        // The dead letter is deleted after the requeued message was stored, so it is never lost in between
+        let publish = PublishMessage::build()
+            .payload(message.payload)
+            .headers(message.headers);
+        self.jetstream_context
+            .send_publish(requeue_subject(&stream_group_id), publish)
+            .await?
+            .await?;
+        self.dead_letter_stream
+            .delete_message(dead_letter_id)
+            .await?;
+
+        return Ok(true);
+    }
+
+    async fn purge_dead_letters(
+        &self,
+        stream_group_id: String,
+        dead_letter_id: Option<u64>,
+    ) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+        let dead_letter_id = match dead_letter_id {
+            Some(value) => value,
+            None => {
+                let response = self
+                    .dead_letter_stream
+                    .purge()
+                    .filter(dead_letter_subject(&stream_group_id))
+                    .await?;
+                return Ok(response.purged);
+            }
+        };
+
+        if self
+            .get_dead_letter_message(&stream_group_id, dead_letter_id)
+            .await?
+            .is_none()
+        {
+            return Ok(0);
+        }
+        self.dead_letter_stream
+            .delete_message(dead_letter_id)
+            .await?;
+
+        return Ok(1);
+    }
+
+    // The webhook is stored in the metadata of the consumer, so it is removed together with the consumer
+    async fn store_webhook(
+        &self,
+        stream_group_id: String,
+        webhook: &StoredWebhook,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let info = match self.stream.consumer_info(&stream_group_id).await {
+            Ok(value) => value,
+            Err(err) if err.kind() == ConsumerInfoErrorKind::NotFound => {
+                return Err(Box::new(StreamGroupNotFound { stream_group_id }))
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut config = info.config;
+        config.metadata.insert(
+            WEBHOOK_METADATA_KEY.to_string(),
+            serde_json::to_string(&WebhookMetadata::from(webhook))?,
+        );
+        let _consumer = self.stream.update_consumer(config).await?;
+
+        return Ok(());
+    }
+
+    async fn get_webhook(
+        &self,
+        stream_group_id: String,
+    ) -> Result<Option<StoredWebhook>, Box<dyn std::error::Error + Send + Sync>> {
+        return match self.stream.consumer_info(&stream_group_id).await {
+            Ok(info) => consumer_webhook(&info),
+            Err(err) if err.kind() == ConsumerInfoErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        };
+    }
+
+    async fn list_webhooks(
+        &self,
+    ) -> Result<Vec<(String, StoredWebhook)>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut webhooks = Vec::new();
+        let mut consumers = self.stream.consumers();
+        while let Some(info) = consumers.next().await {
+            let info = info?;
+            // A single unreadable webhook must not keep the others from being resumed
+            match consumer_webhook(&info) {
+                Ok(Some(webhook)) => webhooks.push((info.name.clone(), webhook)),
+                Ok(None) => {}
+                Err(err) => log::error!("webhook of stream group {}: {}", info.name, err),
+            }
+        }
+
+        return Ok(webhooks);
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct NatsIOEventStreamHandler {
+    pub jetstream_context: Context,
+    pub stream_group_id: String,
     pub consumer: consumer::PullConsumer,
+    // Consumer of the requeued dead letters of the stream group, only set if the stream group has max deliveries
+    pub requeue_consumer: Option<consumer::PullConsumer>,
+    // Event type filter of the stream group, messages of other types are acknowledged without delivery
+    pub event_types: Vec<EventType>,
+    pub max_deliver: Option<u64>,
+    pub fetch_options: FetchOptions,
+    // Time of the last pull request sent by keep_alive
+    pub last_keep_alive: Arc<Mutex<Instant>>,
+}
+
+impl NatsIOEventStreamHandler {
+    // Moves a message to the dead letters of the stream group if it reached the max deliveries before
+    // Returns the message if it can be delivered
+    async fn check_deliveries(
+        &self,
+        message: NatsIOMessage,
+    ) -> Result<Option<NatsIOMessage>, Box<dyn std::error::Error + Send + Sync>> {
+        let max_deliver = match self.max_deliver {
+            Some(value) => value,
+            None => return Ok(Some(message)),
+        };
+        if message.redelivery_count < max_deliver {
+            return Ok(Some(message));
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ORIGINAL_SUBJECT_HEADER, message.subject());
+        headers.insert(ORIGINAL_SEQUENCE_HEADER, message.sequence().to_string());
+        headers.insert(ORIGINAL_TIMESTAMP_HEADER, message.timestamp().to_rfc3339());
+        headers.insert(DELIVERIES_HEADER, message.redelivery_count.to_string());
+        let publish = PublishMessage::build()
+            .payload(message.payload())
+            .headers(headers);
+        self.jetstream_context
+            .send_publish(dead_letter_subject(&self.stream_group_id), publish)
+            .await?
+            .await?;
+        message.terminate().await?;
+
+        return Ok(None);
+    }
+
+    // Returns a fetched message if it can be delivered to the stream group, see check_deliveries
+    // Events of a type the stream group does not read are acknowledged and dropped
+    // Used for fetched events and requeued dead letters alike
+    async fn deliverable_message(
+        &self,
+        message: NatsIOMessage,
+    ) -> Result<Option<NatsIOMessage>, Box<dyn std::error::Error + Send + Sync>> {
+        let message = match self.check_deliveries(message).await? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        if let Ok(event) = EventNotificationMessage::decode(message.payload()) {
+            if !event_type_matches(&self.event_types, event.updated_type()) {
+                message.ack().await?;
+                return Ok(None);
+            }
+        }
+
+        return Ok(Some(message));
+    }
+
+    // A failed fetch does not tell whether the consumer of the stream group still exists, so it is looked up
+    async fn consumer_deleted(&self) -> bool {
+        return match self.consumer.get_info().await {
+            Ok(_) => false,
+            Err(err) => err.kind() == ConsumerInfoErrorKind::NotFound,
+        };
+    }
 }
 
 #[async_trait]
 impl EventStreamHandler for NatsIOEventStreamHandler {
     async fn get_stream_group_msgs(
         &self,
-    ) -> Result<Vec<async_nats::jetstream::Message>, Box<dyn std::error::Error + Send + Sync>> {
+    ) -> Result<
+        Vec<Box<dyn EventStreamMessage + Send + Sync>>,
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
+        let mut messages: Vec<Box<dyn EventStreamMessage + Send + Sync>> = Vec::new();
+
+        // Requeued dead letters are delivered first, the fetch returns immediately if there are none
+        if let Some(requeue_consumer) = &self.requeue_consumer {
+            let mut requeued = requeue_consumer
+                .fetch()
+                .max_messages(self.fetch_options.max_messages)
+                .max_bytes(self.fetch_options.max_bytes)
+                .messages()
+                .await?;
+            while let Some(Ok(message)) = requeued.next().await {
+                let message = match NatsIOMessage::new(message).await {
+                    Some(value) => value,
+                    None => continue,
+                };
+                if let Some(message) = self.deliverable_message(message).await? {
+                    messages.push(Box::new(message));
+                }
+            }
+            if !messages.is_empty() {
+                return Ok(messages);
+            }
+        }
+
         let mut batch = self
             .consumer
             .batch()
-            .expires(Duration::from_millis(250))
+            .max_messages(self.fetch_options.max_messages)
+            .max_bytes(self.fetch_options.max_bytes)
+            .expires(self.fetch_options.max_wait)
             .messages()
             .await?;
-        let mut messages = Vec::new();
-        while let Some(Ok(message)) = batch.next().await {
-            messages.push(message);
+        while let Some(message) = batch.next().await {
+            // Other errors end the batch, the messages fetched so far are still delivered
+            let message = match message {
+                Ok(value) => value,
+                Err(err) => {
+                    if self.consumer_deleted().await {
+                        return Err(Box::new(StreamGroupNotFound {
+                            stream_group_id: self.stream_group_id.clone(),
+                        }));
+                    }
+                    log::error!("{}", err);
+                    break;
+                }
+            };
+            let message = match NatsIOMessage::new(message).await {
+                Some(value) => value,
+                None => continue,
+            };
+            if let Some(message) = self.deliverable_message(message).await? {
+                messages.push(Box::new(message));
+            }
         }
 
         Ok(messages)
     }
+
+    fn ack_wait(&self) -> Duration {
+        return self.consumer.cached_info().config.ack_wait;
+    }
+
+    // Sends pull requests often enough to keep the consumers from reaching their inactive threshold
+    async fn keep_alive(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let inactive_threshold = self.consumer.cached_info().config.inactive_threshold;
+        if inactive_threshold.is_zero() {
+            return Ok(());
+        }
+        {
+            let mut last_keep_alive = self.last_keep_alive.lock().unwrap();
+            if last_keep_alive.elapsed() < inactive_threshold / 4 {
+                return Ok(());
+            }
+            *last_keep_alive = Instant::now();
+        }
+
+        keep_alive_pull(&self.consumer).await?;
+        if let Some(requeue_consumer) = &self.requeue_consumer {
+            keep_alive_pull(requeue_consumer).await?;
+        }
+
+        return Ok(());
+    }
+}
+
+// Sends a pull request that counts as activity of the consumer but does not deliver a message
+// Every event is larger than the byte limit of one, Jetstream answers without a message then
+// A message that is delivered nevertheless is handed back right away
+async fn keep_alive_pull(
+    consumer: &consumer::PullConsumer,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut messages = consumer
+        .fetch()
+        .max_messages(1)
+        .max_bytes(1)
+        .messages()
+        .await?;
+    while let Some(message) = messages.next().await {
+        if let Ok(message) = message {
+            message.ack_with(AckKind::Nak(None)).await?;
+        }
+    }
+
+    return Ok(());
+}
+
+// A Jetstream message together with its parsed metadata
+// Requeued dead letters carry the subject, sequence and publish time of the original message
+#[derive(Debug)]
+pub struct NatsIOMessage {
+    message: async_nats::jetstream::Message,
+    subject: String,
+    sequence: u64,
+    timestamp: DateTime<Utc>,
+    redelivery_count: u64,
+}
+
+impl NatsIOMessage {
+    // Wraps a fetched message, None if its metadata can not be read
+    // Such a message can never be delivered, it is terminated and the fetch continues with the next one
+    pub async fn new(message: async_nats::jetstream::Message) -> Option<Self> {
+        let (subject, sequence, timestamp, redelivery_count) = match message_metadata(&message) {
+            Ok(value) => value,
+            Err(err) => {
+                log::error!(
+                    "terminating unreadable message on {}: {}",
+                    message.subject,
+                    err
+                );
+                if let Err(err) = message.ack_with(AckKind::Term).await {
+                    log::error!("{}", err);
+                }
+                return None;
+            }
+        };
+
+        return Some(NatsIOMessage {
+            message,
+            subject,
+            sequence,
+            timestamp,
+            redelivery_count,
+        });
+    }
+}
+
+// Subject, sequence, publish time and redelivery count of a fetched message
+type MessageMetadata = (String, u64, DateTime<Utc>, u64);
+
+fn message_metadata(
+    message: &async_nats::jetstream::Message,
+) -> Result<MessageMetadata, Box<dyn std::error::Error + Send + Sync>> {
+    let info = message.info()?;
+    let redelivery_count = (info.delivered.max(1) - 1) as u64;
+    let (subject, sequence, timestamp) =
+        match message.headers.as_ref().and_then(parse_original_headers) {
+            Some(value) => value,
+            None => (
+                message.subject.to_string(),
+                info.stream_sequence,
+                publish_time(info.published.unix_timestamp(), info.published.nanosecond())?,
+            ),
+        };
+
+    return Ok((subject, sequence, timestamp, redelivery_count));
+}
+
+#[async_trait]
+impl EventStreamMessage for NatsIOMessage {
+    fn payload(&self) -> Bytes {
+        return self.message.payload.clone();
+    }
+
+    fn subject(&self) -> String {
+        return self.subject.clone();
+    }
+
+    fn resource_path(&self) -> Result<ResourcePath, tonic::Status> {
+        return NatsIOUtils::parse_subject(&self.subject);
+    }
+
+    fn sequence(&self) -> u64 {
+        return self.sequence;
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        return self.timestamp;
+    }
+
+    fn redelivery_count(&self) -> u64 {
+        return self.redelivery_count;
+    }
+
+    async fn ack(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.message.ack().await?;
+        return Ok(());
+    }
+
+    async fn nack(
+        &self,
+        delay: Option<Duration>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.message.ack_with(AckKind::Nak(delay)).await?;
+        return Ok(());
+    }
+
+    async fn terminate(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.message.ack_with(AckKind::Term).await?;
+        return Ok(());
+    }
+
+    async fn in_progress(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.message.ack_with(AckKind::Progress).await?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_nats::jetstream::context::{PublishError, PublishErrorKind};
+    use async_nats::jetstream::stream::{self, DiscardPolicy, StorageType};
+
+    use super::{is_transient_publish_error, reconcile_stream, stream_drift, NatsIOStreamConfig};
+
+    #[test]
+    fn test_stream_config_reconciliation() {
+        let stream_config = NatsIOStreamConfig {
+            max_age: Duration::from_secs(3600),
+            ..Default::default()
+        };
+
+        let current = stream::Config {
+            name: "STORAGE_UPDATES".to_string(),
+            subjects: vec!["UPDATES.STORAGE.>".to_string()],
+            storage: StorageType::Memory,
+            num_replicas: 1,
+            max_bytes: -1,
+            discard: DiscardPolicy::New,
+            duplicate_window: Duration::from_secs(120),
+            ..Default::default()
+        };
+
+        let desired = stream_config.stream_config();
+        assert_eq!(
+            stream_drift(&current, &desired),
+            vec![
+                "storage: Memory != File",
+                "max_age: 0ns != 3600s",
+                "discard: New != Old"
+            ]
+        );
+
+        let reconciled = reconcile_stream(&current, &desired);
+        assert!(stream_drift(&reconciled, &desired)
+            .iter()
+            .all(|x| x.starts_with("storage")));
+        assert_eq!(reconciled.storage, StorageType::Memory);
+        assert_eq!(reconciled.duplicate_window, Duration::from_secs(120));
+
+        assert!(stream_drift(&desired, &desired).is_empty());
+    }
+
+    #[test]
+    fn test_dead_letter_stream_config_reconciliation() {
+        let stream_config = NatsIOStreamConfig {
+            max_age: Duration::from_secs(3600),
+            replicas: 3,
+            ..Default::default()
+        };
+        let desired = stream_config.dead_letter_stream_config();
+
+        // A dead letter stream created with the defaults of an earlier version
+        let current = stream::Config {
+            name: "STORAGE_UPDATES_DEAD_LETTERS".to_string(),
+            subjects: vec!["DEADLETTER.>".to_string()],
+            storage: StorageType::File,
+            num_replicas: 1,
+            max_age: Duration::from_secs(3600),
+            max_bytes: -1,
+            duplicate_window: Duration::from_secs(120),
+            ..Default::default()
+        };
+
+        // The limits of the event stream do not apply to dead letters, only its replicas do
+        assert_eq!(
+            stream_drift(&current, &desired),
+            vec!["replicas: 1 != 3", "max_age: 3600s != 0ns"]
+        );
+        assert!(stream_drift(&reconcile_stream(&current, &desired), &desired).is_empty());
+    }
+
+    #[test]
+    fn test_transient_publish_errors() {
+        for kind in [
+            PublishErrorKind::TimedOut,
+            PublishErrorKind::BrokenPipe,
+            PublishErrorKind::MaxAckPending,
+        ] {
+            assert!(is_transient_publish_error(&PublishError::new(kind)));
+        }
+        for kind in [
+            PublishErrorKind::StreamNotFound,
+            PublishErrorKind::MaxPayloadExceeded,
+            PublishErrorKind::WrongLastMessageId,
+            PublishErrorKind::Other,
+        ] {
+            assert!(!is_transient_publish_error(&PublishError::new(kind)));
+        }
+    }
 }
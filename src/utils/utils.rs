@@ -1,7 +1,62 @@
+use aruna_rust_api::api::{
+    internal::v1::Relation,
+    storage::{models::v1::ResourceType, services::v1::Hierarchy},
+};
+
 const STREAM_SUBJECT_COMMMON_PREFIX: &str = "UPDATES.STORAGE";
 const STREAM_SUBJECT_OBJECT_NAME: &str = "OBJECT";
 const STREAM_SUBJECT_OBJECT_GROUP_NAME: &str = "OBJECTGROUP";
 
+// The resource an event subject was published under, parsed from the subject
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourcePath {
+    pub project_id: String,
+    pub collection_id: Option<String>,
+    pub shared_resource: Option<SharedResourcePath>,
+}
+
+// The object or object group part of a subject
+// Indicates whether an object event arrived via the object path or via one of its object groups
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SharedResourcePath {
+    Object {
+        shared_object_id: String,
+        object_id: String,
+    },
+    ObjectGroup {
+        shared_object_group_id: String,
+        object_group_id: String,
+    },
+}
+
+impl ResourcePath {
+    // The type of the deepest resource of the path
+    pub fn resource_type(&self) -> ResourceType {
+        return match (&self.collection_id, &self.shared_resource) {
+            (_, Some(SharedResourcePath::Object { .. })) => ResourceType::Object,
+            (_, Some(SharedResourcePath::ObjectGroup { .. })) => ResourceType::ObjectGroup,
+            (Some(_), None) => ResourceType::Collection,
+            (None, None) => ResourceType::Project,
+        };
+    }
+
+    // The id of the deepest resource of the path
+    // Object events published to an object group subject carry the object id here
+    pub fn resource_id(&self) -> &str {
+        return match (&self.collection_id, &self.shared_resource) {
+            (_, Some(SharedResourcePath::Object { object_id, .. })) => object_id,
+            (
+                _,
+                Some(SharedResourcePath::ObjectGroup {
+                    object_group_id, ..
+                }),
+            ) => object_group_id,
+            (Some(collection_id), None) => collection_id,
+            (None, None) => &self.project_id,
+        };
+    }
+}
+
 // Utility functions for Nats.io
 pub struct NatsIOUtils {}
 
@@ -13,10 +68,12 @@ impl NatsIOUtils {
     // This is required for proper queries
     // Between a collection and an SHAREDOBJECT|SHAREDOBJECTGROUP id there is an additional denominator that indicates if
     // the following id is and SHAREDOBJECT or SHAREDOBJECTGROUP id. The denominator is simply the resource name splitted by ._.
-    fn base_subject(ids: Vec<String>, is_object_group: bool) -> String {
-        let mut stage = 0;
-        let mut base_subject = format!("{}", STREAM_SUBJECT_COMMMON_PREFIX);
-        for id in ids {
+    // Every id is validated before it is added, see validate_id
+    #[allow(clippy::result_large_err)]
+    fn base_subject(ids: Vec<String>, is_object_group: bool) -> Result<String, tonic::Status> {
+        let mut base_subject = STREAM_SUBJECT_COMMMON_PREFIX.to_string();
+        for (stage, id) in ids.into_iter().enumerate() {
+            NatsIOUtils::validate_id(&id)?;
             if stage == 2 {
                 if is_object_group {
                     base_subject =
@@ -26,9 +83,48 @@ impl NatsIOUtils {
                 }
             }
             base_subject = format!("{}._.{}", base_subject, id);
-            stage += 1;
         }
-        return base_subject;
+        return Ok(base_subject);
+    }
+
+    // Checks that an id can be used as a single token of a subject
+    // Ids must not be empty, must not contain the token separator ., the wildcards * and >
+    // or whitespace and must not be the id separator _ itself
+    // Otherwise an id could corrupt the subject or turn a query into a wildcard that matches other resources
+    #[allow(clippy::result_large_err)]
+    pub fn validate_id(id: &str) -> Result<(), tonic::Status> {
+        if id.is_empty() {
+            return Err(tonic::Status::invalid_argument(
+                "resource id must not be empty",
+            ));
+        }
+        if id == "_" {
+            return Err(tonic::Status::invalid_argument(format!(
+                "invalid resource id {:?}",
+                id
+            )));
+        }
+        if id
+            .chars()
+            .any(|x| x == '.' || x == '*' || x == '>' || x.is_whitespace() || x.is_control())
+        {
+            return Err(tonic::Status::invalid_argument(format!(
+                "invalid resource id {:?}, ids must not contain '.', '*', '>' or whitespace",
+                id
+            )));
+        }
+
+        return Ok(());
+    }
+
+    // The subject that captures all events, used to configure the event stream
+    pub fn stream_subject() -> String {
+        return format!("{}.>", STREAM_SUBJECT_COMMMON_PREFIX);
+    }
+
+    // Creates the message id used to deduplicate an event on a single subject
+    pub fn message_id(idempotency_key: &str, subject: &str) -> String {
+        return format!("{}:{}", idempotency_key, subject);
     }
 
     // Turns a subject into a query, depending on whether subresources are included or not
@@ -41,75 +137,88 @@ impl NatsIOUtils {
         return query;
     }
 
-    pub fn project_subject(project_id: String) -> String {
-        let subject = format!("{}._", NatsIOUtils::base_subject(vec![project_id], false));
-        return subject;
+    #[allow(clippy::result_large_err)]
+    pub fn project_subject(project_id: String) -> Result<String, tonic::Status> {
+        let subject = format!("{}._", NatsIOUtils::base_subject(vec![project_id], false)?);
+        return Ok(subject);
     }
 
-    pub fn project_query(project_id: String, include_subresources: bool) -> String {
-        let base_subject = NatsIOUtils::base_subject(vec![project_id], false);
+    #[allow(clippy::result_large_err)]
+    pub fn project_query(
+        project_id: String,
+        include_subresources: bool,
+    ) -> Result<String, tonic::Status> {
+        let base_subject = NatsIOUtils::base_subject(vec![project_id], false)?;
         let query = NatsIOUtils::query(base_subject, include_subresources);
 
-        return query;
+        return Ok(query);
     }
 
-    pub fn collection_subject(project_id: String, collection_id: String) -> String {
+    #[allow(clippy::result_large_err)]
+    pub fn collection_subject(
+        project_id: String,
+        collection_id: String,
+    ) -> Result<String, tonic::Status> {
         let subject = format!(
             "{}._",
-            NatsIOUtils::base_subject(vec![project_id, collection_id], false)
+            NatsIOUtils::base_subject(vec![project_id, collection_id], false)?
         );
-        return subject;
+        return Ok(subject);
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn collection_query(
         project_id: String,
         collection_id: String,
         include_subresources: bool,
-    ) -> String {
-        let base_subject = NatsIOUtils::base_subject(vec![project_id, collection_id], false);
+    ) -> Result<String, tonic::Status> {
+        let base_subject = NatsIOUtils::base_subject(vec![project_id, collection_id], false)?;
         let query = NatsIOUtils::query(base_subject, include_subresources);
 
-        return query;
+        return Ok(query);
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn object_subject(
         project_id: String,
         collection_id: String,
         shared_object_id: String,
         object_id: String,
-    ) -> String {
+    ) -> Result<String, tonic::Status> {
         let subject = format!(
             "{}._",
             NatsIOUtils::base_subject(
                 vec![project_id, collection_id, shared_object_id, object_id],
                 false
-            )
+            )?
         );
-        return subject;
+        return Ok(subject);
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn object_query(
         project_id: String,
         collection_id: String,
         shared_object_id: String,
         object_id: String,
         include_subresources: bool,
-    ) -> String {
+    ) -> Result<String, tonic::Status> {
         let base_subject = NatsIOUtils::base_subject(
             vec![project_id, collection_id, shared_object_id, object_id],
             false,
-        );
+        )?;
         let query = NatsIOUtils::query(base_subject, include_subresources);
 
-        return query;
+        return Ok(query);
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn object_group_subject(
         project_id: String,
         collection_id: String,
         shared_object_group_id: String,
         object_group_id: String,
-    ) -> String {
+    ) -> Result<String, tonic::Status> {
         let subject = format!(
             "{}._",
             NatsIOUtils::base_subject(
@@ -120,18 +229,19 @@ impl NatsIOUtils {
                     object_group_id
                 ],
                 true
-            )
+            )?
         );
-        return subject;
+        return Ok(subject);
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn object_group_query(
         project_id: String,
         collection_id: String,
         shared_object_group_id: String,
         object_group_id: String,
         include_subresources: bool,
-    ) -> String {
+    ) -> Result<String, tonic::Status> {
         let base_subject = NatsIOUtils::base_subject(
             vec![
                 project_id,
@@ -140,25 +250,390 @@ impl NatsIOUtils {
                 object_group_id,
             ],
             true,
-        );
+        )?;
         let query = NatsIOUtils::query(base_subject, include_subresources);
 
-        return query;
+        return Ok(query);
+    }
+
+    // Creates all subjects an event has to be published to
+    // Objects and object groups are published once per related object group and
+    // objects additionally to their own object subject
+    // Events for all resources are published to every level of the relation, the resource id is used as object id
+    #[allow(clippy::result_large_err)]
+    pub fn event_subjects(
+        resource_type: ResourceType,
+        resource_id: String,
+        relation: &Relation,
+    ) -> Result<Vec<String>, tonic::Status> {
+        let subjects = match resource_type {
+            ResourceType::Unspecified => {
+                return Err(tonic::Status::invalid_argument(
+                    "resource type needs to be specified",
+                ))
+            }
+            ResourceType::Project => {
+                vec![NatsIOUtils::project_subject(resource_id)?]
+            }
+            ResourceType::Collection => {
+                vec![NatsIOUtils::collection_subject(
+                    relation.project.clone(),
+                    resource_id,
+                )?]
+            }
+            ResourceType::ObjectGroup => {
+                let mut subjects = Vec::new();
+
+                for object_group in &relation.object_groups {
+                    let subject = NatsIOUtils::object_group_subject(
+                        relation.project.clone(),
+                        relation.collection.clone(),
+                        object_group.shared_object_group_id.clone(),
+                        resource_id.clone(),
+                    )?;
+                    subjects.push(subject)
+                }
+
+                subjects
+            }
+            ResourceType::Object => {
+                let mut subjects = Vec::new();
+
+                for object_group in &relation.object_groups {
+                    let subject = NatsIOUtils::object_group_subject(
+                        relation.project.clone(),
+                        relation.collection.clone(),
+                        object_group.shared_object_group_id.clone(),
+                        resource_id.clone(),
+                    )?;
+                    subjects.push(subject)
+                }
+
+                let object_subject = NatsIOUtils::object_subject(
+                    relation.project.clone(),
+                    relation.collection.clone(),
+                    relation.shared_object.clone(),
+                    resource_id,
+                )?;
+
+                subjects.push(object_subject);
+
+                subjects
+            }
+            ResourceType::All => NatsIOUtils::relation_subjects(resource_id, relation)?,
+        };
+
+        if subjects.is_empty() {
+            return Err(tonic::Status::invalid_argument(
+                "relation does not contain any resource to publish the event to",
+            ));
+        }
+
+        return Ok(subjects);
+    }
+
+    // Creates the subjects for every level of a relation
+    // Levels with empty ids are skipped
+    #[allow(clippy::result_large_err)]
+    fn relation_subjects(
+        resource_id: String,
+        relation: &Relation,
+    ) -> Result<Vec<String>, tonic::Status> {
+        let mut subjects = Vec::new();
+        if relation.project.is_empty() {
+            return Ok(subjects);
+        }
+        subjects.push(NatsIOUtils::project_subject(relation.project.clone())?);
+
+        if relation.collection.is_empty() {
+            return Ok(subjects);
+        }
+        subjects.push(NatsIOUtils::collection_subject(
+            relation.project.clone(),
+            relation.collection.clone(),
+        )?);
+
+        for object_group in &relation.object_groups {
+            for object_group_id in &object_group.object_group_ids {
+                subjects.push(NatsIOUtils::object_group_subject(
+                    relation.project.clone(),
+                    relation.collection.clone(),
+                    object_group.shared_object_group_id.clone(),
+                    object_group_id.clone(),
+                )?);
+            }
+        }
+
+        if !relation.shared_object.is_empty() && !resource_id.is_empty() {
+            subjects.push(NatsIOUtils::object_subject(
+                relation.project.clone(),
+                relation.collection.clone(),
+                relation.shared_object.clone(),
+                resource_id,
+            )?);
+        }
+
+        return Ok(subjects);
+    }
+
+    // Creates the query subjects of a stream group for the given resource
+    // The ids of the parent resources are taken from the first hierarchy of the resource
+    // For objects the hierarchy object_id is the id of the shared object,
+    // for object groups the first entry of object_group_ids is the id of the shared object group
+    // A stream group for all resources queries every hierarchy at its deepest level including all subresources,
+    // see hierarchy_queries, queries covered by the query of another hierarchy are dropped
+    #[allow(clippy::result_large_err)]
+    pub fn stream_group_queries(
+        hierarchies: &[Hierarchy],
+        resource_type: ResourceType,
+        resource_id: String,
+        include_subresources: bool,
+    ) -> Result<Vec<String>, tonic::Status> {
+        if resource_type == ResourceType::All {
+            let mut queries = Vec::new();
+            for hierarchy in hierarchies {
+                for query in NatsIOUtils::hierarchy_queries(hierarchy)? {
+                    if !queries.contains(&query) {
+                        queries.push(query);
+                    }
+                }
+            }
+            let covered_queries: Vec<String> = queries
+                .iter()
+                .filter(|query| {
+                    queries.iter().any(|other| {
+                        other != *query && query.starts_with(other.trim_end_matches('>'))
+                    })
+                })
+                .cloned()
+                .collect();
+            queries.retain(|query| !covered_queries.contains(query));
+
+            if queries.is_empty() {
+                return Err(tonic::Status::invalid_argument(
+                    "no readable resources found for stream group",
+                ));
+            }
+
+            return Ok(queries);
+        }
+
+        let hierarchy = match hierarchies.first() {
+            Some(value) => value,
+            None => {
+                return Err(tonic::Status::internal(
+                    "no hierarchy found, cannot create query string",
+                ))
+            }
+        };
+
+        let query = match resource_type {
+            ResourceType::Unspecified => {
+                return Err(tonic::Status::invalid_argument(
+                    "resource type needs to be specified",
+                ))
+            }
+            ResourceType::Project => NatsIOUtils::project_query(resource_id, include_subresources)?,
+            ResourceType::Collection => NatsIOUtils::collection_query(
+                hierarchy.project_id.clone(),
+                resource_id,
+                include_subresources,
+            )?,
+            ResourceType::ObjectGroup => {
+                let shared_object_group_id = match hierarchy.object_group_ids.first() {
+                    Some(value) => value.clone(),
+                    None => {
+                        return Err(tonic::Status::internal(
+                            "no shared object group id found in hierarchy",
+                        ))
+                    }
+                };
+
+                NatsIOUtils::object_group_query(
+                    hierarchy.project_id.clone(),
+                    hierarchy.collection_id.clone(),
+                    shared_object_group_id,
+                    resource_id,
+                    include_subresources,
+                )?
+            }
+            ResourceType::Object => {
+                if hierarchy.object_id.is_empty() {
+                    return Err(tonic::Status::internal(
+                        "no shared object id found in hierarchy",
+                    ));
+                }
+
+                NatsIOUtils::object_query(
+                    hierarchy.project_id.clone(),
+                    hierarchy.collection_id.clone(),
+                    hierarchy.object_id.clone(),
+                    resource_id,
+                    include_subresources,
+                )?
+            }
+            ResourceType::All => unreachable!(),
+        };
+
+        return Ok(vec![query]);
+    }
+
+    // Creates the queries for the resource a hierarchy of a stream group for all resources points to
+    // The query is built at the deepest level with an id: shared object, shared object groups, collection or project
+    // Hierarchies without a project are skipped
+    #[allow(clippy::result_large_err)]
+    fn hierarchy_queries(hierarchy: &Hierarchy) -> Result<Vec<String>, tonic::Status> {
+        if hierarchy.project_id.is_empty() {
+            return Ok(Vec::new());
+        }
+        if hierarchy.collection_id.is_empty() {
+            return Ok(vec![NatsIOUtils::project_query(
+                hierarchy.project_id.clone(),
+                true,
+            )?]);
+        }
+
+        let parent_ids = vec![
+            hierarchy.project_id.clone(),
+            hierarchy.collection_id.clone(),
+        ];
+        if !hierarchy.object_id.is_empty() {
+            let base_subject = NatsIOUtils::base_subject(
+                [parent_ids, vec![hierarchy.object_id.clone()]].concat(),
+                false,
+            )?;
+            return Ok(vec![NatsIOUtils::query(base_subject, true)]);
+        }
+        if !hierarchy.object_group_ids.is_empty() {
+            let mut queries = Vec::new();
+            for shared_object_group_id in &hierarchy.object_group_ids {
+                let base_subject = NatsIOUtils::base_subject(
+                    [parent_ids.clone(), vec![shared_object_group_id.clone()]].concat(),
+                    true,
+                )?;
+                queries.push(NatsIOUtils::query(base_subject, true));
+            }
+            return Ok(queries);
+        }
+
+        return Ok(vec![NatsIOUtils::collection_query(
+            hierarchy.project_id.clone(),
+            hierarchy.collection_id.clone(),
+            true,
+        )?]);
+    }
+
+    // Parses a subject created by one of the subject functions back into the resource path it was published under
+    // Queries with wildcards or subresources are not subjects and are rejected
+    #[allow(clippy::result_large_err)]
+    pub fn parse_subject(subject: &str) -> Result<ResourcePath, tonic::Status> {
+        let invalid_subject =
+            || tonic::Status::invalid_argument(format!("invalid event subject {:?}", subject));
+
+        // The prefix has to be followed by the token separator, so that a longer first token does not pass
+        let path = match subject
+            .strip_prefix(STREAM_SUBJECT_COMMMON_PREFIX)
+            .and_then(|x| x.strip_prefix('.'))
+        {
+            Some(value) => value,
+            None => return Err(invalid_subject()),
+        };
+
+        // The path alternates between the id separator and an id and ends with the id separator: _.id._.id._
+        let tokens: Vec<&str> = path.split('.').collect();
+        if tokens.len() < 3 || tokens.len().is_multiple_of(2) {
+            return Err(invalid_subject());
+        }
+        let mut ids = Vec::new();
+        for (position, token) in tokens.into_iter().enumerate() {
+            if position.is_multiple_of(2) {
+                if token != "_" {
+                    return Err(invalid_subject());
+                }
+            } else {
+                if NatsIOUtils::validate_id(token).is_err() {
+                    return Err(invalid_subject());
+                }
+                ids.push(token.to_string());
+            }
+        }
+
+        let resource_path = match ids.as_slice() {
+            [project_id] => ResourcePath {
+                project_id: project_id.clone(),
+                collection_id: None,
+                shared_resource: None,
+            },
+            [project_id, collection_id] => ResourcePath {
+                project_id: project_id.clone(),
+                collection_id: Some(collection_id.clone()),
+                shared_resource: None,
+            },
+            [project_id, collection_id, kind, shared_id, id] => {
+                let shared_resource = match kind.as_str() {
+                    STREAM_SUBJECT_OBJECT_NAME => SharedResourcePath::Object {
+                        shared_object_id: shared_id.clone(),
+                        object_id: id.clone(),
+                    },
+                    STREAM_SUBJECT_OBJECT_GROUP_NAME => SharedResourcePath::ObjectGroup {
+                        shared_object_group_id: shared_id.clone(),
+                        object_group_id: id.clone(),
+                    },
+                    _ => return Err(invalid_subject()),
+                };
+
+                ResourcePath {
+                    project_id: project_id.clone(),
+                    collection_id: Some(collection_id.clone()),
+                    shared_resource: Some(shared_resource),
+                }
+            }
+            _ => return Err(invalid_subject()),
+        };
+
+        return Ok(resource_path);
+    }
+
+    // Checks if a subject matches a query subject
+    // Follows the Nats.io wildcard semantics: * matches exactly one token, > matches one or more trailing tokens
+    pub fn subject_matches(query: &str, subject: &str) -> bool {
+        let mut query_tokens = query.split('.');
+        let mut subject_tokens = subject.split('.');
+
+        loop {
+            match (query_tokens.next(), subject_tokens.next()) {
+                (Some(">"), Some(_)) => return true,
+                (Some("*"), Some(_)) => continue,
+                (Some(query_token), Some(subject_token)) if query_token == subject_token => {
+                    continue
+                }
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use aruna_rust_api::api::{
+        internal::v1::{ObjectGroupRelation, Relation},
+        storage::{models::v1::ResourceType, services::v1::Hierarchy},
+    };
+
     use crate::utils;
 
+    use super::{ResourcePath, SharedResourcePath};
+
     #[test]
     fn test_base_subject() {
         let project_base_subject =
-            utils::utils::NatsIOUtils::base_subject(vec!["project_id".to_string()], false);
+            utils::utils::NatsIOUtils::base_subject(vec!["project_id".to_string()], false).unwrap();
         let collection_base_subject = utils::utils::NatsIOUtils::base_subject(
             vec!["project_id".to_string(), "collection_id".to_string()],
             false,
-        );
+        )
+        .unwrap();
         let object_base_subject = utils::utils::NatsIOUtils::base_subject(
             vec![
                 "project_id".to_string(),
@@ -167,7 +642,8 @@ mod tests {
                 "object_id".to_string(),
             ],
             false,
-        );
+        )
+        .unwrap();
         let object_group_base_subject = utils::utils::NatsIOUtils::base_subject(
             vec![
                 "project_id".to_string(),
@@ -176,7 +652,8 @@ mod tests {
                 "object_group_id".to_string(),
             ],
             true,
-        );
+        )
+        .unwrap();
 
         assert_eq!(project_base_subject, "UPDATES.STORAGE._.project_id");
         assert_eq!(
@@ -196,47 +673,53 @@ mod tests {
     #[test]
     fn test_query_strings() {
         let project_query =
-            utils::utils::NatsIOUtils::project_query("project_id".to_string(), false);
+            utils::utils::NatsIOUtils::project_query("project_id".to_string(), false).unwrap();
         let project_query_sub =
-            utils::utils::NatsIOUtils::project_query("project_id".to_string(), true);
+            utils::utils::NatsIOUtils::project_query("project_id".to_string(), true).unwrap();
         let collection_query = utils::utils::NatsIOUtils::collection_query(
             "project_id".to_string(),
             "collection_id".to_string(),
             false,
-        );
+        )
+        .unwrap();
         let collection_query_sub = utils::utils::NatsIOUtils::collection_query(
             "project_id".to_string(),
             "collection_id".to_string(),
             true,
-        );
+        )
+        .unwrap();
         let object_query = utils::utils::NatsIOUtils::object_query(
             "project_id".to_string(),
             "collection_id".to_string(),
             "shared_object_id".to_string(),
             "object_id".to_string(),
             false,
-        );
+        )
+        .unwrap();
         let object_query_sub = utils::utils::NatsIOUtils::object_query(
             "project_id".to_string(),
             "collection_id".to_string(),
             "shared_object_id".to_string(),
             "object_id".to_string(),
             true,
-        );
+        )
+        .unwrap();
         let object_group_query = utils::utils::NatsIOUtils::object_group_query(
             "project_id".to_string(),
             "collection_id".to_string(),
             "shared_object_group_id".to_string(),
             "object_group_id".to_string(),
             false,
-        );
+        )
+        .unwrap();
         let object_group_query_sub = utils::utils::NatsIOUtils::object_group_query(
             "project_id".to_string(),
             "collection_id".to_string(),
             "shared_object_group_id".to_string(),
             "object_group_id".to_string(),
             true,
-        );
+        )
+        .unwrap();
 
         assert_eq!(project_query, "UPDATES.STORAGE._.project_id._");
         assert_eq!(project_query_sub, "UPDATES.STORAGE._.project_id.>");
@@ -265,23 +748,27 @@ mod tests {
 
     #[test]
     fn test_subject_strings() {
-        let project_subject = utils::utils::NatsIOUtils::project_subject("project_id".to_string());
+        let project_subject =
+            utils::utils::NatsIOUtils::project_subject("project_id".to_string()).unwrap();
         let collection_subject = utils::utils::NatsIOUtils::collection_subject(
             "project_id".to_string(),
             "collection_id".to_string(),
-        );
+        )
+        .unwrap();
         let object_subject = utils::utils::NatsIOUtils::object_subject(
             "project_id".to_string(),
             "collection_id".to_string(),
             "shared_object_id".to_string(),
             "object_id".to_string(),
-        );
+        )
+        .unwrap();
         let object_group_subject = utils::utils::NatsIOUtils::object_group_subject(
             "project_id".to_string(),
             "collection_id".to_string(),
             "shared_object_group_id".to_string(),
             "object_group_id".to_string(),
-        );
+        )
+        .unwrap();
 
         assert_eq!(project_subject, "UPDATES.STORAGE._.project_id._");
         assert_eq!(
@@ -297,4 +784,301 @@ mod tests {
             "UPDATES.STORAGE._.project_id._.collection_id._.OBJECTGROUP._.shared_object_group_id._.object_group_id._"
         );
     }
+
+    #[test]
+    fn test_subject_matches() {
+        let subject = utils::utils::NatsIOUtils::collection_subject(
+            "project_id".to_string(),
+            "collection_id".to_string(),
+        )
+        .unwrap();
+
+        let project_query =
+            utils::utils::NatsIOUtils::project_query("project_id".to_string(), false).unwrap();
+        let project_query_sub =
+            utils::utils::NatsIOUtils::project_query("project_id".to_string(), true).unwrap();
+        let collection_query = utils::utils::NatsIOUtils::collection_query(
+            "project_id".to_string(),
+            "collection_id".to_string(),
+            false,
+        )
+        .unwrap();
+        let other_project_query_sub =
+            utils::utils::NatsIOUtils::project_query("other_project_id".to_string(), true).unwrap();
+
+        assert!(!utils::utils::NatsIOUtils::subject_matches(
+            project_query.as_str(),
+            subject.as_str()
+        ));
+        assert!(utils::utils::NatsIOUtils::subject_matches(
+            project_query_sub.as_str(),
+            subject.as_str()
+        ));
+        assert!(utils::utils::NatsIOUtils::subject_matches(
+            collection_query.as_str(),
+            subject.as_str()
+        ));
+        assert!(!utils::utils::NatsIOUtils::subject_matches(
+            other_project_query_sub.as_str(),
+            subject.as_str()
+        ));
+        assert!(utils::utils::NatsIOUtils::subject_matches(
+            "UPDATES.STORAGE._.*._.collection_id._",
+            subject.as_str()
+        ));
+        assert!(!utils::utils::NatsIOUtils::subject_matches(
+            "UPDATES.STORAGE._.project_id.>",
+            "UPDATES.STORAGE._.project_id"
+        ));
+    }
+
+    #[test]
+    fn test_event_subjects() {
+        let relation = Relation {
+            project: "project_id".to_string(),
+            collection: "collection_id".to_string(),
+            shared_object: "shared_object_id".to_string(),
+            object_groups: vec![ObjectGroupRelation {
+                shared_object_group_id: "shared_object_group_id".to_string(),
+                object_group_ids: vec!["object_group_id".to_string()],
+            }],
+        };
+
+        let all_subjects = utils::utils::NatsIOUtils::event_subjects(
+            ResourceType::All,
+            "object_id".to_string(),
+            &relation,
+        )
+        .unwrap();
+
+        assert_eq!(
+            all_subjects,
+            vec![
+                "UPDATES.STORAGE._.project_id._",
+                "UPDATES.STORAGE._.project_id._.collection_id._",
+                "UPDATES.STORAGE._.project_id._.collection_id._.OBJECTGROUP._.shared_object_group_id._.object_group_id._",
+                "UPDATES.STORAGE._.project_id._.collection_id._.OBJECT._.shared_object_id._.object_id._",
+            ]
+        );
+
+        let unspecified = utils::utils::NatsIOUtils::event_subjects(
+            ResourceType::Unspecified,
+            "object_id".to_string(),
+            &relation,
+        );
+        assert_eq!(
+            unspecified.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+
+        let empty_relation = utils::utils::NatsIOUtils::event_subjects(
+            ResourceType::All,
+            "object_id".to_string(),
+            &Relation::default(),
+        );
+        assert_eq!(
+            empty_relation.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_stream_group_queries() {
+        let hierarchies = vec![
+            Hierarchy {
+                project_id: "project_id".to_string(),
+                ..Default::default()
+            },
+            Hierarchy {
+                project_id: "other_project_id".to_string(),
+                collection_id: "collection_id".to_string(),
+                ..Default::default()
+            },
+            Hierarchy {
+                project_id: "project_id".to_string(),
+                collection_id: "collection_id".to_string(),
+                ..Default::default()
+            },
+            Hierarchy {
+                project_id: "object_project_id".to_string(),
+                collection_id: "collection_id".to_string(),
+                object_id: "shared_object_id".to_string(),
+                ..Default::default()
+            },
+            Hierarchy {
+                project_id: "object_project_id".to_string(),
+                collection_id: "collection_id".to_string(),
+                object_group_ids: vec![
+                    "shared_object_group_id".to_string(),
+                    "other_shared_object_group_id".to_string(),
+                ],
+                ..Default::default()
+            },
+        ];
+
+        let all_queries = utils::utils::NatsIOUtils::stream_group_queries(
+            &hierarchies,
+            ResourceType::All,
+            "".to_string(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            all_queries,
+            vec![
+                "UPDATES.STORAGE._.project_id.>",
+                "UPDATES.STORAGE._.other_project_id._.collection_id.>",
+                "UPDATES.STORAGE._.object_project_id._.collection_id._.OBJECT._.shared_object_id.>",
+                "UPDATES.STORAGE._.object_project_id._.collection_id._.OBJECTGROUP._.shared_object_group_id.>",
+                "UPDATES.STORAGE._.object_project_id._.collection_id._.OBJECTGROUP._.other_shared_object_group_id.>",
+            ]
+        );
+
+        let unspecified = utils::utils::NatsIOUtils::stream_group_queries(
+            &hierarchies,
+            ResourceType::Unspecified,
+            "project_id".to_string(),
+            false,
+        );
+        assert_eq!(
+            unspecified.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_validate_id() {
+        for id in ["project_id", "3fa85f64-5717-4562-b3fc-2c963f66afa6", "a_b"] {
+            assert!(utils::utils::NatsIOUtils::validate_id(id).is_ok());
+        }
+        for id in [
+            "",
+            "_",
+            "project.id",
+            "project_*",
+            "project>",
+            "project id",
+            "id\n",
+        ] {
+            assert_eq!(
+                utils::utils::NatsIOUtils::validate_id(id)
+                    .unwrap_err()
+                    .code(),
+                tonic::Code::InvalidArgument
+            );
+        }
+
+        let wildcard_subject = utils::utils::NatsIOUtils::event_subjects(
+            ResourceType::Collection,
+            "*".to_string(),
+            &Relation {
+                project: "project_id".to_string(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            wildcard_subject.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+
+        let wildcard_query = utils::utils::NatsIOUtils::stream_group_queries(
+            &[Hierarchy {
+                project_id: "project_id".to_string(),
+                ..Default::default()
+            }],
+            ResourceType::Collection,
+            ">".to_string(),
+            true,
+        );
+        assert_eq!(
+            wildcard_query.unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_parse_subject() {
+        let project_path = utils::utils::NatsIOUtils::parse_subject(
+            &utils::utils::NatsIOUtils::project_subject("project_id".to_string()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            project_path,
+            ResourcePath {
+                project_id: "project_id".to_string(),
+                collection_id: None,
+                shared_resource: None,
+            }
+        );
+        assert_eq!(project_path.resource_type(), ResourceType::Project);
+        assert_eq!(project_path.resource_id(), "project_id");
+
+        let collection_path = utils::utils::NatsIOUtils::parse_subject(
+            "UPDATES.STORAGE._.project_id._.collection_id._",
+        )
+        .unwrap();
+        assert_eq!(collection_path.resource_type(), ResourceType::Collection);
+        assert_eq!(collection_path.resource_id(), "collection_id");
+
+        let object_path = utils::utils::NatsIOUtils::parse_subject(
+            &utils::utils::NatsIOUtils::object_subject(
+                "project_id".to_string(),
+                "collection_id".to_string(),
+                "shared_object_id".to_string(),
+                "object_id".to_string(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            object_path,
+            ResourcePath {
+                project_id: "project_id".to_string(),
+                collection_id: Some("collection_id".to_string()),
+                shared_resource: Some(SharedResourcePath::Object {
+                    shared_object_id: "shared_object_id".to_string(),
+                    object_id: "object_id".to_string(),
+                }),
+            }
+        );
+        assert_eq!(object_path.resource_type(), ResourceType::Object);
+        assert_eq!(object_path.resource_id(), "object_id");
+
+        let object_group_path = utils::utils::NatsIOUtils::parse_subject(
+            "UPDATES.STORAGE._.project_id._.collection_id._.OBJECTGROUP._.shared_object_group_id._.object_group_id._",
+        )
+        .unwrap();
+        assert_eq!(
+            object_group_path.shared_resource,
+            Some(SharedResourcePath::ObjectGroup {
+                shared_object_group_id: "shared_object_group_id".to_string(),
+                object_group_id: "object_group_id".to_string(),
+            })
+        );
+        assert_eq!(object_group_path.resource_type(), ResourceType::ObjectGroup);
+
+        for subject in [
+            "",
+            "UPDATES.STORAGE",
+            "UPDATES.STORAGE._",
+            "UPDATES.STORAGE._.project_id",
+            "UPDATES.STORAGE._.project_id.>",
+            "UPDATES.STORAGE._.*._",
+            "UPDATES.STORAGE._.project_id._._",
+            "UPDATES.STORAGE._.project_id.x.collection_id._",
+            "UPDATES.STORAGE._.project_id._.collection_id._.OBJECT._.shared_object_id._",
+            "UPDATES.STORAGE._.project_id._.collection_id._.OTHER._.shared_id._.id._",
+            "OTHER.STORAGE._.project_id._",
+            "XUPDATES.STORAGE._.project_id._",
+            "UPDATES.STORAGEX._.project_id._",
+            "UPDATES.STORAGE_._.project_id._",
+        ] {
+            assert_eq!(
+                utils::utils::NatsIOUtils::parse_subject(subject)
+                    .unwrap_err()
+                    .code(),
+                tonic::Code::InvalidArgument
+            );
+        }
+    }
 }